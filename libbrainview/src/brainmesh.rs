@@ -1,96 +1,444 @@
 //! Datastructures modeling brain meshes.
 
-use std::path::{Path};
+use std::path::{Path, PathBuf};
 
-use neuroformats::{BrainMesh, read_curv, read_surf, read_annot, read_label};
-use crate::{FsLabelDisplay, color_from_data, error::{Result}};
+use neuroformats::{BrainMesh, FsAnnot, FsLabel, read_annot, read_label};
+use crate::{FsLabelDisplay, Hemisphere, color_from_data, error::{BrainviewError, Result}};
+use crate::color::{Colormap, ColorMapping, ColorScale, ColorRange, OutOfRange, Threshold, curv_background_colors, threshold_overlay_colors};
 use crate::vertexcolor::VertexColor;
+use crate::layers::{ColorLayer, LayerStack};
+use crate::topology::{MeshTopology, TopologyCache};
+use crate::util::{read_file, rgb_to_opaque_rgba};
+use crate::subject::{Measure, Subject};
+use crate::vertexdata::{is_gifti_file, is_mgh_file, read_mgh_frames, read_vertex_data_array};
+use crate::gifti::{read_gifti_annot, GiftiArray};
+use crate::frames::{FrameData};
+use crate::import::{read_mesh};
+use crate::cifti::{read_cifti, CiftiImage};
+
+
+/// The color of vertices which are not covered by any color layer.
+const LAYER_BASE_COLOR : [u8; 4] = [255, 255, 255, 255];
 
 /// Models a vertex-colored BrainMesh, typically for a single hemisphere.
+///
+/// The constructors that read a surface file accept all formats supported by `read_mesh`, like GIFTI or PLY, based on the file extension.
 #[derive(Debug, Clone, PartialEq)]
 pub struct ColoredBrainMesh {
     pub mesh : BrainMesh,
     pub vertex_colors: Vec<u8>,
+    /// The color layers the `vertex_colors` are composited from. Empty if the mesh only has a single set of vertex colors.
+    pub layers : LayerStack,
+    /// The colormap and value range used to color the data, if the vertex colors represent per-vertex data. Used to draw a colorbar.
+    pub color_scale : Option<ColorScale>,
+    /// The cached topology of the mesh, see `topology()`. Clear it if you change the faces of the mesh.
+    pub topology_cache : TopologyCache,
+    /// The per-vertex data the mesh was colored from, if any. Used to show the value of a picked vertex.
+    pub vertex_data : Option<Vec<f32>>,
+    /// The brain atlas parcellation of the mesh, if any. Used to show the region of a picked vertex.
+    pub annot : Option<FsAnnot>,
+    /// The frames of per-vertex data, if the mesh shows one of several frames of data at a time, see `show_frame`.
+    pub frames : Option<FrameData>,
+    /// The hemisphere the mesh represents, if known. Used to lay out the hemispheres in a scene, see `HemisphereLayout`.
+    pub hemisphere : Option<Hemisphere>,
 }
 
 impl ColoredBrainMesh {
 
+    /// Construct a ColoredBrainMesh with the given vertex colors and nothing else: no layers, data, parcellation, frames or hemisphere.
+    ///
+    /// The public constructors build on this and set the fields they know about. The number of colors is not checked.
+    fn new(mesh: BrainMesh, vertex_colors: Vec<u8>) -> ColoredBrainMesh {
+        ColoredBrainMesh {
+            mesh,
+            vertex_colors,
+            layers: LayerStack::new(),
+            color_scale: None,
+            topology_cache: TopologyCache::new(),
+            vertex_data: None,
+            annot: None,
+            frames: None,
+            hemisphere: None,
+        }
+    }
+
+
+    /// Construct a ColoredBrainMesh colored from per-vertex data with the given mapping, keeping the data and the resulting color scale. The number of values is not checked.
+    fn from_data(mesh: BrainMesh, data: Vec<f32>, mapping: &ColorMapping) -> ColoredBrainMesh {
+        let vertex_colors = color_from_data(data.clone(), mapping);
+        ColoredBrainMesh { color_scale: mapping.resolve_scale(&[&data]), vertex_data: Some(data), ..ColoredBrainMesh::new(mesh, vertex_colors) }
+    }
+
+
     /// Construct a ColoredBrainMesh from a BrainMesh with n vertices and vertex colors given as n*4 u8 values representing RGBA color values for each vertex.
+    ///
+    /// Returns a `ColorCountMismatch` error if the number of color values is not n*4. Use `fit_colors_to_vertex_count` to explicitly accept colors of another length.
     pub fn from_brainmesh_and_colors(b_mesh: &BrainMesh, colors: Vec<u8>) -> Result<ColoredBrainMesh> {
-        let cb_mesh = ColoredBrainMesh {
-            mesh: b_mesh.clone(),
-            vertex_colors: colors
-        };
-        Ok(cb_mesh)
+        if colors.len() != b_mesh.num_vertices() * 4 {
+            return Err(BrainviewError::ColorCountMismatch(b_mesh.num_vertices(), colors.len()));
+        }
+        Ok(ColoredBrainMesh::new(b_mesh.clone(), colors))
     }
 
 
-    /// Construct a ColoredBrainMesh from a BrainMesh and per-vertex data.
-    pub fn from_brainmesh_and_data(b_mesh: &BrainMesh, data: Vec<f32>) -> Result<ColoredBrainMesh> {
-        let cb_mesh = ColoredBrainMesh {
-            mesh: b_mesh.clone(),
-            vertex_colors: color_from_data(data)
-        };
-        Ok(cb_mesh)
+    /// Construct a ColoredBrainMesh from a BrainMesh and per-vertex data. If no color mapping is given, the default mapping is used.
+    ///
+    /// Returns a `VertexCountMismatch` error if there is not exactly one value per vertex, which typically means the data belongs to another
+    /// subject or template. Use `fit_to_vertex_count` to explicitly accept data of another length, padded with NaN values or truncated.
+    pub fn from_brainmesh_and_data(b_mesh: &BrainMesh, data: Vec<f32>, mapping: Option<ColorMapping>) -> Result<ColoredBrainMesh> {
+        if data.len() != b_mesh.num_vertices() {
+            return Err(BrainviewError::VertexCountMismatch(b_mesh.num_vertices(), data.len()));
+        }
+        Ok(ColoredBrainMesh::from_data(b_mesh.clone(), data, &mapping.unwrap_or_default()))
     }
 
 
     /// Construct a ColoredBrainMesh from morphometry data files in a FreeSurfer directory. This typically represents a single hemisphere.
-    pub fn from_freesurfer_dir(base_path : &str, surface_file : &str, morph_file: &str) -> Result<ColoredBrainMesh> {
-        let base_path : &Path = &Path::new(base_path);
-        let surface_file : &Path = &Path::new(surface_file);
-        let morph_file : &Path = &Path::new(morph_file);
-        let surface_file = base_path.join(&Path::new("surf")).join(surface_file);
-        let morph_file = base_path.join(&Path::new("surf")).join(morph_file);
-        
-        let surface = read_surf::<&Path>(&surface_file).unwrap();
-        let curv = read_curv::<&Path>(&morph_file).unwrap();
-        let cb_mesh = ColoredBrainMesh {
-            mesh: surface.mesh.clone(),
-            vertex_colors: color_from_data(curv.data)
+    /// The morphometry file can be a curv file or an MGH/MGZ file, of which the first frame is used. If no color mapping is given, the default mapping is used.
+    pub fn from_freesurfer_dir(base_path : &str, surface_file : &str, morph_file: &str, mapping: Option<ColorMapping>) -> Result<ColoredBrainMesh> {
+        let (mesh, data) = read_freesurfer_surface_and_morph(base_path, surface_file, morph_file)?;
+        let cb_mesh = ColoredBrainMesh::from_data(mesh, data, &mapping.unwrap_or_default());
+        Ok(cb_mesh.with_hemisphere(Hemisphere::from_file_name(surface_file)))
+    }
+
+
+    /// Construct ColoredBrainMeshes from morphometry data for several hemispheres and subjects in FreeSurfer directories, using a common color scale.
+    ///
+    /// The value range is computed jointly over the data of all meshes, according to the range of the given color mapping, so the colors
+    /// are comparable between hemispheres and subjects. The `surface` and `measure` are given without hemisphere prefix, e.g., `white`
+    /// and `thickness` for the files `surf/lh.white` and `surf/lh.thickness`. Returns the meshes, ordered by subject and then by hemisphere
+    /// in the given order, and the value range `(vmin, vmax)` that was used.
+    pub fn from_freesurfer_dirs_shared_range(base_paths : &[&str], hemis : &[Hemisphere], surface : &str, measure : &str, mapping: Option<ColorMapping>) -> Result<(Vec<ColoredBrainMesh>, (f32, f32))> {
+        let mapping = mapping.unwrap_or_default();
+        let mut meshes_and_data : Vec<(BrainMesh, Vec<f32>, Hemisphere)> = Vec::with_capacity(base_paths.len() * hemis.len());
+        for base_path in base_paths.iter() {
+            for hemi in hemis.iter() {
+                let (mesh, data) = read_freesurfer_surface_and_morph(base_path, &hemi.file_name(surface), &hemi.file_name(measure))?;
+                meshes_and_data.push((mesh, data, *hemi));
+            }
+        }
+
+        data_meshes_shared_range(meshes_and_data, mapping)
+    }
+
+
+    /// Construct ColoredBrainMeshes from a morphometry measure of a subject for several hemispheres, using a common color scale.
+    ///
+    /// This is the version of `from_subject_measure` for several hemispheres: template space data is shown on the surfaces of the template subject,
+    /// see `Subject::resolve_measure`, and the value range is computed jointly over all hemispheres like in `from_freesurfer_dirs_shared_range`.
+    /// For GIFTI data files, `array` selects the data array, see `read_vertex_data_array`. If no array is given, the first one is used.
+    /// Returns the meshes in the given hemisphere order and the value range `(vmin, vmax)` that was used.
+    pub fn from_subject_measures_shared_range(subject : &Subject, hemis : &[Hemisphere], surface : &str, measure : &Measure, array: Option<GiftiArray>, mapping: Option<ColorMapping>) -> Result<(Vec<ColoredBrainMesh>, (f32, f32))> {
+        let array = array.unwrap_or_default();
+        let mut meshes_and_data : Vec<(BrainMesh, Vec<f32>, Hemisphere)> = Vec::with_capacity(hemis.len());
+        for hemi in hemis.iter() {
+            let (surface_file, data_file) = subject.resolve_measure(*hemi, surface, measure);
+            let (mesh, data) = read_surface_and_vertex_data(&surface_file, &data_file, &array)?;
+            meshes_and_data.push((mesh, data, *hemi));
+        }
+        data_meshes_shared_range(meshes_and_data, mapping.unwrap_or_default())
+    }
+
+
+    /// Construct a ColoredBrainMesh from a statistical overlay in a FreeSurfer directory, shown on top of a grayscale curvature background.
+    ///
+    /// Vertices with overlay values that do not pass the threshold are not colored, so the sulcal/gyral pattern of the background
+    /// file (like `lh.curv` or `lh.sulc`) shows through. Both the overlay and the background file are read from the `surf` sub directory.
+    /// If no color mapping is given, the FreeSurfer heat colormap with a range symmetric around zero is used.
+    pub fn from_freesurfer_threshold_overlay(base_path : &str, surface_file : &str, overlay_file: &str, background_file: &str, threshold: Threshold, mapping: Option<ColorMapping>) -> Result<ColoredBrainMesh> {
+        let (mesh, data) = read_freesurfer_surface_and_morph(base_path, surface_file, overlay_file)?;
+        let (_, background) = read_freesurfer_surface_and_morph(base_path, surface_file, background_file)?;
+        let mapping = match mapping {
+            Some(mapping) => mapping,
+            None => default_overlay_mapping()?,
         };
-        Ok(cb_mesh)
+        let cb_mesh = threshold_overlay_mesh(mesh, data, &background, &threshold, &mapping);
+        Ok(cb_mesh.with_hemisphere(Hemisphere::from_file_name(surface_file)))
+    }
+
+
+    /// Construct ColoredBrainMeshes from a statistical overlay for several hemispheres in a FreeSurfer directory, using a common color scale.
+    ///
+    /// This is the version of `from_freesurfer_threshold_overlay` for several hemispheres: the value range is computed jointly over the overlays
+    /// of all hemispheres, so a color means the same value in all of them. The `surface`, `overlay` and `background` are given without hemisphere
+    /// prefix, e.g., `white`, `tstat` and `curv`. Returns the meshes in the given hemisphere order and the value range `(vmin, vmax)` that was used.
+    pub fn from_freesurfer_threshold_overlays_shared_range(base_path : &str, hemis : &[Hemisphere], surface : &str, overlay : &str, background : &str, threshold: Threshold, mapping: Option<ColorMapping>) -> Result<(Vec<ColoredBrainMesh>, (f32, f32))> {
+        let mut overlays : Vec<(BrainMesh, Vec<f32>, Vec<f32>, Hemisphere)> = Vec::with_capacity(hemis.len());
+        for hemi in hemis.iter() {
+            let (mesh, data) = read_freesurfer_surface_and_morph(base_path, &hemi.file_name(surface), &hemi.file_name(overlay))?;
+            let (_, background) = read_freesurfer_surface_and_morph(base_path, &hemi.file_name(surface), &hemi.file_name(background))?;
+            overlays.push((mesh, data, background, *hemi));
+        }
+        threshold_overlay_meshes_shared_range(overlays, &threshold, mapping)
+    }
+
+
+    /// Construct a ColoredBrainMesh from a surface file and per-vertex data in an MGH or MGZ file, like a group-level statistical map or the output of `mris_preproc`.
+    ///
+    /// Both files are given by their full path. For files with several frames, `frame` selects the frame to show, starting at 0.
+    /// If no color mapping is given, the default mapping is used.
+    pub fn from_mgh_file(surface_file : &str, mgh_file : &str, frame : usize, mapping: Option<ColorMapping>) -> Result<ColoredBrainMesh> {
+        if !is_mgh_file(mgh_file) {
+            return Err(BrainviewError::UnsupportedFileFormat(PathBuf::from(mgh_file)));
+        }
+        let (mesh, data) = read_surface_and_vertex_data(Path::new(surface_file), Path::new(mgh_file), &GiftiArray::Index(frame))?;
+        Ok(ColoredBrainMesh::from_brainmesh_and_data(&mesh, data, mapping)?.with_hemisphere(Hemisphere::from_file_name(surface_file)))
+    }
+
+
+    /// Construct a ColoredBrainMesh from a morphometry measure of a subject, which may be in native space or in a template space like fsaverage.
+    ///
+    /// Template space data is shown on the given surface of the template subject, which must be in the same subjects directory, see `Subject::resolve_measure`.
+    /// If no color mapping is given, the default mapping is used.
+    pub fn from_subject_measure(subject : &Subject, hemi : Hemisphere, surface : &str, measure : &Measure, mapping: Option<ColorMapping>) -> Result<ColoredBrainMesh> {
+        let (surface_file, data_file) = subject.resolve_measure(hemi, surface, measure);
+        let (mesh, data) = read_surface_and_vertex_data(&surface_file, &data_file, &GiftiArray::default())?;
+        Ok(ColoredBrainMesh::from_brainmesh_and_data(&mesh, data, mapping)?.with_hemisphere(Some(hemi)))
+    }
+
+
+    /// Construct a ColoredBrainMesh from a BrainMesh and per-vertex data with several frames, like a time series, showing the first frame.
+    ///
+    /// The value range of the color mapping is resolved over all frames and then fixed for all frames, see `FrameData`. If no color mapping is
+    /// given, the default mapping is used. Returns a `VertexCountMismatch` error if a frame does not contain one value per vertex.
+    pub fn from_brainmesh_and_frames(b_mesh: &BrainMesh, frames: Vec<Vec<f32>>, mapping: Option<ColorMapping>) -> Result<ColoredBrainMesh> {
+        if let Some(frame) = frames.iter().find(|frame| frame.len() != b_mesh.num_vertices()) {
+            return Err(BrainviewError::VertexCountMismatch(b_mesh.num_vertices(), frame.len()));
+        }
+        let frame_data = FrameData::new(frames, mapping)?;
+        let vertex_colors = frame_data.frame_colors(0).unwrap_or_default();
+        let vertex_data = frame_data.frame(0).map(|frame| frame.to_vec());
+        let color_scale = frame_data.color_scale();
+        Ok(ColoredBrainMesh { vertex_data, color_scale, frames: Some(frame_data), ..ColoredBrainMesh::new(b_mesh.clone(), vertex_colors) })
+    }
+
+
+    /// Construct a ColoredBrainMesh from a surface file and all frames of per-vertex data in an MGH or MGZ file, showing the first frame.
+    ///
+    /// Both files are given by their full path. See `from_brainmesh_and_frames` for the color mapping and `show_frame` to switch frames.
+    pub fn from_mgh_frames(surface_file : &str, mgh_file : &str, mapping: Option<ColorMapping>) -> Result<ColoredBrainMesh> {
+        let surface = read_mesh(Path::new(surface_file))?;
+        let frames = read_mgh_frames(mgh_file)?;
+        Ok(ColoredBrainMesh::from_brainmesh_and_frames(&surface, frames, mapping)?.with_hemisphere(Hemisphere::from_file_name(surface_file)))
+    }
+
+
+    /// Construct ColoredBrainMeshes from per-vertex data with several frames in MGH or MGZ files, for several hemispheres and subjects, using a common color scale.
+    ///
+    /// This is the multi-frame version of `from_freesurfer_dirs_shared_range`: the value range is computed jointly over all frames of all meshes. The `measure`
+    /// is given without hemisphere prefix, including the file extension, e.g., `bold.mgz` for the files `surf/lh.bold.mgz` and `surf/rh.bold.mgz`.
+    /// Returns the meshes, ordered by subject and then by hemisphere in the given order, and the value range `(vmin, vmax)` that was used.
+    pub fn from_freesurfer_frames_shared_range(base_paths : &[&str], hemis : &[Hemisphere], surface : &str, measure : &str, mapping: Option<ColorMapping>) -> Result<(Vec<ColoredBrainMesh>, (f32, f32))> {
+        let mapping = mapping.unwrap_or_default();
+        let mut meshes_and_frames : Vec<(BrainMesh, Vec<Vec<f32>>, Hemisphere)> = Vec::with_capacity(base_paths.len() * hemis.len());
+        for base_path in base_paths.iter() {
+            let subject = Subject::new(base_path);
+            for hemi in hemis.iter() {
+                let surface = read_mesh(subject.surface_path(*hemi, surface))?;
+                meshes_and_frames.push((surface, read_mgh_frames(subject.surf_file(&hemi.file_name(measure)))?, *hemi));
+            }
+        }
+
+        let all_data : Vec<&[f32]> = meshes_and_frames.iter().flat_map(|(_, frames, _)| frames.iter().map(|frame| frame.as_slice())).collect();
+        let (vmin, vmax) = mapping.range.resolve(&all_data).ok_or(BrainviewError::NoValidData)?;
+        let shared_mapping = ColorMapping { range: ColorRange::Fixed(vmin, vmax), ..mapping };
+
+        let cb_meshes = meshes_and_frames.into_iter()
+            .map(|(mesh, frames, hemi)| Ok(ColoredBrainMesh::from_brainmesh_and_frames(&mesh, frames, Some(shared_mapping))?.with_hemisphere(Some(hemi))))
+            .collect::<Result<Vec<ColoredBrainMesh>>>()?;
+        Ok((cb_meshes, (vmin, vmax)))
     }
 
 
     /// Construct a ColoredBrainMesh from brain atlas surface parcellation files in a FreeSurfer directory. This typically represents a single hemisphere.
-    pub fn from_freesurfer_annot(base_path : &str, surface_file : &str, annot_file: &str) -> Result<ColoredBrainMesh> {
-        let base_path : &Path = &Path::new(base_path);
-        let surface_file : &Path = &Path::new(surface_file);
-        let annot_file : &Path = &Path::new(annot_file);
-        let surface_file = base_path.join(&Path::new("surf")).join(surface_file);
-        let annot_file = base_path.join(&Path::new("label")).join(annot_file);
-        
-        let surface = read_surf::<&Path>(&surface_file).unwrap();
-        let annot = read_annot::<&Path>(&annot_file).unwrap();
-        let cb_mesh = ColoredBrainMesh {
-            mesh: surface.mesh.clone(),
-            vertex_colors: annot.vertex_colors(true, 0),
-        };
-        Ok(cb_mesh)
+    ///
+    /// The parcellation can be an annot file or a GIFTI label file ending with `.gii`, of which the data array selected by `array` is used, see
+    /// `read_gifti_annot`. If no array is given, the first one is used.
+    pub fn from_freesurfer_annot(base_path : &str, surface_file : &str, annot_file: &str, array: Option<GiftiArray>) -> Result<ColoredBrainMesh> {
+        let subject = Subject::new(base_path);
+        let surface = read_mesh(subject.surf_file(surface_file))?;
+        let annot = read_annot_file(&subject.label_file(annot_file), &array.unwrap_or_default())?;
+        Ok(ColoredBrainMesh::from_brainmesh_and_annot(&surface, annot)?.with_hemisphere(Hemisphere::from_file_name(surface_file)))
+    }
+
+
+    /// Construct a ColoredBrainMesh from a BrainMesh and a brain atlas parcellation for it, coloring each vertex with the color of its region.
+    ///
+    /// Returns a `VertexCountMismatch` error if the parcellation does not contain one label per vertex.
+    pub fn from_brainmesh_and_annot(b_mesh: &BrainMesh, annot: FsAnnot) -> Result<ColoredBrainMesh> {
+        if annot.vertex_labels.len() != b_mesh.num_vertices() {
+            return Err(BrainviewError::VertexCountMismatch(b_mesh.num_vertices(), annot.vertex_labels.len()));
+        }
+        let vertex_colors = rgb_to_opaque_rgba(&annot.vertex_colors(false, 0));
+        Ok(ColoredBrainMesh { annot: Some(annot), ..ColoredBrainMesh::new(b_mesh.clone(), vertex_colors) })
+    }
+
+
+    /// Construct a ColoredBrainMesh for a hemisphere from a map of a CIFTI-2 file, like a `.dscalar.nii` or `.dlabel.nii` file, and the matching cortex surface.
+    ///
+    /// Vertices which are not part of the cortex model of the file, typically the medial wall, get no data. For data files, the value range
+    /// of the color mapping is resolved over the cortex models of both hemispheres, so the colors are comparable between them. If no color
+    /// mapping is given, the default mapping is used. For label files, the vertices are colored by region, like for `from_brainmesh_and_annot`.
+    ///
+    /// Returns an `InvalidMap` error if there is no such map, a `MissingBrainStructure` error if the file contains no cortex model for the
+    /// hemisphere, and a `VertexCountMismatch` error if the surface of the model has another number of vertices than the mesh.
+    pub fn from_cifti(cifti: &CiftiImage, b_mesh: &BrainMesh, hemi: Hemisphere, map: usize, mapping: Option<ColorMapping>) -> Result<ColoredBrainMesh> {
+        let data = cifti.hemisphere_data(map, hemi, f32::NAN)?;
+        if data.len() != b_mesh.num_vertices() {
+            return Err(BrainviewError::VertexCountMismatch(b_mesh.num_vertices(), data.len()));
+        }
+        if cifti.is_label() {
+            return Ok(ColoredBrainMesh::from_brainmesh_and_annot(b_mesh, cifti.hemisphere_annot(map, hemi)?)?.with_hemisphere(Some(hemi)));
+        }
+        let mapping = mapping.unwrap_or_default();
+        let map_data = &cifti.maps[map].data;
+        let cortex_data : Vec<&[f32]> = Hemisphere::both().iter()
+            .filter_map(|h| cifti.cortex_model(*h))
+            .filter_map(|model| map_data.get(model.index_offset..model.index_offset + model.index_count))
+            .collect();
+        let (vmin, vmax) = mapping.range.resolve(&cortex_data).ok_or(BrainviewError::NoValidData)?;
+        Ok(ColoredBrainMesh::from_brainmesh_and_data(b_mesh, data, Some(ColorMapping { range: ColorRange::Fixed(vmin, vmax), ..mapping }))?.with_hemisphere(Some(hemi)))
+    }
+
+
+    /// Construct the ColoredBrainMeshes of both hemispheres from a map of a CIFTI-2 file and the matching left and right cortex surface files, see `from_cifti`.
+    ///
+    /// All files are given by their full path, and the surfaces can be in any format supported by `read_mesh`, like the `.surf.gii` files of the HCP.
+    /// Returns the meshes of the left and the right hemisphere.
+    pub fn from_cifti_file(cifti_file : &str, lh_surface_file : &str, rh_surface_file : &str, map : usize, mapping: Option<ColorMapping>) -> Result<(ColoredBrainMesh, ColoredBrainMesh)> {
+        let cifti = read_cifti(cifti_file)?;
+        let lh_mesh = ColoredBrainMesh::from_cifti(&cifti, &read_mesh(Path::new(lh_surface_file))?, Hemisphere::Lh, map, mapping)?;
+        let rh_mesh = ColoredBrainMesh::from_cifti(&cifti, &read_mesh(Path::new(rh_surface_file))?, Hemisphere::Rh, map, mapping)?;
+        Ok((lh_mesh, rh_mesh))
     }
 
 
     /// Construct a ColoredBrainMesh from a label file in a FreeSurfer directory. This typically represents a single hemisphere.
     pub fn from_freesurfer_label(base_path : &str, surface_file : &str, label_file: &str) -> Result<ColoredBrainMesh> {
-        let base_path : &Path = &Path::new(base_path);
-        let surface_file : &Path = &Path::new(surface_file);
-        let label_file : &Path = &Path::new(label_file);
-        let surface_file = base_path.join(&Path::new("surf")).join(surface_file);
-        let label_file = base_path.join(&Path::new("label")).join(label_file);
-        
-        let surface = read_surf::<&Path>(&surface_file).unwrap();
-        let label = read_label::<&Path>(&label_file).unwrap();
+        let subject = Subject::new(base_path);
+        let surface = read_mesh(subject.surf_file(surface_file))?;
+        let label = read_file(&subject.label_file(label_file), |p| read_label(p))?;
+        check_label_vertices(&label, surface.num_vertices())?;
 
         let red : [u8; 4] = [255, 0, 0, 255];
         let white : [u8; 4] = [255, 255, 255, 255];
-        let label_display = FsLabelDisplay { label : label, num_surface_verts: surface.mesh.num_vertices(), color_bin_inside: red, color_bin_outside: white };
+        let label_display = FsLabelDisplay { label, num_surface_verts: surface.num_vertices(), color_bin_inside: red, color_bin_outside: white, mapping: ColorMapping::default() };
 
-        let cb_mesh = ColoredBrainMesh {
-            mesh: surface.mesh.clone(),
-            vertex_colors: label_display.vertex_color_rgba(), // via VertexColor trait.
-        };
-        Ok(cb_mesh)
+        let cb_mesh = ColoredBrainMesh::new(surface, label_display.vertex_color_rgba()); // via VertexColor trait.
+        Ok(cb_mesh.with_hemisphere(Hemisphere::from_file_name(surface_file)))
+    }
+
+
+    /// Construct a ColoredBrainMesh from a BrainMesh and a stack of color layers. The vertex colors are composited from the layers,
+    /// vertices which are not covered by any layer are white.
+    pub fn from_brainmesh_and_layers(b_mesh: &BrainMesh, layers: LayerStack) -> Result<ColoredBrainMesh> {
+        let vertex_colors = layers.composite(b_mesh.num_vertices(), LAYER_BASE_COLOR);
+        Ok(ColoredBrainMesh { layers, ..ColoredBrainMesh::new(b_mesh.clone(), vertex_colors) })
+    }
+
+
+    /// Set the hemisphere of this mesh, see `hemisphere`. The constructors which read FreeSurfer files set it from the file names.
+    pub fn with_hemisphere(self, hemisphere : Option<Hemisphere>) -> Self {
+        ColoredBrainMesh { hemisphere, ..self }
+    }
+
+
+    /// Add a color layer on top of the layers of this mesh and update the vertex colors.
+    ///
+    /// If the mesh has no layers yet, its current vertex colors become the bottom layer, named `base`.
+    pub fn push_layer(&mut self, layer: ColorLayer) {
+        if self.layers.is_empty() {
+            self.layers.push(ColorLayer::new("base", self.vertex_colors.clone()));
+        }
+        self.layers.push(layer);
+        self.composite_layers();
+    }
+
+
+    /// Toggle the visibility of the layer at the given index and update the vertex colors. Returns the new visibility, or `None` if there is no such layer.
+    pub fn toggle_layer(&mut self, layer_idx: usize) -> Option<bool> {
+        let visible = self.layers.toggle(layer_idx)?;
+        self.composite_layers();
+        Some(visible)
+    }
+
+
+    /// Add a FreeSurfer label file from the `label` sub directory of a FreeSurfer directory as a layer on top of this mesh, see `push_layer`.
+    ///
+    /// The vertices in the label get the given color, the other vertices are not covered.
+    pub fn push_freesurfer_label_layer(&mut self, base_path : &str, label_file: &str, color: [u8; 4], opacity: f32) -> Result<()> {
+        let label = read_file(&Subject::new(base_path).label_file(label_file), |p| read_label(p))?;
+        check_label_vertices(&label, self.mesh.num_vertices())?;
+        let layer = ColorLayer::from_label(label_file, &label, self.mesh.num_vertices(), color).with_opacity(opacity);
+        self.push_layer(layer);
+        Ok(())
+    }
+
+
+    /// Add the region outlines of a FreeSurfer annot file from the `label` sub directory of a FreeSurfer directory as a layer on top of this mesh, see `push_layer`.
+    ///
+    /// The `width` of the outlines is given in edge hops, see `region_boundary_vertices`. The outlines get the given color, or the colors of the regions if no color is given.
+    /// If the mesh has no parcellation yet, the annot also becomes its parcellation, see `vertex_region_name`. For GIFTI label files, `array` selects the
+    /// data array like in `from_freesurfer_annot`.
+    pub fn push_freesurfer_annot_outline_layer(&mut self, base_path : &str, annot_file: &str, width: usize, color: Option<[u8; 4]>, array: Option<GiftiArray>) -> Result<()> {
+        let annot = read_annot_file(&Subject::new(base_path).label_file(annot_file), &array.unwrap_or_default())?;
+        if annot.vertex_labels.len() != self.mesh.num_vertices() {
+            return Err(BrainviewError::VertexCountMismatch(self.mesh.num_vertices(), annot.vertex_labels.len()));
+        }
+        let layer = ColorLayer::from_annot_outline(annot_file, self.topology(), &annot, width, color);
+        self.push_layer(layer);
+        if self.annot.is_none() {
+            self.annot = Some(annot);
+        }
+        Ok(())
+    }
+
+
+    /// Show the frame with the given index of the frames of the mesh, by updating its vertex colors and data.
+    ///
+    /// If the mesh has color layers, the frame replaces the colors of the bottom layer. Returns an `InvalidFrame` error if there is no such frame.
+    pub fn show_frame(&mut self, frame_idx: usize) -> Result<()> {
+        let frame_data = self.frames.as_mut().ok_or(BrainviewError::InvalidFrame(frame_idx, 0))?;
+        let colors = frame_data.frame_colors(frame_idx).ok_or(BrainviewError::InvalidFrame(frame_idx, frame_data.num_frames()))?;
+        frame_data.current = frame_idx;
+        self.vertex_data = frame_data.frame(frame_idx).map(|frame| frame.to_vec());
+        match self.layers.layers.first_mut() {
+            Some(base_layer) => {
+                base_layer.colors = colors;
+                self.composite_layers();
+            },
+            None => self.vertex_colors = colors,
+        }
+        Ok(())
+    }
+
+
+    /// Get the data value of the vertex with the given index, if the mesh has per-vertex data.
+    pub fn vertex_data_value(&self, vertex_idx: usize) -> Option<f32> {
+        self.vertex_data.as_ref()?.get(vertex_idx).copied()
+    }
+
+
+    /// Get the name of the atlas region the vertex with the given index belongs to, if the mesh has a parcellation and the vertex is assigned to a region.
+    pub fn vertex_region_name(&self, vertex_idx: usize) -> Option<&str> {
+        let annot = self.annot.as_ref()?;
+        let label = annot.vertex_labels.get(vertex_idx)?;
+        let region_idx = annot.colortable.label.iter().position(|l| l == label)?;
+        annot.colortable.name.get(region_idx).map(|name| name.as_str())
+    }
+
+
+    /// Get the topology of the mesh, like the vertex neighbors and edges. It is computed on first use and cached.
+    pub fn topology(&self) -> &MeshTopology {
+        self.topology_cache.get_or_compute(&self.mesh)
+    }
+
+
+    /// Composite the visible layers into the vertex colors. Does nothing if the mesh has no layers.
+    fn composite_layers(&mut self) {
+        if !self.layers.is_empty() {
+            self.vertex_colors = self.layers.composite(self.mesh.num_vertices(), LAYER_BASE_COLOR);
+        }
     }
 
 
@@ -101,3 +449,193 @@ impl ColoredBrainMesh {
     
 }
 
+
+/// Read a parcellation from an annot file or, if the file name ends with `.gii`, from the selected data array of a GIFTI label file.
+fn read_annot_file(annot_file : &Path, array : &GiftiArray) -> Result<FsAnnot> {
+    if is_gifti_file(annot_file) {
+        read_gifti_annot(annot_file, array)
+    } else {
+        read_file(annot_file, |p| read_annot(p))
+    }
+}
+
+
+/// The default color mapping for statistical overlays: the FreeSurfer heat colormap with a range symmetric around zero.
+fn default_overlay_mapping() -> Result<ColorMapping> {
+    Ok(ColorMapping { colormap: Colormap::from_name("heat")?, range: ColorRange::Symmetric, out_of_range: OutOfRange::Clip })
+}
+
+
+/// Construct a ColoredBrainMesh showing a thresholded statistical overlay on top of a grayscale background, see `from_freesurfer_threshold_overlay`.
+fn threshold_overlay_mesh(mesh : BrainMesh, data : Vec<f32>, background : &[f32], threshold : &Threshold, mapping : &ColorMapping) -> ColoredBrainMesh {
+    let vertex_colors = threshold_overlay_colors(&data, &curv_background_colors(background), threshold, mapping);
+    ColoredBrainMesh { color_scale: mapping.resolve_scale(&[&data]), vertex_data: Some(data), ..ColoredBrainMesh::new(mesh, vertex_colors) }
+}
+
+
+/// Construct ColoredBrainMeshes showing thresholded statistical overlays, given as meshes with their overlay and background data, with a value
+/// range computed jointly over all overlays. See `from_freesurfer_threshold_overlays_shared_range`.
+fn threshold_overlay_meshes_shared_range(overlays : Vec<(BrainMesh, Vec<f32>, Vec<f32>, Hemisphere)>, threshold : &Threshold, mapping : Option<ColorMapping>) -> Result<(Vec<ColoredBrainMesh>, (f32, f32))> {
+    let mapping = match mapping {
+        Some(mapping) => mapping,
+        None => default_overlay_mapping()?,
+    };
+    let all_data : Vec<&[f32]> = overlays.iter().map(|(_, data, _, _)| data.as_slice()).collect();
+    let (vmin, vmax) = mapping.range.resolve(&all_data).ok_or(BrainviewError::NoValidData)?;
+    let shared_mapping = ColorMapping { range: ColorRange::Fixed(vmin, vmax), ..mapping };
+
+    let cb_meshes = overlays.into_iter()
+        .map(|(mesh, data, background, hemi)| threshold_overlay_mesh(mesh, data, &background, threshold, &shared_mapping).with_hemisphere(Some(hemi)))
+        .collect();
+    Ok((cb_meshes, (vmin, vmax)))
+}
+
+
+/// Read a surface and morphometry data for it from the `surf` sub directory of a FreeSurfer directory.
+///
+/// The morphometry file can be a curv file or an MGH/MGZ file, see `read_vertex_data`. For MGH/MGZ files, the first frame is used.
+fn read_freesurfer_surface_and_morph(base_path : &str, surface_file : &str, morph_file: &str) -> Result<(BrainMesh, Vec<f32>)> {
+    let subject = Subject::new(base_path);
+    read_surface_and_vertex_data(&subject.surf_file(surface_file), &subject.surf_file(morph_file), &GiftiArray::default())
+}
+
+
+/// Color the per-vertex data of several meshes with a value range that is resolved jointly over all of them. Returns the meshes and the value range.
+fn data_meshes_shared_range(meshes_and_data : Vec<(BrainMesh, Vec<f32>, Hemisphere)>, mapping : ColorMapping) -> Result<(Vec<ColoredBrainMesh>, (f32, f32))> {
+    let all_data : Vec<&[f32]> = meshes_and_data.iter().map(|(_, data, _)| data.as_slice()).collect();
+    let (vmin, vmax) = mapping.range.resolve(&all_data).ok_or(BrainviewError::NoValidData)?;
+    let shared_mapping = ColorMapping { range: ColorRange::Fixed(vmin, vmax), ..mapping };
+
+    let cb_meshes = meshes_and_data.into_iter()
+        .map(|(mesh, data, hemi)| ColoredBrainMesh::from_data(mesh, data, &shared_mapping).with_hemisphere(Some(hemi)))
+        .collect();
+    Ok((cb_meshes, (vmin, vmax)))
+}
+
+
+/// Read a surface and the selected data array or frame of per-vertex data for it, see `read_vertex_data_array`, and make sure the data matches the surface.
+fn read_surface_and_vertex_data(surface_file : &Path, data_file : &Path, array : &GiftiArray) -> Result<(BrainMesh, Vec<f32>)> {
+    let surface = read_mesh(surface_file)?;
+    let data = read_vertex_data_array(data_file, array)?;
+    if data.len() != surface.num_vertices() {
+        return Err(BrainviewError::VertexCountMismatch(surface.num_vertices(), data.len()));
+    }
+    Ok((surface, data))
+}
+
+
+/// Make sure all vertices of a label exist in a surface with the given number of vertices.
+fn check_label_vertices(label : &FsLabel, num_vertices : usize) -> Result<()> {
+    if label.vertex_index.iter().any(|idx| *idx < 0 || *idx as usize >= num_vertices) {
+        let max_idx = label.vertex_index.iter().max().copied().unwrap_or(0).max(0) as usize;
+        return Err(BrainviewError::VertexCountMismatch(num_vertices, max_idx + 1));
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::colors_as_u8_4;
+
+    /// A mesh with a single triangle.
+    fn triangle_mesh() -> BrainMesh {
+        BrainMesh { vertices: vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0], faces: vec![0, 1, 2] }
+    }
+
+    #[test]
+    fn threshold_overlays_of_both_hemispheres_share_the_color_scale() {
+        let overlays = vec![
+            (triangle_mesh(), vec![1.0, 2.0, 3.0], vec![0.0; 3], Hemisphere::Lh),
+            (triangle_mesh(), vec![-8.0, 4.0, 5.0], vec![0.0; 3], Hemisphere::Rh),
+        ];
+        let threshold = Threshold::Signed { pos: 2.0, neg: 2.0 };
+        let (cb_meshes, (vmin, vmax)) = threshold_overlay_meshes_shared_range(overlays, &threshold, None).unwrap();
+
+        assert_eq!((-8.0, 8.0), (vmin, vmax));
+        assert_eq!(2, cb_meshes.len());
+        assert_eq!(cb_meshes[0].color_scale, cb_meshes[1].color_scale);
+        assert_eq!(Some(8.0), cb_meshes[0].color_scale.map(|scale| scale.vmax));
+        assert_eq!(Some(Hemisphere::Lh), cb_meshes[0].hemisphere);
+        assert_eq!(Some(Hemisphere::Rh), cb_meshes[1].hemisphere);
+    }
+
+    #[test]
+    fn data_meshes_of_both_hemispheres_share_the_joint_range() {
+        let meshes_and_data = vec![
+            (triangle_mesh(), vec![1.0, 2.0, 3.0], Hemisphere::Lh),
+            (triangle_mesh(), vec![0.5, f32::NAN, 6.0], Hemisphere::Rh),
+        ];
+        let (cb_meshes, (vmin, vmax)) = data_meshes_shared_range(meshes_and_data, ColorMapping::default()).unwrap();
+
+        assert_eq!((0.5, 6.0), (vmin, vmax));
+        assert_eq!(2, cb_meshes.len());
+        for cb_mesh in cb_meshes.iter() {
+            assert_eq!(Some((0.5, 6.0)), cb_mesh.color_scale.map(|scale| (scale.vmin, scale.vmax)));
+        }
+        assert_eq!(Some(vec![1.0, 2.0, 3.0]), cb_meshes[0].vertex_data);
+        assert_eq!(Some(Hemisphere::Lh), cb_meshes[0].hemisphere);
+        assert_eq!(Some(Hemisphere::Rh), cb_meshes[1].hemisphere);
+        // The maximum of the left hemisphere is not the end of the colormap, as the right one has larger values.
+        let cmap_end = colors_as_u8_4(vec![Colormap::default().eval(1.0)], 255);
+        assert_ne!(cmap_end, cb_meshes[0].vertex_colors[8..12].to_vec());
+        assert_eq!(cmap_end, cb_meshes[1].vertex_colors[8..12].to_vec());
+    }
+
+    #[test]
+    fn data_meshes_without_valid_values_have_no_shared_range() {
+        let meshes_and_data = vec![(triangle_mesh(), vec![f32::NAN; 3], Hemisphere::Lh)];
+        assert!(matches!(data_meshes_shared_range(meshes_and_data, ColorMapping::default()), Err(BrainviewError::NoValidData)));
+    }
+
+    #[test]
+    fn a_mesh_from_an_empty_layer_stack_has_one_color_per_vertex() {
+        let cb_mesh = ColoredBrainMesh::from_brainmesh_and_layers(&triangle_mesh(), LayerStack::new()).unwrap();
+        assert_eq!([255u8; 4].repeat(3), cb_mesh.vertex_colors);
+    }
+
+    #[test]
+    fn a_mesh_from_layers_has_the_composited_colors() {
+        let mut layers = LayerStack::new();
+        layers.push(ColorLayer::new("red", [255, 0, 0, 255].repeat(3)));
+        layers.push(ColorLayer::new("blue", vec![0, 0, 255, 255]));
+        let cb_mesh = ColoredBrainMesh::from_brainmesh_and_layers(&triangle_mesh(), layers).unwrap();
+        assert_eq!(vec![0, 0, 255, 255, 255, 0, 0, 255, 255, 0, 0, 255], cb_mesh.vertex_colors);
+    }
+
+    #[test]
+    fn colors_which_do_not_match_the_vertex_count_are_rejected() {
+        let result = ColoredBrainMesh::from_brainmesh_and_colors(&triangle_mesh(), vec![255; 8]);
+        assert!(matches!(result, Err(BrainviewError::ColorCountMismatch(3, 8))));
+        assert!(ColoredBrainMesh::from_brainmesh_and_colors(&triangle_mesh(), vec![255; 12]).is_ok());
+    }
+
+    #[test]
+    fn data_which_does_not_match_the_vertex_count_is_rejected() {
+        let result = ColoredBrainMesh::from_brainmesh_and_data(&triangle_mesh(), vec![1.0, 2.0], None);
+        assert!(matches!(result, Err(BrainviewError::VertexCountMismatch(3, 2))));
+        let result = ColoredBrainMesh::from_brainmesh_and_frames(&triangle_mesh(), vec![vec![1.0; 3], vec![1.0; 4]], None);
+        assert!(matches!(result, Err(BrainviewError::VertexCountMismatch(3, 4))));
+    }
+
+    #[test]
+    fn a_mesh_from_data_keeps_the_data_and_the_color_scale() {
+        let cb_mesh = ColoredBrainMesh::from_brainmesh_and_data(&triangle_mesh(), vec![1.0, 2.0, 3.0], None).unwrap();
+        assert_eq!(12, cb_mesh.vertex_colors.len());
+        assert_eq!(Some(vec![1.0, 2.0, 3.0]), cb_mesh.vertex_data);
+        assert_eq!(Some((1.0, 3.0)), cb_mesh.color_scale.map(|scale| (scale.vmin, scale.vmax)));
+        assert!(cb_mesh.layers.is_empty());
+        assert_eq!(None, cb_mesh.hemisphere);
+    }
+
+    #[test]
+    fn showing_a_frame_requires_frames() {
+        let mut cb_mesh = ColoredBrainMesh::from_brainmesh_and_data(&triangle_mesh(), vec![1.0, 2.0, 3.0], None).unwrap();
+        assert!(matches!(cb_mesh.show_frame(0), Err(BrainviewError::InvalidFrame(0, 0))));
+        let mut cb_mesh = ColoredBrainMesh::from_brainmesh_and_frames(&triangle_mesh(), vec![vec![1.0; 3], vec![2.0; 3]], None).unwrap();
+        assert!(cb_mesh.show_frame(1).is_ok());
+        assert_eq!(Some(vec![2.0; 3]), cb_mesh.vertex_data);
+        assert!(matches!(cb_mesh.show_frame(2), Err(BrainviewError::InvalidFrame(2, 2))));
+    }
+}
@@ -0,0 +1,442 @@
+//! Reading CIFTI-2 files, the file format for data on grayordinates used by the Human Connectome Project (HCP).
+//!
+//! A CIFTI-2 file is a NIfTI-2 file with an XML header extension. The data is a matrix: its rows are the maps, e.g., the measures of a
+//! `.dscalar.nii` file or the parcellations of a `.dlabel.nii` file, and its columns are the brainordinates. The brainordinates are
+//! grouped into brain models, like the vertices of the left cortex or the voxels of a subcortical structure. Surface models only contain
+//! a subset of the vertices of their surface, typically without the medial wall, so their data has to be mapped back onto the surface.
+
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+use xml::reader::{EventReader, XmlEvent};
+
+use neuroformats::{FsAnnot, FsAnnotColortable};
+
+use crate::error::{BrainviewError, Result};
+use crate::gifti::{attribute, parse_label, GiftiLabel};
+use crate::hemisphere::{Hemisphere};
+
+
+/// The brain structure of the left cortex surface model.
+pub const STRUCTURE_CORTEX_LEFT : &str = "CIFTI_STRUCTURE_CORTEX_LEFT";
+
+/// The brain structure of the right cortex surface model.
+pub const STRUCTURE_CORTEX_RIGHT : &str = "CIFTI_STRUCTURE_CORTEX_RIGHT";
+
+/// The size of the NIfTI-2 header in bytes.
+const NIFTI2_HEADER_SIZE : usize = 540;
+
+/// The code of the NIfTI header extension containing the CIFTI XML.
+const NIFTI_ECODE_CIFTI : i32 = 32;
+
+
+/// A brain model of a CIFTI file: a brain structure and the brainordinates it covers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CiftiBrainModel {
+    /// The brain structure, like `CIFTI_STRUCTURE_CORTEX_LEFT`.
+    pub structure : String,
+    /// Whether the model contains surface vertices, as opposed to voxels.
+    pub is_surface : bool,
+    /// The index of the first brainordinate of the model.
+    pub index_offset : usize,
+    /// The number of brainordinates of the model.
+    pub index_count : usize,
+    /// For surface models, the number of vertices of the surface.
+    pub surface_num_vertices : Option<usize>,
+    /// For surface models, the surface vertex of each brainordinate of the model.
+    pub vertex_indices : Vec<usize>,
+}
+
+
+/// A map of a CIFTI file, i.e., a row of its data matrix.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CiftiMap {
+    /// The name of the map. Empty if the file does not name its maps, like for a time series.
+    pub name : String,
+    /// The regions of a parcellation, for `.dlabel.nii` files. Empty otherwise.
+    pub label_table : Vec<GiftiLabel>,
+    /// The values, one per brainordinate.
+    pub data : Vec<f32>,
+}
+
+
+/// The contents of a CIFTI-2 file with brain models along the columns, like a `.dscalar.nii`, `.dlabel.nii` or `.dtseries.nii` file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CiftiImage {
+    pub maps : Vec<CiftiMap>,
+    pub brain_models : Vec<CiftiBrainModel>,
+}
+
+
+impl CiftiImage {
+
+    /// Whether the maps are parcellations, i.e., the file is a `.dlabel.nii` file.
+    pub fn is_label(&self) -> bool {
+        self.maps.iter().any(|map| !map.label_table.is_empty())
+    }
+
+
+    /// The index of the first map with the given name, if any.
+    pub fn map_index(&self, name : &str) -> Option<usize> {
+        self.maps.iter().position(|map| map.name == name)
+    }
+
+
+    /// The surface model of the cortex of a hemisphere, if any.
+    pub fn cortex_model(&self, hemi : Hemisphere) -> Option<&CiftiBrainModel> {
+        let structure = match hemi {
+            Hemisphere::Lh => STRUCTURE_CORTEX_LEFT,
+            Hemisphere::Rh => STRUCTURE_CORTEX_RIGHT,
+        };
+        self.brain_models.iter().find(|model| model.is_surface && model.structure == structure)
+    }
+
+
+    /// Map the data of a map onto all vertices of the cortex surface of a hemisphere. Vertices which are not part of the model, like
+    /// the medial wall, get the given fill value.
+    ///
+    /// Returns an `InvalidMap` error if there is no such map, and a `MissingBrainStructure` error if the file has no model for the hemisphere.
+    pub fn hemisphere_data(&self, map : usize, hemi : Hemisphere, fill_value : f32) -> Result<Vec<f32>> {
+        let cifti_map = self.maps.get(map).ok_or(BrainviewError::InvalidMap(map, self.maps.len()))?;
+        let model = self.cortex_model(hemi).ok_or_else(|| BrainviewError::MissingBrainStructure(hemi.to_string()))?;
+        let num_vertices = model.surface_num_vertices.unwrap_or(0);
+        let mut data = vec![fill_value; num_vertices];
+        for (bidx, vidx) in model.vertex_indices.iter().enumerate() {
+            if let (Some(target), Some(value)) = (data.get_mut(*vidx), cifti_map.data.get(model.index_offset + bidx)) {
+                *target = *value;
+            }
+        }
+        Ok(data)
+    }
+
+
+    /// Map the data of a map onto all vertices of both cortex surfaces, filling vertices which are not part of the models with NaN. The left hemisphere comes first.
+    pub fn cortex_data(&self, map : usize) -> Result<(Vec<f32>, Vec<f32>)> {
+        Ok((self.hemisphere_data(map, Hemisphere::Lh, f32::NAN)?, self.hemisphere_data(map, Hemisphere::Rh, f32::NAN)?))
+    }
+
+
+    /// The parcellation of a map of a `.dlabel.nii` file on the cortex surface of a hemisphere, as a FreeSurfer parcellation, see `read_gifti_annot`.
+    ///
+    /// Vertices which are not part of the model, like the medial wall, get the label -1, which is not in the label table.
+    pub fn hemisphere_annot(&self, map : usize, hemi : Hemisphere) -> Result<FsAnnot> {
+        let data = self.hemisphere_data(map, hemi, -1.0)?;
+        let labels = &self.maps[map].label_table;
+        let channel = |c : usize| -> Vec<i32> { labels.iter().map(|l| (l.color[c].clamp(0.0, 1.0) * 255.0).round() as i32).collect() };
+        let colortable = FsAnnotColortable {
+            id : labels.iter().map(|l| l.key).collect(),
+            name : labels.iter().map(|l| l.name.clone()).collect(),
+            r : channel(0),
+            g : channel(1),
+            b : channel(2),
+            a : vec![0; labels.len()],
+            label : labels.iter().map(|l| l.key).collect(),
+        };
+        Ok(FsAnnot { vertex_indices: (0..data.len() as i32).collect(), vertex_labels: data.iter().map(|v| *v as i32).collect(), colortable })
+    }
+}
+
+
+/// Read a CIFTI-2 file with maps along the rows and brain models along the columns, like a `.dscalar.nii`, `.dlabel.nii` or `.dtseries.nii` file.
+///
+/// Returns a `MissingFile` error if the file does not exist, and an `InvalidFile` error if it is not such a CIFTI-2 file.
+pub fn read_cifti<P: AsRef<Path>>(path : P) -> Result<CiftiImage> {
+    let path = path.as_ref();
+    if !path.is_file() {
+        return Err(BrainviewError::MissingFile(path.to_path_buf()));
+    }
+    let mut bytes = fs::read(path)?;
+    if bytes.starts_with(&[0x1f, 0x8b]) {
+        let mut decompressed = Vec::new();
+        GzDecoder::new(bytes.as_slice()).read_to_end(&mut decompressed)?;
+        bytes = decompressed;
+    }
+    parse_cifti(&bytes).map_err(|msg| BrainviewError::InvalidFile(path.to_path_buf(), msg))
+}
+
+
+/// Read the bytes of a value from a position in the file content, in the given byte order.
+fn read_bytes<const N : usize>(bytes : &[u8], pos : usize, is_big_endian : bool) -> std::result::Result<[u8; N], String> {
+    let mut b = [0u8; N];
+    b.copy_from_slice(bytes.get(pos..pos + N).ok_or_else(|| String::from("Unexpected end of file."))?);
+    if is_big_endian {
+        b.reverse();
+    }
+    Ok(b)
+}
+
+
+/// Parse the content of a CIFTI-2 file: the NIfTI-2 header, the CIFTI extension and the data matrix.
+fn parse_cifti(bytes : &[u8]) -> std::result::Result<CiftiImage, String> {
+    let header_size = i32::from_le_bytes(read_bytes(bytes, 0, false)?);
+    let is_big_endian = match header_size {
+        540 => false,
+        _ if header_size.swap_bytes() == 540 => true,
+        _ => return Err(String::from("Not a NIfTI-2 file, CIFTI-2 requires NIfTI-2.")),
+    };
+    let i16_at = |pos : usize| read_bytes::<2>(bytes, pos, is_big_endian).map(i16::from_le_bytes);
+    let i32_at = |pos : usize| read_bytes::<4>(bytes, pos, is_big_endian).map(i32::from_le_bytes);
+    let i64_at = |pos : usize| read_bytes::<8>(bytes, pos, is_big_endian).map(i64::from_le_bytes);
+    let f64_at = |pos : usize| read_bytes::<8>(bytes, pos, is_big_endian).map(f64::from_le_bytes);
+
+    let datatype = i16_at(12)?;
+    let dims = (0..8).map(|d| i64_at(16 + d * 8)).collect::<std::result::Result<Vec<i64>, String>>()?;
+    let vox_offset = i64_at(168)? as usize;
+    let (scl_slope, scl_inter) = (f64_at(176)?, f64_at(184)?);
+    // The matrix dimensions are stored in dim[5] (rows) and dim[6] (columns), dim[1] to dim[4] are 1.
+    if dims[0] < 6 || dims[1..5].iter().any(|d| *d != 1) {
+        return Err(String::from("Invalid dimensions for a CIFTI-2 file."));
+    }
+    let (num_rows, num_cols) = (dims[5] as usize, dims[6] as usize);
+
+    // Find the CIFTI extension, which follows the header and the 4 extension flag bytes.
+    let mut xml : Option<&[u8]> = None;
+    let mut pos = NIFTI2_HEADER_SIZE + 4;
+    if bytes.get(NIFTI2_HEADER_SIZE) != Some(&0) {
+        while pos + 8 <= vox_offset {
+            let (esize, ecode) = (i32_at(pos)? as usize, i32_at(pos + 4)?);
+            if esize < 8 {
+                break;
+            }
+            if ecode == NIFTI_ECODE_CIFTI {
+                xml = Some(bytes.get(pos + 8..pos + esize).ok_or_else(|| String::from("Unexpected end of file."))?);
+                break;
+            }
+            pos += esize;
+        }
+    }
+    let xml = xml.ok_or_else(|| String::from("No CIFTI extension, this is a plain NIfTI-2 file."))?;
+    // The extension is padded to a multiple of 16 bytes with zeros, which are not part of the XML.
+    let xml = &xml[..xml.iter().rposition(|b| *b != 0).map_or(0, |last| last + 1)];
+    let (mut maps, brain_models) = parse_cifti_xml(xml)?;
+
+    let value_size = match datatype {
+        2 | 256 => 1,
+        4 | 512 => 2,
+        8 | 16 | 768 => 4,
+        64 | 1024 | 1280 => 8,
+        _ => return Err(format!("Unsupported NIfTI data type {}.", datatype)),
+    };
+    let values = (0..num_rows * num_cols).map(|idx| {
+        let pos = vox_offset + idx * value_size;
+        let value = match datatype {
+            2 => read_bytes::<1>(bytes, pos, is_big_endian)?[0] as f64,
+            256 => read_bytes::<1>(bytes, pos, is_big_endian)?[0] as i8 as f64,
+            4 => i16::from_le_bytes(read_bytes(bytes, pos, is_big_endian)?) as f64,
+            512 => u16::from_le_bytes(read_bytes(bytes, pos, is_big_endian)?) as f64,
+            8 => i32::from_le_bytes(read_bytes(bytes, pos, is_big_endian)?) as f64,
+            16 => f32::from_le_bytes(read_bytes(bytes, pos, is_big_endian)?) as f64,
+            768 => u32::from_le_bytes(read_bytes(bytes, pos, is_big_endian)?) as f64,
+            64 => f64::from_le_bytes(read_bytes(bytes, pos, is_big_endian)?),
+            1024 => i64::from_le_bytes(read_bytes(bytes, pos, is_big_endian)?) as f64,
+            _ => u64::from_le_bytes(read_bytes(bytes, pos, is_big_endian)?) as f64,
+        };
+        let scaled = if scl_slope != 0.0 && (scl_slope != 1.0 || scl_inter != 0.0) { value * scl_slope + scl_inter } else { value };
+        Ok(scaled as f32)
+    }).collect::<std::result::Result<Vec<f32>, String>>()?;
+
+    // Files without named maps, like time series, get one unnamed map per row.
+    maps.resize(num_rows, CiftiMap { name: String::new(), label_table: Vec::new(), data: Vec::new() });
+    for (row, map) in maps.iter_mut().enumerate() {
+        // NIfTI data is in column major order, so the values of a row are `num_rows` apart.
+        map.data = (0..num_cols).map(|col| values[row + col * num_rows]).collect();
+    }
+    if let Some(model) = brain_models.iter().find(|model| model.index_offset + model.index_count > num_cols) {
+        return Err(format!("The brain model {} exceeds the {} columns of the data.", model.structure, num_cols));
+    }
+    Ok(CiftiImage { maps, brain_models })
+}
+
+
+/// Parse the CIFTI XML into the named maps of the rows and the brain models of the columns.
+fn parse_cifti_xml(xml : &[u8]) -> std::result::Result<(Vec<CiftiMap>, Vec<CiftiBrainModel>), String> {
+    let reader = EventReader::new(xml);
+    let mut maps : Vec<CiftiMap> = Vec::new();
+    let mut brain_models : Vec<CiftiBrainModel> = Vec::new();
+    let mut label_attributes : Vec<(String, String)> = Vec::new();
+    let mut text = String::new();
+
+    for event in reader {
+        match event.map_err(|err| err.to_string())? {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                let attrs : Vec<(String, String)> = attributes.into_iter().map(|attr| (attr.name.local_name, attr.value)).collect();
+                match name.local_name.as_str() {
+                    "MatrixIndicesMap" => {
+                        let applies_to = attribute(&attrs, "AppliesToMatrixDimension").unwrap_or("");
+                        let is_brain_models = attribute(&attrs, "IndicesMapToDataType") == Some("CIFTI_INDEX_TYPE_BRAIN_MODELS");
+                        if (applies_to == "1" && !is_brain_models) || (applies_to == "0" && is_brain_models) {
+                            return Err(String::from("Unsupported CIFTI file, only files with brain models along the columns are supported."));
+                        }
+                    },
+                    "NamedMap" => maps.push(CiftiMap { name: String::new(), label_table: Vec::new(), data: Vec::new() }),
+                    "Label" => label_attributes = attrs,
+                    "BrainModel" => {
+                        let parse_attribute = |name : &str| -> std::result::Result<Option<usize>, String> {
+                            attribute(&attrs, name).map(|v| v.trim().parse::<usize>().map_err(|_| format!("Invalid {} '{}'.", name, v))).transpose()
+                        };
+                        brain_models.push(CiftiBrainModel {
+                            structure : attribute(&attrs, "BrainStructure").unwrap_or("").to_string(),
+                            is_surface : attribute(&attrs, "ModelType") == Some("CIFTI_MODEL_TYPE_SURFACE"),
+                            index_offset : parse_attribute("IndexOffset")?.unwrap_or(0),
+                            index_count : parse_attribute("IndexCount")?.unwrap_or(0),
+                            surface_num_vertices : parse_attribute("SurfaceNumberOfVertices")?,
+                            vertex_indices : Vec::new(),
+                        });
+                    },
+                    _ => {},
+                }
+                text.clear();
+            },
+            XmlEvent::Characters(chars) | XmlEvent::CData(chars) => text.push_str(&chars),
+            XmlEvent::EndElement { name } => {
+                match name.local_name.as_str() {
+                    "MapName" => if let Some(map) = maps.last_mut() { map.name = text.trim().to_string() },
+                    "Label" => if let Some(map) = maps.last_mut() { map.label_table.push(parse_label(&label_attributes, &text)?) },
+                    "VertexIndices" => if let Some(model) = brain_models.last_mut() {
+                        model.vertex_indices = text.split_whitespace().map(|v| v.parse::<usize>().map_err(|_| format!("Invalid vertex index '{}'.", v))).collect::<std::result::Result<Vec<usize>, String>>()?;
+                    },
+                    _ => {},
+                }
+                text.clear();
+            },
+            _ => {},
+        }
+    }
+    Ok((maps, brain_models))
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The brain models of the test files: a left cortex with 5 vertices and a right cortex with 4 vertices, each without one medial wall
+    /// vertex, followed by 2 voxels of the left thalamus. That makes 4 + 3 + 2 = 9 columns.
+    const BRAIN_MODELS : &str = "<BrainModel IndexOffset=\"0\" IndexCount=\"4\" ModelType=\"CIFTI_MODEL_TYPE_SURFACE\" BrainStructure=\"CIFTI_STRUCTURE_CORTEX_LEFT\" SurfaceNumberOfVertices=\"5\"><VertexIndices>0 1 3 4</VertexIndices></BrainModel>\
+        <BrainModel IndexOffset=\"4\" IndexCount=\"3\" ModelType=\"CIFTI_MODEL_TYPE_SURFACE\" BrainStructure=\"CIFTI_STRUCTURE_CORTEX_RIGHT\" SurfaceNumberOfVertices=\"4\"><VertexIndices>1 2 3</VertexIndices></BrainModel>\
+        <BrainModel IndexOffset=\"7\" IndexCount=\"2\" ModelType=\"CIFTI_MODEL_TYPE_VOXELS\" BrainStructure=\"CIFTI_STRUCTURE_THALAMUS_LEFT\"><VoxelIndicesIJK>1 2 3\n4 5 6</VoxelIndicesIJK></BrainModel>";
+
+    const NUM_COLUMNS : usize = 9;
+
+    /// The content of a CIFTI-2 file with the given maps along the rows, brain models along the columns and float32 data.
+    fn cifti_bytes(row_map : &str, index_type : &str, brain_models : &str, rows : &[Vec<f32>], is_big_endian : bool) -> Vec<u8> {
+        let xml = format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?><CIFTI Version=\"2\"><Matrix>\
+            <MatrixIndicesMap AppliesToMatrixDimension=\"0\" IndicesMapToDataType=\"{}\">{}</MatrixIndicesMap>\
+            <MatrixIndicesMap AppliesToMatrixDimension=\"1\" IndicesMapToDataType=\"CIFTI_INDEX_TYPE_BRAIN_MODELS\">{}</MatrixIndicesMap>\
+            </Matrix></CIFTI>", index_type, row_map, brain_models);
+        let esize = (8 + xml.len()).div_ceil(16) * 16;
+        let vox_offset = NIFTI2_HEADER_SIZE + 4 + esize;
+        let (num_rows, num_cols) = (rows.len(), rows[0].len());
+        let to_bytes = |b : &[u8]| -> Vec<u8> { if is_big_endian { b.iter().rev().copied().collect() } else { b.to_vec() } };
+
+        let mut bytes = vec![0u8; NIFTI2_HEADER_SIZE];
+        bytes[0..4].copy_from_slice(&to_bytes(&540i32.to_le_bytes()));
+        bytes[4..12].copy_from_slice(b"n+2\0\r\n\x1a\n");
+        bytes[12..14].copy_from_slice(&to_bytes(&16i16.to_le_bytes()));
+        bytes[14..16].copy_from_slice(&to_bytes(&32i16.to_le_bytes()));
+        for (d, dim) in [6i64, 1, 1, 1, 1, num_rows as i64, num_cols as i64, 1].iter().enumerate() {
+            bytes[16 + d * 8..24 + d * 8].copy_from_slice(&to_bytes(&dim.to_le_bytes()));
+        }
+        bytes[168..176].copy_from_slice(&to_bytes(&(vox_offset as i64).to_le_bytes()));
+        bytes[176..184].copy_from_slice(&to_bytes(&1.0f64.to_le_bytes()));
+        bytes.extend_from_slice(&[1, 0, 0, 0]);
+        bytes.extend_from_slice(&to_bytes(&(esize as i32).to_le_bytes()));
+        bytes.extend_from_slice(&to_bytes(&NIFTI_ECODE_CIFTI.to_le_bytes()));
+        bytes.extend_from_slice(xml.as_bytes());
+        bytes.resize(vox_offset, 0);
+        for col in 0..num_cols {
+            for row in rows.iter() {
+                bytes.extend_from_slice(&to_bytes(&row[col].to_le_bytes()));
+            }
+        }
+        bytes
+    }
+
+    /// A scalar file with the maps `thickness`, with the values 1 to 9, and `double`, with twice these values.
+    fn dscalar_bytes(is_big_endian : bool) -> Vec<u8> {
+        let thickness : Vec<f32> = (1..=NUM_COLUMNS).map(|v| v as f32).collect();
+        let double : Vec<f32> = thickness.iter().map(|v| v * 2.0).collect();
+        let maps = "<NamedMap><MapName>thickness</MapName></NamedMap><NamedMap><MapName>double</MapName></NamedMap>";
+        cifti_bytes(maps, "CIFTI_INDEX_TYPE_SCALARS", BRAIN_MODELS, &[thickness, double], is_big_endian)
+    }
+
+    #[test]
+    fn the_maps_and_cortex_models_are_read() {
+        let cifti = parse_cifti(&dscalar_bytes(false)).unwrap();
+        assert!(!cifti.is_label());
+        assert_eq!(2, cifti.maps.len());
+        assert_eq!(Some(1), cifti.map_index("double"));
+        assert_eq!(None, cifti.map_index("area"));
+        assert_eq!(3, cifti.brain_models.len());
+
+        let lh = cifti.cortex_model(Hemisphere::Lh).unwrap();
+        assert_eq!((STRUCTURE_CORTEX_LEFT, 0, 4, Some(5)), (lh.structure.as_str(), lh.index_offset, lh.index_count, lh.surface_num_vertices));
+        assert_eq!(vec![0, 1, 3, 4], lh.vertex_indices);
+        let rh = cifti.cortex_model(Hemisphere::Rh).unwrap();
+        assert_eq!((STRUCTURE_CORTEX_RIGHT, 4, 3, Some(4)), (rh.structure.as_str(), rh.index_offset, rh.index_count, rh.surface_num_vertices));
+        assert!(!cifti.brain_models[2].is_surface);
+    }
+
+    #[test]
+    fn the_medial_wall_gets_nan_values() {
+        let cifti = parse_cifti(&dscalar_bytes(false)).unwrap();
+        let (lh, rh) = cifti.cortex_data(1).unwrap();
+        assert_eq!(5, lh.len());
+        assert_eq!(vec![2.0, 4.0, 6.0, 8.0], lh.iter().filter(|v| !v.is_nan()).copied().collect::<Vec<f32>>());
+        assert!(lh[2].is_nan());
+        assert_eq!(4, rh.len());
+        assert!(rh[0].is_nan());
+        assert_eq!(vec![10.0, 12.0, 14.0], rh[1..].to_vec());
+        assert_eq!(vec![0.0, 5.0, 6.0, 7.0], cifti.hemisphere_data(0, Hemisphere::Rh, 0.0).unwrap());
+    }
+
+    #[test]
+    fn big_endian_files_are_read() {
+        assert_eq!(parse_cifti(&dscalar_bytes(false)).unwrap(), parse_cifti(&dscalar_bytes(true)).unwrap());
+    }
+
+    #[test]
+    fn the_medial_wall_of_a_parcellation_gets_label_minus_one() {
+        let maps = "<NamedMap><MapName>regions</MapName><LabelTable>\
+            <Label Key=\"0\" Red=\"0\" Green=\"0\" Blue=\"0\" Alpha=\"0\">???</Label><Label Key=\"1\" Red=\"1\" Green=\"0\" Blue=\"0\" Alpha=\"1\">motor</Label>\
+            <Label Key=\"2\" Red=\"0\" Green=\"0.5\" Blue=\"1\" Alpha=\"1\">visual</Label></LabelTable></NamedMap>";
+        let labels = vec![1.0, 1.0, 2.0, 0.0, 2.0, 2.0, 1.0, 0.0, 0.0];
+        let cifti = parse_cifti(&cifti_bytes(maps, "CIFTI_INDEX_TYPE_LABELS", BRAIN_MODELS, &[labels], false)).unwrap();
+        assert!(cifti.is_label());
+
+        let annot = cifti.hemisphere_annot(0, Hemisphere::Lh).unwrap();
+        assert_eq!(vec![1, 1, -1, 2, 0], annot.vertex_labels);
+        assert_eq!(vec![0, 1, 2, 3, 4], annot.vertex_indices);
+        assert_eq!(vec!["???", "motor", "visual"], annot.colortable.name);
+        assert_eq!(vec![0, 0, 128], annot.colortable.g);
+        assert_eq!(vec![-1, 2, 2, 1], cifti.hemisphere_annot(0, Hemisphere::Rh).unwrap().vertex_labels);
+    }
+
+    #[test]
+    fn missing_maps_and_brain_structures_are_errors() {
+        let cifti = parse_cifti(&dscalar_bytes(false)).unwrap();
+        assert!(matches!(cifti.hemisphere_data(2, Hemisphere::Lh, 0.0), Err(BrainviewError::InvalidMap(2, 2))));
+        assert!(matches!(cifti.cortex_data(5), Err(BrainviewError::InvalidMap(5, 2))));
+
+        let left_only = "<BrainModel IndexOffset=\"0\" IndexCount=\"2\" ModelType=\"CIFTI_MODEL_TYPE_SURFACE\" BrainStructure=\"CIFTI_STRUCTURE_CORTEX_LEFT\" SurfaceNumberOfVertices=\"3\"><VertexIndices>0 2</VertexIndices></BrainModel>";
+        let cifti = parse_cifti(&cifti_bytes("<NamedMap><MapName>a</MapName></NamedMap>", "CIFTI_INDEX_TYPE_SCALARS", left_only, &[vec![1.0, 2.0]], false)).unwrap();
+        assert_eq!(vec![1.0, 0.0, 2.0], cifti.hemisphere_data(0, Hemisphere::Lh, 0.0).unwrap());
+        assert!(matches!(cifti.hemisphere_data(0, Hemisphere::Rh, 0.0), Err(BrainviewError::MissingBrainStructure(_))));
+        assert!(matches!(cifti.hemisphere_annot(0, Hemisphere::Rh), Err(BrainviewError::MissingBrainStructure(_))));
+    }
+
+    #[test]
+    fn files_which_are_not_cifti_files_are_rejected() {
+        let mut bytes = dscalar_bytes(false);
+        assert!(parse_cifti(&bytes[..100]).is_err());
+        bytes[NIFTI2_HEADER_SIZE] = 0;  // No extension.
+        assert!(parse_cifti(&bytes).unwrap_err().contains("No CIFTI extension"));
+        assert!(parse_cifti(&[0u8; 600]).unwrap_err().contains("NIfTI-2"));
+
+        let too_many = BRAIN_MODELS.replace("IndexCount=\"2\"", "IndexCount=\"3\"");
+        let data : Vec<f32> = vec![0.0; NUM_COLUMNS];
+        assert!(parse_cifti(&cifti_bytes("<NamedMap><MapName>a</MapName></NamedMap>", "CIFTI_INDEX_TYPE_SCALARS", &too_many, &[data], false)).unwrap_err().contains("exceeds"));
+    }
+}
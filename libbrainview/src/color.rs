@@ -1,17 +1,345 @@
 
 
-use crate::{scale_to_01};
+//! Colormaps and functions to map per-vertex data to colors.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::util::{percentile, scale_to_range};
+use crate::error::{BrainviewError, Result};
+
+
+/// The colorous gradients that can be selected by name. The names follow the matplotlib naming scheme.
+const GRADIENTS : [(&str, colorous::Gradient); 38] = [
+    ("viridis", colorous::VIRIDIS),
+    ("inferno", colorous::INFERNO),
+    ("magma", colorous::MAGMA),
+    ("plasma", colorous::PLASMA),
+    ("cividis", colorous::CIVIDIS),
+    ("turbo", colorous::TURBO),
+    ("warm", colorous::WARM),
+    ("cool", colorous::COOL),
+    ("cubehelix", colorous::CUBEHELIX),
+    ("bugn", colorous::BLUE_GREEN),
+    ("bupu", colorous::BLUE_PURPLE),
+    ("gnbu", colorous::GREEN_BLUE),
+    ("orrd", colorous::ORANGE_RED),
+    ("pubugn", colorous::PURPLE_BLUE_GREEN),
+    ("pubu", colorous::PURPLE_BLUE),
+    ("purd", colorous::PURPLE_RED),
+    ("rdpu", colorous::RED_PURPLE),
+    ("ylgnbu", colorous::YELLOW_GREEN_BLUE),
+    ("ylgn", colorous::YELLOW_GREEN),
+    ("ylorbr", colorous::YELLOW_ORANGE_BROWN),
+    ("ylorrd", colorous::YELLOW_ORANGE_RED),
+    ("brbg", colorous::BROWN_GREEN),
+    ("prgn", colorous::PURPLE_GREEN),
+    ("piyg", colorous::PINK_GREEN),
+    ("puor", colorous::PURPLE_ORANGE),
+    ("rdbu", colorous::RED_BLUE),
+    ("rdgy", colorous::RED_GREY),
+    ("rdylbu", colorous::RED_YELLOW_BLUE),
+    ("rdylgn", colorous::RED_YELLOW_GREEN),
+    ("spectral", colorous::SPECTRAL),
+    ("blues", colorous::BLUES),
+    ("greens", colorous::GREENS),
+    ("greys", colorous::GREYS),
+    ("oranges", colorous::ORANGES),
+    ("purples", colorous::PURPLES),
+    ("reds", colorous::REDS),
+    ("rainbow", colorous::RAINBOW),
+    ("sinebow", colorous::SINEBOW),
+];
+
+
+/// The FreeSurfer heat colormap, for data with a range that is symmetric around zero: negative values are blue to cyan, positive values red to yellow.
+const FS_HEAT : &str = "heat";
+
+/// The FreeSurfer binary curvature colormap, for curvature data with a range that is symmetric around zero: gyri (negative values) are light gray, sulci dark gray.
+const FS_BINARY_CURV : &str = "binary_curv";
+
+
+/// The kind of a colormap. Gradients are stored as their index in `GRADIENTS`, because `colorous::Gradient` is not `Sync`.
+#[derive(Clone, Copy)]
+enum ColormapKind {
+    Gradient(usize),
+    FsHeat,
+    FsBinaryCurv,
+}
+
+
+/// A colormap, used to map scalar data in range `0..1` to colors.
+///
+/// Use `Colormap::from_name` to get a colormap. All colorous gradients are available under their matplotlib names (like `viridis`,
+/// `rdbu` or `spectral`), plus the FreeSurfer colormaps `heat` and `binary_curv`. Appending `_r` to a name reverses the colormap.
+#[derive(Clone, Copy)]
+pub struct Colormap {
+    name : &'static str,
+    reversed : bool,
+    kind : ColormapKind,
+}
+
+
+impl Colormap {
+
+    /// Get a colormap by name, e.g., `viridis` or `rdbu_r`.
+    pub fn from_name(name : &str) -> Result<Colormap> {
+        let (base_name, reversed) = match name.strip_suffix("_r") {
+            Some(base_name) => (base_name, true),
+            None => (name, false),
+        };
+        if let Some(gradient_idx) = GRADIENTS.iter().position(|(gname, _)| *gname == base_name) {
+            return Ok(Colormap { name: GRADIENTS[gradient_idx].0, reversed, kind: ColormapKind::Gradient(gradient_idx) });
+        }
+        match base_name {
+            FS_HEAT => Ok(Colormap { name: FS_HEAT, reversed, kind: ColormapKind::FsHeat }),
+            FS_BINARY_CURV => Ok(Colormap { name: FS_BINARY_CURV, reversed, kind: ColormapKind::FsBinaryCurv }),
+            _ => Err(BrainviewError::UnknownColormap(String::from(name))),
+        }
+    }
+
+
+    /// The names of all available colormaps, without the reversed variants.
+    pub fn names() -> Vec<&'static str> {
+        let mut names : Vec<&'static str> = GRADIENTS.iter().map(|(name, _)| *name).collect();
+        names.push(FS_HEAT);
+        names.push(FS_BINARY_CURV);
+        names
+    }
+
+
+    /// The name of this colormap, including the `_r` suffix if it is reversed.
+    pub fn name(&self) -> String {
+        if self.reversed { format!("{}_r", self.name) } else { String::from(self.name) }
+    }
+
+
+    /// Get a reversed version of this colormap.
+    pub fn reversed(&self) -> Colormap {
+        Colormap { reversed: !self.reversed, ..*self }
+    }
+
+
+    /// Map a value in range `0..1` to a color. Values outside of the range are clamped.
+    pub fn eval(&self, t : f32) -> colorous::Color {
+        let t = t.clamp(0.0, 1.0);
+        let t = if self.reversed { 1.0 - t } else { t };
+        match self.kind {
+            ColormapKind::Gradient(gradient_idx) => GRADIENTS[gradient_idx].1.eval_continuous(t.into()),
+            ColormapKind::FsHeat => {
+                let s = ((t - 0.5).abs() * 2.0 * 255.0).round() as u8;
+                if t >= 0.5 {
+                    colorous::Color { r: 255, g: s, b: 0 }
+                } else {
+                    colorous::Color { r: 0, g: s, b: 255 }
+                }
+            },
+            ColormapKind::FsBinaryCurv => {
+                if t >= 0.5 {
+                    colorous::Color { r: 85, g: 85, b: 85 }
+                } else {
+                    colorous::Color { r: 170, g: 170, b: 170 }
+                }
+            },
+        }
+    }
+}
+
+
+impl Default for Colormap {
+
+    /// The default colormap, viridis.
+    fn default() -> Self {
+        Colormap { name: GRADIENTS[0].0, reversed: false, kind: ColormapKind::Gradient(0) }
+    }
+}
+
+
+impl fmt::Debug for Colormap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Colormap({})", self.name())
+    }
+}
+
+
+impl PartialEq for Colormap {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.reversed == other.reversed
+    }
+}
+
+
+impl FromStr for Colormap {
+    type Err = BrainviewError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Colormap::from_name(s)
+    }
+}
+
+
+/// Determines the value range of the data that is mapped onto the colormap.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorRange {
+    /// From the minimum to the maximum of the data.
+    MinMax,
+    /// A fixed range `[vmin, vmax]`.
+    Fixed(f32, f32),
+    /// From the lower to the upper percentile of the data, both in range `0..100`. Makes the colors robust against outliers.
+    Percentile(f32, f32),
+    /// Symmetric around zero, `[-m, m]`, where `m` is the maximal absolute value of the data. For signed data like t-statistics.
+    Symmetric,
+    /// Symmetric around zero, `[-m, m]`, where `m` is the given percentile, in range `0..100`, of the absolute values of the data.
+    SymmetricPercentile(f32),
+}
+
+
+impl ColorRange {
+
+    /// Compute the value range `(vmin, vmax)` for the given data. NaN values are ignored.
+    ///
+    /// Several data vectors can be passed to compute a joint range, e.g., for both hemispheres of a subject, so that the colors
+    /// are comparable between them. Returns `None` if the range depends on the data and the data contains no values other than NaN.
+    pub fn resolve(&self, data: &[&[f32]]) -> Option<(f32, f32)> {
+        match self {
+            ColorRange::Fixed(vmin, vmax) => Some((*vmin, *vmax)),
+            ColorRange::MinMax => {
+                let values = sorted_values(data, false)?;
+                Some((values[0], values[values.len() - 1]))
+            },
+            ColorRange::Percentile(lower, upper) => {
+                let values = sorted_values(data, false)?;
+                Some((percentile(&values, *lower)?, percentile(&values, *upper)?))
+            },
+            ColorRange::Symmetric => {
+                let values = sorted_values(data, true)?;
+                let m = values[values.len() - 1];
+                Some((-m, m))
+            },
+            ColorRange::SymmetricPercentile(pct) => {
+                let m = percentile(&sorted_values(data, true)?, *pct)?;
+                Some((-m, m))
+            },
+        }
+    }
+}
+
+
+/// Collect the values of all data vectors which are not NaN, optionally their absolute values, in ascending order. Returns `None` if there are none.
+fn sorted_values(data: &[&[f32]], absolute: bool) -> Option<Vec<f32>> {
+    let mut values : Vec<f32> = data.iter().flat_map(|d| d.iter()).filter(|v| !v.is_nan()).copied().collect();
+    if values.is_empty() {
+        return None;
+    }
+    if absolute {
+        for v in values.iter_mut() {
+            *v = v.abs();
+        }
+    }
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    Some(values)
+}
+
+
+impl FromStr for ColorRange {
+    type Err = String;
+
+    /// Parse a range from strings like `minmax`, `fixed:VMIN:VMAX`, `percentile:LOWER:UPPER`, `symmetric` or `symmetric:PERCENTILE`.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let parts : Vec<&str> = s.split(':').collect();
+        let mut values : Vec<f32> = Vec::with_capacity(parts.len() - 1);
+        for p in parts[1..].iter() {
+            values.push(p.parse::<f32>().map_err(|_| format!("Invalid number '{}' in color range '{}'.", p, s))?);
+        }
+        match (parts[0], values.len()) {
+            ("minmax", 0) => Ok(ColorRange::MinMax),
+            ("fixed", 2) => Ok(ColorRange::Fixed(values[0], values[1])),
+            ("percentile", 2) => Ok(ColorRange::Percentile(values[0], values[1])),
+            ("symmetric", 0) => Ok(ColorRange::Symmetric),
+            ("symmetric", 1) => Ok(ColorRange::SymmetricPercentile(values[0])),
+            _ => Err(format!("Invalid color range '{}', must be one of minmax, fixed:VMIN:VMAX, percentile:LOWER:UPPER, symmetric or symmetric:PERCENTILE.", s)),
+        }
+    }
+}
+
+
+/// Determines how values outside of the color range are displayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutOfRange {
+    /// Use the color of the closest end of the colormap.
+    Clip,
+    /// Make the vertices fully transparent, i.e., set the alpha channel to 0.
+    Transparent,
+}
+
+
+/// A colormap together with the data values that are mapped to its ends, i.e., the color scale of a mesh. Used to draw colorbars.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorScale {
+    pub colormap : Colormap,
+    pub vmin : f32,
+    pub vmax : f32,
+}
+
+
+/// Describes how per-vertex data is mapped to colors: the colormap, the value range and what to do with values outside of that range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorMapping {
+    pub colormap : Colormap,
+    pub range : ColorRange,
+    pub out_of_range : OutOfRange,
+}
+
+
+impl ColorMapping {
+
+    /// The default mapping with the given colormap.
+    pub fn with_colormap(colormap : Colormap) -> Self {
+        ColorMapping { colormap, ..Default::default() }
+    }
+
+
+    /// Get a copy of this mapping with the range fixed to the joint range of the given data vectors, see `ColorRange::resolve`.
+    ///
+    /// Use the result to color several meshes with the same color scale. If the data contains no values other than NaN, the range is unchanged.
+    pub fn with_joint_range(&self, data: &[&[f32]]) -> Self {
+        match self.range.resolve(data) {
+            Some((vmin, vmax)) => ColorMapping { range: ColorRange::Fixed(vmin, vmax), ..*self },
+            None => *self,
+        }
+    }
+
+
+    /// Resolve the range of this mapping for the given data vectors, see `ColorRange::resolve`, and get the resulting color scale.
+    pub fn resolve_scale(&self, data: &[&[f32]]) -> Option<ColorScale> {
+        self.range.resolve(data).map(|(vmin, vmax)| ColorScale { colormap: self.colormap, vmin, vmax })
+    }
+}
+
+
+impl Default for ColorMapping {
+
+    /// The default mapping: the default colormap from data minimum to maximum.
+    fn default() -> Self {
+        ColorMapping { colormap: Colormap::default(), range: ColorRange::MinMax, out_of_range: OutOfRange::Clip }
+    }
+}
+
+
+/// The RGB color of vertices with NaN values, a light gray.
+pub const NAN_COLOR : [u8; 3] = [245, 245, 245];
+
 
 /// Apply a colormap to the given data, i.e., map the values to colors.
-pub fn apply_colormap(data: Vec<f32>, cmap: colorous::Gradient) -> Vec<colorous::Color> {
+pub fn apply_colormap(data: Vec<f32>, cmap: &Colormap) -> Vec<colorous::Color> {
 
-    let nan_color = colorous::Color{ r: 245 as u8, g: 245 as u8, b: 245 as u8};
+    let nan_color = colorous::Color{ r: NAN_COLOR[0], g: NAN_COLOR[1], b: NAN_COLOR[2]};
     let mut colors : Vec<colorous::Color> = Vec::with_capacity(data.len());
     for v in data.iter() {
         if v.is_nan() {
             colors.push(nan_color);
         } else {
-            colors.push(cmap.eval_continuous((*v).into()));
+            colors.push(cmap.eval(*v));
         }
     }
     colors
@@ -26,16 +354,231 @@ pub fn colors_as_u8_4(colors : Vec<colorous::Color>, alpha: u8) -> Vec<u8> {
         for c in rgb.iter() {
             col_255.push(*c);
         }
-        col_255.push(alpha as u8);
+        col_255.push(alpha);
     }
     col_255
 }
 
 
-/// Apply a colormap and return colors as required for three-d.
-pub fn color_from_data(data : Vec<f32>) -> Vec<u8> {
+/// Convert an RGBA color with channel values in range `0..1` to `u8` channel values. Values outside the range are clamped.
+pub fn rgba_f32_to_u8(color : &[f32; 4]) -> [u8; 4] {
+    let mut col : [u8; 4] = [0; 4];
+    for (c, v) in col.iter_mut().zip(color.iter()) {
+        *c = (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+    }
+    col
+}
+
+
+/// Map data to colors as described by the mapping, and return colors as required for three-d.
+///
+/// NaN values, and all values if the data contains nothing but NaN values, get a light gray.
+pub fn color_from_data(data : Vec<f32>, mapping : &ColorMapping) -> Vec<u8> {
     let mesh_alpha : u8 = 255;
-    let gradient = colorous::VIRIDIS;
-    colors_as_u8_4(apply_colormap(scale_to_01(data), gradient), mesh_alpha)
+    let scaled = match mapping.range.resolve(&[&data]) {
+        Some((vmin, vmax)) => scale_to_range(&data, vmin, vmax),
+        None => vec![f32::NAN; data.len()],
+    };
+    let mut colors = colors_as_u8_4(apply_colormap(scaled.clone(), &mapping.colormap), mesh_alpha);
+    if mapping.out_of_range == OutOfRange::Transparent {
+        for (idx, v) in scaled.iter().enumerate() {
+            if *v < 0.0 || *v > 1.0 {
+                colors[idx * 4 + 3] = 0;
+            }
+        }
+    }
+    colors
+}
+
+
+/// Determines which values of a statistical overlay are shown, see `threshold_overlay_colors`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Threshold {
+    /// Show values `>= pos` and values `<= -neg`. Both thresholds are given as positive numbers, use `f32::INFINITY` to hide all values of one sign.
+    Signed { pos : f32, neg : f32 },
+    /// Show p-values `<= alpha`.
+    PValue(f32),
+}
+
+
+impl Threshold {
+
+    /// Whether a value passes the threshold, i.e., should be shown. NaN values never pass.
+    pub fn passes(&self, v : f32) -> bool {
+        match self {
+            Threshold::Signed { pos, neg } => v >= *pos || v <= -*neg,
+            Threshold::PValue(alpha) => v <= *alpha,
+        }
+    }
 }
 
+
+/// Compute grayscale background colors from curvature data, like `?h.curv` or `?h.sulc`: gyri are light gray, sulci dark gray.
+pub fn curv_background_colors(curv : &[f32]) -> Vec<u8> {
+    let binary_curv = Colormap::from_name("binary_curv").unwrap_or_default();
+    color_from_data(curv.to_vec(), &ColorMapping { colormap: binary_curv, range: ColorRange::Symmetric, out_of_range: OutOfRange::Clip })
+}
+
+
+/// Color a statistical overlay on top of background colors, like the heat overlays in FreeSurfer's freeview.
+///
+/// Vertices with values that pass the threshold are colored as described by the mapping. All other vertices, including those with NaN
+/// values and those made transparent by the mapping, keep their background color, e.g., from `curv_background_colors`. The `background`
+/// must contain 4 RGBA values per vertex.
+pub fn threshold_overlay_colors(data : &[f32], background : &[u8], threshold : &Threshold, mapping : &ColorMapping) -> Vec<u8> {
+    let overlay = color_from_data(data.to_vec(), mapping);
+    let mut colors = background.to_vec();
+    for (idx, v) in data.iter().enumerate() {
+        if !v.is_nan() && threshold.passes(*v) && overlay[idx * 4 + 3] > 0 {
+            colors[idx * 4..idx * 4 + 4].copy_from_slice(&overlay[idx * 4..idx * 4 + 4]);
+        }
+    }
+    colors
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The heat colormap with a fixed range from -4 to 4, so that 4 is yellow and -4 is cyan.
+    fn heat_mapping() -> ColorMapping {
+        ColorMapping { colormap: Colormap::from_name("heat").unwrap(), range: ColorRange::Fixed(-4.0, 4.0), out_of_range: OutOfRange::Clip }
+    }
+
+    #[test]
+    fn colormaps_can_be_looked_up_by_name() {
+        let cmap = Colormap::from_name("viridis").unwrap();
+        assert_eq!("viridis", cmap.name());
+        assert_eq!(Colormap::default(), cmap);
+        assert_eq!(colorous::VIRIDIS.eval_continuous(0.0).into_array(), cmap.eval(0.0).into_array());
+        assert_eq!(colorous::VIRIDIS.eval_continuous(1.0).into_array(), cmap.eval(1.0).into_array());
+        assert_eq!(cmap, "viridis".parse::<Colormap>().unwrap());
+        assert!(Colormap::names().iter().all(|name| Colormap::from_name(name).is_ok()));
+    }
+
+    #[test]
+    fn the_r_suffix_reverses_a_colormap() {
+        let cmap = Colormap::from_name("rdbu").unwrap();
+        let reversed = Colormap::from_name("rdbu_r").unwrap();
+        assert_eq!("rdbu_r", reversed.name());
+        assert_eq!(cmap.reversed(), reversed);
+        assert_eq!(cmap, reversed.reversed());
+        assert_eq!(cmap.eval(0.0).into_array(), reversed.eval(1.0).into_array());
+        assert_eq!(cmap.eval(0.25).into_array(), reversed.eval(0.75).into_array());
+    }
+
+    #[test]
+    fn the_heat_colormap_is_blue_to_cyan_and_red_to_yellow() {
+        let cmap = Colormap::from_name("heat").unwrap();
+        assert_eq!([0, 255, 255], cmap.eval(0.0).into_array());
+        assert_eq!([0, 128, 255], cmap.eval(0.25).into_array());
+        assert_eq!([255, 0, 0], cmap.eval(0.5).into_array());
+        assert_eq!([255, 255, 0], cmap.eval(1.0).into_array());
+        assert_eq!(cmap.eval(1.0).into_array(), cmap.eval(3.0).into_array());
+    }
+
+    #[test]
+    fn the_binary_curv_colormap_has_two_grays() {
+        let cmap = Colormap::from_name("binary_curv").unwrap();
+        assert_eq!([170, 170, 170], cmap.eval(0.2).into_array());
+        assert_eq!([85, 85, 85], cmap.eval(0.5).into_array());
+        assert_eq!([85, 85, 85], cmap.eval(0.9).into_array());
+        assert_eq!([170, 170, 170], Colormap::from_name("binary_curv_r").unwrap().eval(0.9).into_array());
+    }
+
+    #[test]
+    fn unknown_colormap_names_are_rejected() {
+        assert!(matches!(Colormap::from_name("nope"), Err(BrainviewError::UnknownColormap(name)) if name == "nope"));
+        assert!(matches!(Colormap::from_name("_r"), Err(BrainviewError::UnknownColormap(_))));
+        assert!("nope_r".parse::<Colormap>().is_err());
+    }
+
+    #[test]
+    fn signed_thresholds_pass_large_values_of_both_signs() {
+        let threshold = Threshold::Signed { pos: 2.0, neg: 3.0 };
+        assert!(threshold.passes(2.0));
+        assert!(threshold.passes(10.0));
+        assert!(!threshold.passes(1.9));
+        assert!(!threshold.passes(-2.9));
+        assert!(threshold.passes(-3.0));
+        assert!(!threshold.passes(f32::NAN));
+        assert!(!Threshold::Signed { pos: 2.0, neg: f32::INFINITY }.passes(-100.0));
+    }
+
+    #[test]
+    fn p_value_thresholds_pass_small_values() {
+        let threshold = Threshold::PValue(0.05);
+        assert!(threshold.passes(0.01));
+        assert!(threshold.passes(0.05));
+        assert!(!threshold.passes(0.2));
+        assert!(!threshold.passes(f32::NAN));
+    }
+
+    #[test]
+    fn values_below_the_threshold_keep_the_background_color() {
+        let background = [1, 2, 3, 255].repeat(5);
+        let data = [4.0, 1.0, -1.0, -4.0, f32::NAN];
+        let colors = threshold_overlay_colors(&data, &background, &Threshold::Signed { pos: 2.0, neg: 2.0 }, &heat_mapping());
+        assert_eq!(vec![255, 255, 0, 255], colors[0..4].to_vec());
+        assert_eq!(background[4..12].to_vec(), colors[4..12].to_vec());
+        assert_eq!(vec![0, 255, 255, 255], colors[12..16].to_vec());
+        assert_eq!(background[16..20].to_vec(), colors[16..20].to_vec());
+    }
+
+    #[test]
+    fn values_made_transparent_by_the_mapping_keep_the_background_color() {
+        let background = [1, 2, 3, 255].repeat(2);
+        let mapping = ColorMapping { range: ColorRange::Fixed(-4.0, 4.0), out_of_range: OutOfRange::Transparent, ..heat_mapping() };
+        let colors = threshold_overlay_colors(&[8.0, 3.0], &background, &Threshold::Signed { pos: 2.0, neg: 2.0 }, &mapping);
+        assert_eq!(background[0..4].to_vec(), colors[0..4].to_vec());
+        assert_ne!(background[4..8].to_vec(), colors[4..8].to_vec());
+    }
+
+    #[test]
+    fn percentile_ranges_ignore_outliers_and_nan_values() {
+        let data : Vec<f32> = (0..=100).map(|v| v as f32).chain(vec![f32::NAN, 1000.0]).collect();
+        let (vmin, vmax) = ColorRange::Percentile(0.0, 100.0).resolve(&[&data]).unwrap();
+        assert_eq!((0.0, 1000.0), (vmin, vmax));
+        let (vmin, vmax) = ColorRange::Percentile(10.0, 90.0).resolve(&[&data[0..101]]).unwrap();
+        assert!((vmin - 10.0).abs() < 1e-4);
+        assert!((vmax - 90.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn symmetric_ranges_use_the_largest_absolute_value() {
+        let lh = [-5.0, 1.0, f32::NAN];
+        let rh = [2.0, 3.0];
+        assert_eq!(Some((-5.0, 5.0)), ColorRange::Symmetric.resolve(&[&lh, &rh]));
+        assert_eq!(Some((-3.0, 3.0)), ColorRange::Symmetric.resolve(&[&rh]));
+    }
+
+    #[test]
+    fn symmetric_percentile_ranges_use_a_percentile_of_the_absolute_values() {
+        let data = [-4.0, -3.0, 0.0, 1.0, 2.0];
+        assert_eq!(Some((-2.0, 2.0)), ColorRange::SymmetricPercentile(50.0).resolve(&[&data]));
+        assert_eq!(Some((-4.0, 4.0)), ColorRange::SymmetricPercentile(100.0).resolve(&[&data]));
+    }
+
+    #[test]
+    fn data_dependent_ranges_of_nan_only_data_cannot_be_resolved() {
+        let data = [f32::NAN, f32::NAN];
+        assert_eq!(None, ColorRange::MinMax.resolve(&[&data]));
+        assert_eq!(None, ColorRange::Percentile(5.0, 95.0).resolve(&[&data]));
+        assert_eq!(None, ColorRange::Symmetric.resolve(&[&data]));
+        assert_eq!(None, ColorRange::SymmetricPercentile(95.0).resolve(&[&data, &[]]));
+        assert_eq!(Some((-1.0, 1.0)), ColorRange::Fixed(-1.0, 1.0).resolve(&[&data]));
+    }
+
+    #[test]
+    fn out_of_range_values_can_be_made_transparent() {
+        let data = vec![-1.0, 0.0, 0.5, 1.0, 2.0];
+        let mapping = ColorMapping { range: ColorRange::Fixed(0.0, 1.0), out_of_range: OutOfRange::Transparent, ..Default::default() };
+        let alphas : Vec<u8> = color_from_data(data.clone(), &mapping).chunks(4).map(|c| c[3]).collect();
+        assert_eq!(vec![0, 255, 255, 255, 0], alphas);
+        let clipped = ColorMapping { out_of_range: OutOfRange::Clip, ..mapping };
+        let colors = color_from_data(data, &clipped);
+        assert!(colors.chunks(4).all(|c| c[3] == 255));
+        assert_eq!(colors[0..4].to_vec(), colors[4..8].to_vec());
+    }
+}
@@ -0,0 +1,329 @@
+//! Colorbars, which show the values represented by the colors of a color scale.
+//!
+//! A colorbar can be rendered to an image or exported as SVG, and it can be shown in the interactive scene, see `SceneSettings`.
+
+use std::fs;
+use std::path::Path;
+
+use image::{Rgba, RgbaImage};
+
+use crate::ColoredBrainMesh;
+use crate::color::{ColorScale};
+use crate::font::{draw_text, text_height, text_width, GLYPH_HEIGHT};
+use crate::error::{Result};
+
+
+/// The number of gradient stops used when exporting a colorbar as SVG.
+const SVG_GRADIENT_STOPS : usize = 64;
+
+
+/// The orientation of a colorbar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorbarOrientation {
+    /// The minimum is on the left, the tick labels are below the bar.
+    Horizontal,
+    /// The minimum is at the bottom, the tick labels are right of the bar.
+    Vertical,
+}
+
+
+/// A gradient bar for a color scale, with ticks, tick labels and an optional label describing the data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Colorbar {
+    pub scale : ColorScale,
+    /// A label describing the data, e.g., `Thickness (mm)`. Drawn above the bar, set to an empty string to omit it.
+    pub label : String,
+    pub orientation : ColorbarOrientation,
+    /// The number of ticks, including those at the minimum and maximum. Values below 2 result in ticks at the minimum and maximum only.
+    pub num_ticks : usize,
+    /// The length and thickness of the gradient bar in pixels. Sizes below 1 are treated as 1.
+    pub bar_size : (u32, u32),
+    /// The size of a font pixel in image pixels.
+    pub text_scale : u32,
+    pub text_color : [u8; 4],
+    pub bg_color : [u8; 4],
+}
+
+
+/// The positions of the parts of a colorbar image, shared by the image and SVG renderers.
+struct ColorbarLayout {
+    width : u32,
+    height : u32,
+    /// The top left corner of the bar, and its width and height.
+    bar : (u32, u32, u32, u32),
+    /// The top left corner of the label.
+    label_pos : (u32, u32),
+    ticks : Vec<ColorbarTick>,
+}
+
+
+/// The position of a tick of a colorbar image.
+struct ColorbarTick {
+    /// The start and end point of the tick line.
+    line : ((u32, u32), (u32, u32)),
+    /// The top left corner of the tick label.
+    label_pos : (u32, u32),
+    label : String,
+}
+
+
+impl Colorbar {
+
+    /// A horizontal colorbar with 5 ticks and without label for the given color scale.
+    pub fn new(scale : ColorScale) -> Self {
+        Colorbar {
+            scale,
+            label : String::new(),
+            orientation : ColorbarOrientation::Horizontal,
+            num_ticks : 5,
+            bar_size : (300, 20),
+            text_scale : 2,
+            text_color : [0, 0, 0, 255],
+            bg_color : [255, 255, 255, 255],
+        }
+    }
+
+
+    /// The colorbar for the color scale of a mesh, see `ColoredBrainMesh::color_scale`. Returns `None` if the colors of the mesh do not represent data.
+    pub fn for_mesh(cb_mesh : &ColoredBrainMesh) -> Option<Self> {
+        cb_mesh.color_scale.map(Colorbar::new)
+    }
+
+
+    /// Set the label of the colorbar.
+    pub fn with_label(self, label : &str) -> Self {
+        Colorbar { label: String::from(label), ..self }
+    }
+
+
+    /// Set the orientation of the colorbar.
+    pub fn with_orientation(self, orientation : ColorbarOrientation) -> Self {
+        Colorbar { orientation, ..self }
+    }
+
+
+    /// The data values at the ticks, evenly spaced from the minimum to the maximum of the color scale.
+    pub fn tick_values(&self) -> Vec<f32> {
+        let num_ticks = self.num_ticks.max(2);
+        (0..num_ticks).map(|idx| self.scale.vmin + (self.scale.vmax - self.scale.vmin) * idx as f32 / (num_ticks - 1) as f32).collect()
+    }
+
+
+    /// Format a tick value, with a precision that depends on the value range of the color scale.
+    pub fn tick_label(&self, value : f32) -> String {
+        let span = (self.scale.vmax - self.scale.vmin).abs();
+        let decimals = if span >= 100.0 { 0 } else if span >= 10.0 { 1 } else if span >= 1.0 { 2 } else { 3 };
+        let label = format!("{:.*}", decimals, value);
+        // Avoid labels like `-0.00`.
+        if label.starts_with('-') && label[1..].chars().all(|c| c == '0' || c == '.') { label[1..].to_string() } else { label }
+    }
+
+
+    /// The size of the colorbar image in pixels.
+    pub fn image_size(&self) -> (u32, u32) {
+        let layout = self.layout();
+        (layout.width, layout.height)
+    }
+
+
+    /// Compute the positions of the bar, the ticks and the texts.
+    fn layout(&self) -> ColorbarLayout {
+        let scale = self.text_scale;
+        let margin = 4 * scale;
+        let gap = 2 * scale;
+        let tick_length = 3 * scale;
+        let line_height = text_height(scale);
+        let (bar_length, bar_thickness) = (self.bar_size.0.max(1), self.bar_size.1.max(1));
+
+        let tick_values = self.tick_values();
+        let tick_labels : Vec<String> = tick_values.iter().map(|v| self.tick_label(*v)).collect();
+        let max_label_width = tick_labels.iter().map(|l| text_width(l, scale)).max().unwrap_or(0);
+        let label_height = if self.label.is_empty() { 0 } else { line_height + gap };
+        let num_ticks = tick_values.len() as u32;
+
+        match self.orientation {
+            ColorbarOrientation::Horizontal => {
+                // The tick labels at the ends may extend beyond the bar.
+                let side = margin.max(max_label_width / 2 + scale);
+                let bar = (side, margin + label_height, bar_length, bar_thickness);
+                let width = (2 * side + bar_length).max(2 * margin + text_width(&self.label, scale));
+                let height = bar.1 + bar_thickness + tick_length + gap + line_height + margin;
+                let ticks = tick_labels.into_iter().enumerate().map(|(idx, label)| {
+                    let x = bar.0 + (bar_length - 1) * idx as u32 / (num_ticks - 1);
+                    let y = bar.1 + bar_thickness;
+                    let text_x = x.saturating_sub(text_width(&label, scale) / 2);
+                    ColorbarTick { line: ((x, y), (x, y + tick_length)), label_pos: (text_x, y + tick_length + gap), label }
+                }).collect();
+                ColorbarLayout { width, height, bar, label_pos: (margin, margin), ticks }
+            },
+            ColorbarOrientation::Vertical => {
+                // The tick labels at the ends may extend beyond the bar.
+                let top = margin.max(line_height / 2 + scale) + label_height;
+                let bar = (margin, top, bar_thickness, bar_length);
+                let width = (2 * margin + bar_thickness + tick_length + gap + max_label_width).max(2 * margin + text_width(&self.label, scale));
+                let height = top + bar_length + margin.max(line_height / 2 + scale);
+                let ticks = tick_labels.into_iter().enumerate().map(|(idx, label)| {
+                    let x = bar.0 + bar_thickness;
+                    let y = bar.1 + (bar_length - 1) - (bar_length - 1) * idx as u32 / (num_ticks - 1);
+                    let text_y = y.saturating_sub((GLYPH_HEIGHT * scale) / 2);
+                    ColorbarTick { line: ((x, y), (x + tick_length, y)), label_pos: (x + tick_length + gap, text_y), label }
+                }).collect();
+                ColorbarLayout { width, height, bar, label_pos: (margin, margin), ticks }
+            },
+        }
+    }
+
+
+    /// The position of a point along the bar in range `0..1`, where 0 is at the minimum and 1 at the maximum of the color scale.
+    fn bar_position(&self, layout : &ColorbarLayout, x : u32, y : u32) -> f32 {
+        let (bar_x, bar_y, bar_w, bar_h) = layout.bar;
+        match self.orientation {
+            ColorbarOrientation::Horizontal => (x - bar_x) as f32 / (bar_w.max(2) - 1) as f32,
+            ColorbarOrientation::Vertical => 1.0 - (y - bar_y) as f32 / (bar_h.max(2) - 1) as f32,
+        }
+    }
+
+
+    /// Render the colorbar into an image.
+    pub fn to_image(&self) -> RgbaImage {
+        let layout = self.layout();
+        let mut img = RgbaImage::from_pixel(layout.width, layout.height, Rgba(self.bg_color));
+
+        let (bar_x, bar_y, bar_w, bar_h) = layout.bar;
+        for y in bar_y..bar_y + bar_h {
+            for x in bar_x..bar_x + bar_w {
+                let color = self.scale.colormap.eval(self.bar_position(&layout, x, y));
+                img.put_pixel(x, y, Rgba([color.r, color.g, color.b, 255]));
+            }
+        }
+
+        for tick in layout.ticks.iter() {
+            let ((x0, y0), (x1, y1)) = tick.line;
+            for y in y0..=y1 {
+                for x in x0..=x1 {
+                    if x < layout.width && y < layout.height {
+                        img.put_pixel(x, y, Rgba(self.text_color));
+                    }
+                }
+            }
+            draw_text(&mut img, tick.label_pos.0 as i64, tick.label_pos.1 as i64, &tick.label, self.text_color, self.text_scale);
+        }
+
+        if !self.label.is_empty() {
+            draw_text(&mut img, layout.label_pos.0 as i64, layout.label_pos.1 as i64, &self.label, self.text_color, self.text_scale);
+        }
+        img
+    }
+
+
+    /// Export the colorbar as an SVG document, with the same layout as the image.
+    pub fn to_svg(&self) -> String {
+        let layout = self.layout();
+        let (bar_x, bar_y, bar_w, bar_h) = layout.bar;
+        let font_size = text_height(self.text_scale);
+        let (x2, y1, y2) = match self.orientation {
+            ColorbarOrientation::Horizontal => (1, 0, 0),
+            ColorbarOrientation::Vertical => (0, 1, 0),
+        };
+
+        let mut svg = String::new();
+        svg.push_str(&format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">\n", layout.width, layout.height, layout.width, layout.height));
+        svg.push_str(&format!("  <defs>\n    <linearGradient id=\"colormap\" x1=\"0\" y1=\"{}\" x2=\"{}\" y2=\"{}\">\n", y1, x2, y2));
+        for idx in 0..SVG_GRADIENT_STOPS {
+            let t = idx as f32 / (SVG_GRADIENT_STOPS - 1) as f32;
+            let color = self.scale.colormap.eval(t);
+            svg.push_str(&format!("      <stop offset=\"{:.4}\" stop-color=\"{}\"/>\n", t, svg_color(&[color.r, color.g, color.b, 255])));
+        }
+        svg.push_str("    </linearGradient>\n  </defs>\n");
+        svg.push_str(&format!("  <rect width=\"{}\" height=\"{}\" fill=\"{}\"/>\n", layout.width, layout.height, svg_color(&self.bg_color)));
+        svg.push_str(&format!("  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"url(#colormap)\"/>\n", bar_x, bar_y, bar_w, bar_h));
+
+        for tick in layout.ticks.iter() {
+            let ((x0, y0), (x1, y1)) = tick.line;
+            svg.push_str(&format!("  <line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"{}\" stroke-width=\"{}\"/>\n", x0, y0, x1, y1, svg_color(&self.text_color), self.text_scale));
+            svg.push_str(&format!("  <text x=\"{}\" y=\"{}\" font-family=\"sans-serif\" font-size=\"{}\" fill=\"{}\">{}</text>\n", tick.label_pos.0, tick.label_pos.1 + font_size, font_size, svg_color(&self.text_color), svg_escape(&tick.label)));
+        }
+        if !self.label.is_empty() {
+            svg.push_str(&format!("  <text x=\"{}\" y=\"{}\" font-family=\"sans-serif\" font-size=\"{}\" fill=\"{}\">{}</text>\n", layout.label_pos.0, layout.label_pos.1 + font_size, font_size, svg_color(&self.text_color), svg_escape(&self.label)));
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+
+    /// Write the colorbar to a file. The format is determined by the file extension: SVG for `.svg`, otherwise an image format supported by the `image` crate, like PNG.
+    pub fn save<P: AsRef<Path>>(&self, path : P) -> Result<()> {
+        let path = path.as_ref();
+        if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("svg")) {
+            fs::write(path, self.to_svg())?;
+        } else {
+            self.to_image().save(path)?;
+        }
+        Ok(())
+    }
+}
+
+
+/// Draw a colorbar into the bottom right corner of an image, e.g., a screenshot. The colorbar is omitted if it does not fit.
+pub fn draw_colorbar(img : &mut RgbaImage, colorbar : &Colorbar) {
+    let cb_img = colorbar.to_image();
+    if cb_img.width() <= img.width() && cb_img.height() <= img.height() {
+        let x = img.width() - cb_img.width();
+        let y = img.height() - cb_img.height();
+        image::imageops::overlay(img, &cb_img, x, y);
+    }
+}
+
+
+/// Format an RGBA color as an SVG color.
+fn svg_color(color : &[u8; 4]) -> String {
+    if color[3] == 255 {
+        format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+    } else {
+        format!("rgba({},{},{},{:.3})", color[0], color[1], color[2], color[3] as f32 / 255.0)
+    }
+}
+
+
+/// Escape the characters that have a special meaning in XML.
+fn svg_escape(text : &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::{Colormap};
+
+    fn colorbar() -> Colorbar {
+        Colorbar::new(ColorScale { colormap: Colormap::default(), vmin: 1.0, vmax: 3.0 })
+    }
+
+    #[test]
+    fn the_ticks_are_evenly_spaced_over_the_color_scale() {
+        assert_eq!(vec![1.0, 1.5, 2.0, 2.5, 3.0], colorbar().tick_values());
+        assert_eq!(vec![1.0, 3.0], Colorbar { num_ticks: 0, ..colorbar() }.tick_values());
+        assert_eq!("1.50", colorbar().tick_label(1.5));
+    }
+
+    #[test]
+    fn the_image_contains_the_bar() {
+        let colorbar = colorbar();
+        let img = colorbar.to_image();
+        assert_eq!(colorbar.image_size(), img.dimensions());
+        let (width, height) = colorbar.image_size();
+        assert!(width >= 300 && height >= 20);
+    }
+
+    #[test]
+    fn colorbars_with_a_zero_bar_size_can_be_rendered() {
+        for orientation in [ColorbarOrientation::Horizontal, ColorbarOrientation::Vertical].iter() {
+            for bar_size in [(0, 0), (0, 20), (300, 0)].iter() {
+                let colorbar = Colorbar { bar_size: *bar_size, ..colorbar().with_orientation(*orientation) };
+                assert_eq!(colorbar.image_size(), colorbar.to_image().dimensions());
+                assert!(colorbar.to_svg().contains("<svg"));
+            }
+        }
+    }
+}
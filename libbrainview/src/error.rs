@@ -3,16 +3,117 @@
 
 use quick_error::quick_error;
 use std::io::Error as IOError;
+use std::path::PathBuf;
+use neuroformats::error::NeuroformatsError;
 
 quick_error! {
     /// Error type for all error variants originated by this crate.
     #[derive(Debug)]
     pub enum BrainviewError {
         
+        /// A binary view of a label was requested, but the label values are not binary
         LabelNotBinary {
             display("Label not binary, but binary data view requested.")
         }
 
+        /// The data contains no values other than NaN, so no value range can be computed for it
+        NoValidData {
+            display("The data contains no valid values, all values are NaN.")
+        }
+
+        /// No colormap with the given name exists
+        UnknownColormap(name: String) {
+            display("Unknown colormap '{}'.", name)
+        }
+
+        /// The layout of a figure is invalid, e.g., its tiles do not fit into its grid or refer to meshes that do not exist
+        InvalidFigureLayout(msg: String) {
+            display("Invalid figure layout: {}", msg)
+        }
+
+        /// The number of vertices of a surface and of the data for it do not match
+        VertexCountMismatch(expected: usize, actual: usize) {
+            display("Vertex count mismatch: expected data for {} vertices, but got data for {} vertices.", expected, actual)
+        }
+
+        /// The number of vertex color values does not match the number of vertices of a surface
+        ColorCountMismatch(num_vertices: usize, actual: usize) {
+            display("Vertex count mismatch: expected 4 RGBA color values for each of {} vertices, i.e., {} values, but got {} values.", num_vertices, num_vertices * 4, actual)
+        }
+
+        /// The data contains no values at all
+        EmptyData {
+            display("The data is empty.")
+        }
+
+        /// A scene or image was requested without any meshes to show
+        EmptyScene {
+            display("The scene contains no meshes.")
+        }
+
+        /// The SUBJECTS_DIR environment variable is required to find a subject, but it is not set
+        SubjectsDirNotSet {
+            display("The environment variable SUBJECTS_DIR is not set.")
+        }
+
+        /// A required input file does not exist
+        MissingFile(path: PathBuf) {
+            display("File not found: '{}'.", path.display())
+        }
+
+        /// A file has a format that is not supported for the requested data
+        UnsupportedFileFormat(path: PathBuf) {
+            display("Unsupported file format: '{}'.", path.display())
+        }
+
+        /// A file exists, but its content is not valid for its format
+        InvalidFile(path: PathBuf, msg: String) {
+            display("Failed to read file '{}': {}", path.display(), msg)
+        }
+
+        /// A GIFTI file does not contain the selected data array
+        NoSuchDataArray(path: PathBuf, selection: String) {
+            display("The file '{}' contains no data array {}.", path.display(), selection)
+        }
+
+        /// The selected frame of per-vertex data does not exist
+        InvalidFrame(frame: usize, num_frames: usize) {
+            display("Invalid frame {}, the data has {} frames.", frame, num_frames)
+        }
+
+        /// The selected map of a CIFTI file does not exist
+        InvalidMap(map: usize, num_maps: usize) {
+            display("Invalid map {}, the data has {} maps.", map, num_maps)
+        }
+
+        /// A CIFTI file contains no cortex surface model for the requested hemisphere
+        MissingBrainStructure(hemi: String) {
+            display("The file contains no cortex surface model for hemisphere {}.", hemi)
+        }
+
+        /// A neuroimaging file exists, but could not be read
+        Parse(path: PathBuf, err: NeuroformatsError) {
+            display("Failed to read file '{}': {}", path.display(), err)
+            source(err)
+        }
+
+        /// The window for the interactive scene could not be opened
+        Window(msg: String) {
+            display("Failed to open window: {}", msg)
+            from(err: three_d::WindowError) -> (format!("{:?}", err))
+        }
+
+        /// Creating GPU resources or rendering failed
+        Render(msg: String) {
+            display("Rendering failed: {}", msg)
+            from(err: three_d::Error) -> (format!("{:?}", err))
+        }
+
+        /// Image encoding or decoding error
+        Image(err: image::ImageError) {
+            from()
+            source(err)
+        }
 
         /// I/O Error
         Io(err: IOError) {
@@ -0,0 +1,420 @@
+//! Export of colored brain meshes to standard 3D file formats, for use in tools like Blender or MeshLab, and for 3D printing.
+//!
+//! All formats store the vertices, the faces and the RGBA vertex colors of the mesh, as far as the format supports them:
+//! PLY and glTF store per-vertex colors. OBJ stores per-vertex colors as the widely supported `v x y z r g b` extension, and
+//! per-face materials in an MTL file. STL only supports per-face colors, which are stored using the VisCAM/SolidView convention.
+//! The color of a face is the color shared by most of its vertices, which keeps the region colors of an atlas intact.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::brainmesh::{ColoredBrainMesh};
+use crate::error::{BrainviewError, Result};
+use crate::geometry::{cross3, normalize3, sub3, vertex_coords, vertex_normals};
+
+
+/// The file formats a mesh can be exported to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeshFormat {
+    /// The Stanford polygon file format, as text.
+    PlyAscii,
+    /// The Stanford polygon file format, binary little endian.
+    PlyBinary,
+    /// The Wavefront OBJ format, with the materials in an MTL file next to it.
+    Obj,
+    /// Binary glTF 2.0.
+    Glb,
+    /// Binary STL, with per-face colors.
+    Stl,
+}
+
+
+impl MeshFormat {
+
+    /// The format for a file name, determined by its extension: binary PLY for `.ply`, and OBJ, GLB or STL for `.obj`, `.glb` and `.stl`.
+    pub fn from_path<P: AsRef<Path>>(path : P) -> Option<MeshFormat> {
+        let ext = path.as_ref().extension()?.to_string_lossy().to_lowercase();
+        match ext.as_str() {
+            "ply" => Some(MeshFormat::PlyBinary),
+            "obj" => Some(MeshFormat::Obj),
+            "glb" => Some(MeshFormat::Glb),
+            "stl" => Some(MeshFormat::Stl),
+            _ => None,
+        }
+    }
+}
+
+
+impl FromStr for MeshFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "ply" => Ok(MeshFormat::PlyBinary),
+            "ply-ascii" => Ok(MeshFormat::PlyAscii),
+            "obj" => Ok(MeshFormat::Obj),
+            "glb" => Ok(MeshFormat::Glb),
+            "stl" => Ok(MeshFormat::Stl),
+            _ => Err(format!("Invalid mesh format '{}', must be one of ply, ply-ascii, obj, glb or stl.", s)),
+        }
+    }
+}
+
+
+/// Export a mesh to a file in the given format. If no format is given, it is determined by the file extension, see `MeshFormat::from_path`.
+///
+/// For OBJ, the materials are written to a file with the same name and the extension `.mtl`. Returns an `UnsupportedFileFormat` error if
+/// no format is given and the extension is unknown.
+pub fn export_mesh<P: AsRef<Path>>(cb_mesh : &ColoredBrainMesh, path : P, format : Option<MeshFormat>) -> Result<()> {
+    let path = path.as_ref();
+    let format = format.or_else(|| MeshFormat::from_path(path)).ok_or_else(|| BrainviewError::UnsupportedFileFormat(path.to_path_buf()))?;
+    let mut writer = BufWriter::new(File::create(path)?);
+    match format {
+        MeshFormat::PlyAscii => write_ply(cb_mesh, &mut writer, false)?,
+        MeshFormat::PlyBinary => write_ply(cb_mesh, &mut writer, true)?,
+        MeshFormat::Glb => write_glb(cb_mesh, &mut writer)?,
+        MeshFormat::Stl => write_stl(cb_mesh, &mut writer)?,
+        MeshFormat::Obj => {
+            let mtl_path = path.with_extension("mtl");
+            let mtl_file_name = mtl_path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+            let mut mtl_writer = BufWriter::new(File::create(&mtl_path)?);
+            write_obj(cb_mesh, &mut writer, &mut mtl_writer, &mtl_file_name)?;
+            mtl_writer.flush()?;
+        },
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+
+/// Write a mesh in PLY format, with RGBA vertex colors. The binary variant is little endian.
+///
+/// Returns a `ColorCountMismatch` error if the mesh does not have one color per vertex.
+pub fn write_ply<W: Write>(cb_mesh : &ColoredBrainMesh, writer : &mut W, binary : bool) -> Result<()> {
+    let mesh = &cb_mesh.mesh;
+    check_color_count(cb_mesh)?;
+    let colors = &cb_mesh.vertex_colors;
+    writeln!(writer, "ply")?;
+    writeln!(writer, "format {} 1.0", if binary { "binary_little_endian" } else { "ascii" })?;
+    writeln!(writer, "comment Written by libbrainview")?;
+    writeln!(writer, "element vertex {}", mesh.num_vertices())?;
+    for coord in ["x", "y", "z"].iter() {
+        writeln!(writer, "property float {}", coord)?;
+    }
+    for channel in ["red", "green", "blue", "alpha"].iter() {
+        writeln!(writer, "property uchar {}", channel)?;
+    }
+    writeln!(writer, "element face {}", mesh.faces.len() / 3)?;
+    writeln!(writer, "property list uchar int vertex_indices")?;
+    writeln!(writer, "end_header")?;
+
+    for (coords, color) in mesh.vertices.chunks(3).zip(colors.chunks(4)) {
+        if binary {
+            for c in coords.iter() {
+                writer.write_all(&c.to_le_bytes())?;
+            }
+            writer.write_all(color)?;
+        } else {
+            writeln!(writer, "{} {} {} {} {} {} {}", coords[0], coords[1], coords[2], color[0], color[1], color[2], color[3])?;
+        }
+    }
+    for face in mesh.faces.chunks(3) {
+        if binary {
+            writer.write_all(&[3])?;
+            for vidx in face.iter() {
+                writer.write_all(&vidx.to_le_bytes())?;
+            }
+        } else {
+            writeln!(writer, "3 {} {} {}", face[0], face[1], face[2])?;
+        }
+    }
+    Ok(())
+}
+
+
+/// Write a mesh in OBJ format, and its materials in MTL format.
+///
+/// The vertex colors are written as RGB values after the vertex coordinates. Faces are grouped into one material per face color,
+/// see `face_colors`, and the OBJ file references the MTL file under the given file name. Note that data with continuous colors results
+/// in many materials, the materials are most useful for meshes with few colors like parcellations. Returns a `ColorCountMismatch` error if
+/// the mesh does not have one color per vertex.
+pub fn write_obj<W: Write, M: Write>(cb_mesh : &ColoredBrainMesh, obj_writer : &mut W, mtl_writer : &mut M, mtl_file_name : &str) -> Result<()> {
+    let mesh = &cb_mesh.mesh;
+    let face_colors = face_colors(cb_mesh)?;
+    writeln!(obj_writer, "# Written by libbrainview")?;
+    writeln!(obj_writer, "mtllib {}", mtl_file_name)?;
+    for (coords, color) in mesh.vertices.chunks(3).zip(cb_mesh.vertex_colors.chunks(4)) {
+        writeln!(obj_writer, "v {} {} {} {} {} {}", coords[0], coords[1], coords[2], color[0] as f32 / 255.0, color[1] as f32 / 255.0, color[2] as f32 / 255.0)?;
+    }
+
+    writeln!(mtl_writer, "# Written by libbrainview")?;
+    let mut materials : HashSet<[u8; 4]> = HashSet::new();
+    let mut current_material : Option<[u8; 4]> = None;
+    for (face, color) in mesh.faces.chunks(3).zip(face_colors) {
+        if current_material != Some(color) {
+            let name = format!("color_{:02x}{:02x}{:02x}{:02x}", color[0], color[1], color[2], color[3]);
+            if materials.insert(color) {
+                writeln!(mtl_writer, "\nnewmtl {}", name)?;
+                writeln!(mtl_writer, "Ka 0 0 0")?;
+                writeln!(mtl_writer, "Kd {} {} {}", color[0] as f32 / 255.0, color[1] as f32 / 255.0, color[2] as f32 / 255.0)?;
+                writeln!(mtl_writer, "d {}", color[3] as f32 / 255.0)?;
+                writeln!(mtl_writer, "illum 1")?;
+            }
+            writeln!(obj_writer, "usemtl {}", name)?;
+            current_material = Some(color);
+        }
+        // OBJ vertex indices start at 1.
+        writeln!(obj_writer, "f {} {} {}", face[0] + 1, face[1] + 1, face[2] + 1)?;
+    }
+    Ok(())
+}
+
+
+/// Write a mesh in binary glTF 2.0 format (`.glb`), with vertex normals and RGBA vertex colors.
+///
+/// The material is white, non-metallic, rough and double-sided, so the appearance is determined by the vertex colors. It uses alpha blending
+/// if any vertex is transparent. Returns an `EmptyData` error if the mesh has no vertices, as glTF requires the coordinate range, and a
+/// `ColorCountMismatch` error if the mesh does not have one color per vertex.
+pub fn write_glb<W: Write>(cb_mesh : &ColoredBrainMesh, writer : &mut W) -> Result<()> {
+    let mesh = &cb_mesh.mesh;
+    if mesh.num_vertices() == 0 {
+        return Err(BrainviewError::EmptyData);
+    }
+    check_color_count(cb_mesh)?;
+    let num_vertices = mesh.num_vertices();
+    let num_indices = mesh.faces.len();
+
+    // The binary buffer holds the positions, normals, colors and indices, one after the other. All sizes are multiples of 4.
+    let mut bin : Vec<u8> = Vec::with_capacity(num_vertices * 28 + num_indices * 4);
+    for c in mesh.vertices.iter().chain(vertex_normals(mesh).iter()) {
+        bin.extend_from_slice(&c.to_le_bytes());
+    }
+    bin.extend_from_slice(&cb_mesh.vertex_colors);
+    for vidx in mesh.faces.iter() {
+        bin.extend_from_slice(&(*vidx as u32).to_le_bytes());
+    }
+    let (positions_len, colors_len, indices_len) = (num_vertices * 12, num_vertices * 4, num_indices * 4);
+
+    let (min, max) = coords_minmax(&mesh.vertices);
+    let is_transparent = cb_mesh.vertex_colors.chunks(4).any(|color| color[3] < 255);
+    let json = format!(concat!(
+        r#"{{"asset":{{"version":"2.0","generator":"libbrainview"}},"scene":0,"scenes":[{{"nodes":[0]}}],"nodes":[{{"mesh":0}}],"#,
+        r#""meshes":[{{"primitives":[{{"attributes":{{"POSITION":0,"NORMAL":1,"COLOR_0":2}},"indices":3,"material":0,"mode":4}}]}}],"#,
+        r#""materials":[{{"pbrMetallicRoughness":{{"baseColorFactor":[1,1,1,1],"metallicFactor":0,"roughnessFactor":1}},"doubleSided":true,"alphaMode":"{}"}}],"#,
+        r#""buffers":[{{"byteLength":{}}}],"#,
+        r#""bufferViews":[{{"buffer":0,"byteOffset":0,"byteLength":{},"target":34962}},{{"buffer":0,"byteOffset":{},"byteLength":{},"target":34962}},"#,
+        r#"{{"buffer":0,"byteOffset":{},"byteLength":{},"target":34962}},{{"buffer":0,"byteOffset":{},"byteLength":{},"target":34963}}],"#,
+        r#""accessors":[{{"bufferView":0,"componentType":5126,"count":{},"type":"VEC3","min":[{},{},{}],"max":[{},{},{}]}},"#,
+        r#"{{"bufferView":1,"componentType":5126,"count":{},"type":"VEC3"}},{{"bufferView":2,"componentType":5121,"normalized":true,"count":{},"type":"VEC4"}},"#,
+        r#"{{"bufferView":3,"componentType":5125,"count":{},"type":"SCALAR"}}]}}"#),
+        if is_transparent { "BLEND" } else { "OPAQUE" },
+        bin.len(),
+        positions_len, positions_len, positions_len,
+        2 * positions_len, colors_len, 2 * positions_len + colors_len, indices_len,
+        num_vertices, min[0], min[1], min[2], max[0], max[1], max[2],
+        num_vertices, num_vertices,
+        num_indices);
+
+    // Chunks must be aligned to 4 bytes: the JSON is padded with spaces.
+    let mut json = json.into_bytes();
+    json.resize(json.len().div_ceil(4) * 4, b' ');
+    let total_len = 12 + 8 + json.len() + 8 + bin.len();
+
+    writer.write_all(b"glTF")?;
+    writer.write_all(&2u32.to_le_bytes())?;
+    writer.write_all(&(total_len as u32).to_le_bytes())?;
+    writer.write_all(&(json.len() as u32).to_le_bytes())?;
+    writer.write_all(b"JSON")?;
+    writer.write_all(&json)?;
+    writer.write_all(&(bin.len() as u32).to_le_bytes())?;
+    writer.write_all(b"BIN\0")?;
+    writer.write_all(&bin)?;
+    Ok(())
+}
+
+
+/// Write a mesh in binary STL format, with face colors.
+///
+/// The colors are stored in the attribute bytes of the faces, using the 15 bit RGB format of VisCAM and SolidView. Alpha is ignored.
+/// Returns a `ColorCountMismatch` error if the mesh does not have one color per vertex.
+pub fn write_stl<W: Write>(cb_mesh : &ColoredBrainMesh, writer : &mut W) -> Result<()> {
+    let mesh = &cb_mesh.mesh;
+    let face_colors = face_colors(cb_mesh)?;
+    let mut header = [b' '; 80];
+    let text = b"Binary STL written by libbrainview, with VisCAM/SolidView face colors";
+    header[..text.len()].copy_from_slice(text);
+    writer.write_all(&header)?;
+    writer.write_all(&((mesh.faces.len() / 3) as u32).to_le_bytes())?;
+
+    for (face, color) in mesh.faces.chunks(3).zip(face_colors) {
+        let v : Vec<[f32; 3]> = face.iter().map(|vidx| vertex_coords(&mesh.vertices, *vidx as usize)).collect();
+        let normal = normalize3(&cross3(&sub3(&v[1], &v[0]), &sub3(&v[2], &v[0])));
+        for c in normal.iter().chain(v.iter().flatten()) {
+            writer.write_all(&c.to_le_bytes())?;
+        }
+        // Bit 15 marks the color as valid, followed by 5 bits each for red, green and blue.
+        let attribute : u16 = 0x8000 | ((color[0] as u16 >> 3) << 10) | ((color[1] as u16 >> 3) << 5) | (color[2] as u16 >> 3);
+        writer.write_all(&attribute.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+
+/// Compute the RGBA color of each face of a mesh: the color shared by at least two of its vertices, or the color of its first vertex if all differ.
+///
+/// Returns a `ColorCountMismatch` error if the mesh does not have one color per vertex.
+pub fn face_colors(cb_mesh : &ColoredBrainMesh) -> Result<Vec<[u8; 4]>> {
+    check_color_count(cb_mesh)?;
+    let colors = &cb_mesh.vertex_colors;
+    let vertex_color = |vidx : i32| -> [u8; 4] {
+        let i = vidx as usize * 4;
+        [colors[i], colors[i + 1], colors[i + 2], colors[i + 3]]
+    };
+    Ok(cb_mesh.mesh.faces.chunks(3).map(|face| {
+        let (c0, c1, c2) = (vertex_color(face[0]), vertex_color(face[1]), vertex_color(face[2]));
+        if c1 == c2 && c0 != c1 { c1 } else { c0 }
+    }).collect())
+}
+
+
+/// Make sure the mesh has one RGBA color per vertex, so the colors can be written along with the vertices.
+fn check_color_count(cb_mesh : &ColoredBrainMesh) -> Result<()> {
+    let num_vertices = cb_mesh.mesh.num_vertices();
+    if cb_mesh.vertex_colors.len() != num_vertices * 4 {
+        return Err(BrainviewError::ColorCountMismatch(num_vertices, cb_mesh.vertex_colors.len()));
+    }
+    Ok(())
+}
+
+
+/// Compute the minimal and maximal coordinates along each axis, as required for the positions in glTF.
+fn coords_minmax(vertices : &[f32]) -> ([f32; 3], [f32; 3]) {
+    let mut min = [f32::INFINITY; 3];
+    let mut max = [f32::NEG_INFINITY; 3];
+    for coords in vertices.chunks(3) {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(coords[axis]);
+            max[axis] = max[axis].max(coords[axis]);
+        }
+    }
+    (min, max)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use neuroformats::BrainMesh;
+    use crate::import::{parse_obj, parse_ply};
+
+    /// A mesh with two triangles forming a square, with a different color per vertex.
+    fn square_mesh() -> ColoredBrainMesh {
+        let b_mesh = BrainMesh { vertices: vec![0.0, 0.0, 0.0, 1.5, 0.0, 0.0, 1.5, 2.0, 0.0, 0.0, 2.0, -0.25], faces: vec![0, 1, 2, 0, 2, 3] };
+        let colors = vec![255, 0, 0, 255, 0, 255, 0, 255, 0, 255, 0, 255, 0, 0, 255, 128];
+        ColoredBrainMesh::from_brainmesh_and_colors(&b_mesh, colors).unwrap()
+    }
+
+    /// Split the bytes of a written file into the text header, up to and including the line `end_header`, and the body.
+    fn split_ply_header(bytes : &[u8]) -> (String, &[u8]) {
+        let marker = b"end_header\n";
+        let end = bytes.windows(marker.len()).position(|w| w == marker).unwrap() + marker.len();
+        (String::from_utf8(bytes[..end].to_vec()).unwrap(), &bytes[end..])
+    }
+
+    #[test]
+    fn an_ascii_ply_file_can_be_read_back() {
+        let cb_mesh = square_mesh();
+        let mut bytes : Vec<u8> = Vec::new();
+        write_ply(&cb_mesh, &mut bytes, false).unwrap();
+
+        assert_eq!(cb_mesh.mesh, parse_ply(&bytes).unwrap());
+        let (header, body) = split_ply_header(&bytes);
+        assert!(header.contains("format ascii 1.0"));
+        let colors : Vec<u8> = String::from_utf8(body.to_vec()).unwrap().lines().take(4)
+            .flat_map(|line| line.split_whitespace().skip(3).map(|v| v.parse::<u8>().unwrap()).collect::<Vec<u8>>())
+            .collect();
+        assert_eq!(cb_mesh.vertex_colors, colors);
+    }
+
+    #[test]
+    fn a_binary_ply_file_can_be_read_back() {
+        let cb_mesh = square_mesh();
+        let mut bytes : Vec<u8> = Vec::new();
+        write_ply(&cb_mesh, &mut bytes, true).unwrap();
+
+        assert_eq!(cb_mesh.mesh, parse_ply(&bytes).unwrap());
+        let (header, body) = split_ply_header(&bytes);
+        assert!(header.contains("format binary_little_endian 1.0"));
+        // Each vertex has 3 floats and 4 color bytes, each face a count byte and 3 ints.
+        assert_eq!(4 * 16 + 2 * 13, body.len());
+        let colors : Vec<u8> = body[..4 * 16].chunks(16).flat_map(|vertex| vertex[12..].to_vec()).collect();
+        assert_eq!(cb_mesh.vertex_colors, colors);
+    }
+
+    #[test]
+    fn an_obj_file_can_be_read_back() {
+        let cb_mesh = square_mesh();
+        let (mut obj, mut mtl) : (Vec<u8>, Vec<u8>) = (Vec::new(), Vec::new());
+        write_obj(&cb_mesh, &mut obj, &mut mtl, "square.mtl").unwrap();
+
+        assert_eq!(cb_mesh.mesh, parse_obj(&obj).unwrap());
+        let obj = String::from_utf8(obj).unwrap();
+        assert!(obj.contains("mtllib square.mtl"));
+        let colors : Vec<u8> = obj.lines().filter(|line| line.starts_with("v "))
+            .flat_map(|line| line.split_whitespace().skip(4).map(|v| (v.parse::<f32>().unwrap() * 255.0).round() as u8).collect::<Vec<u8>>())
+            .collect();
+        let rgb : Vec<u8> = cb_mesh.vertex_colors.chunks(4).flat_map(|c| c[..3].to_vec()).collect();
+        assert_eq!(rgb, colors);
+
+        // Both faces have a different color, so there is one material for each of them.
+        let mtl = String::from_utf8(mtl).unwrap();
+        assert_eq!(2, mtl.matches("newmtl").count());
+        assert_eq!(2, obj.matches("usemtl").count());
+    }
+
+    #[test]
+    fn a_glb_file_has_a_valid_header_and_aligned_chunks() {
+        let cb_mesh = square_mesh();
+        let mut bytes : Vec<u8> = Vec::new();
+        write_glb(&cb_mesh, &mut bytes).unwrap();
+
+        let u32_at = |pos : usize| u32::from_le_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]]) as usize;
+        assert_eq!(b"glTF", &bytes[0..4]);
+        assert_eq!(2, u32_at(4));
+        assert_eq!(bytes.len(), u32_at(8));
+
+        let json_len = u32_at(12);
+        assert_eq!(b"JSON", &bytes[16..20]);
+        assert_eq!(0, json_len % 4);
+        let json = String::from_utf8(bytes[20..20 + json_len].to_vec()).unwrap();
+        assert!(json.starts_with('{'));
+        assert!(json.trim_end().ends_with('}'));
+        assert!(json.contains(r#""alphaMode":"BLEND""#));
+
+        let bin_start = 20 + json_len;
+        let bin_len = u32_at(bin_start);
+        assert_eq!(b"BIN\0", &bytes[bin_start + 4..bin_start + 8]);
+        assert_eq!(0, bin_len % 4);
+        // Positions and normals with 12 bytes, and colors with 4 bytes per vertex, then the indices with 4 bytes each.
+        assert_eq!(4 * 28 + 6 * 4, bin_len);
+        assert_eq!(bytes.len(), bin_start + 8 + bin_len);
+        let colors_start = bin_start + 8 + 4 * 24;
+        assert_eq!(cb_mesh.vertex_colors, bytes[colors_start..colors_start + 16].to_vec());
+    }
+
+    #[test]
+    fn meshes_with_too_few_colors_are_rejected() {
+        let mut cb_mesh = square_mesh();
+        cb_mesh.vertex_colors.truncate(8);
+        let mut bytes : Vec<u8> = Vec::new();
+
+        assert!(matches!(face_colors(&cb_mesh), Err(BrainviewError::ColorCountMismatch(4, 8))));
+        assert!(matches!(write_ply(&cb_mesh, &mut bytes, true), Err(BrainviewError::ColorCountMismatch(4, 8))));
+        assert!(matches!(write_obj(&cb_mesh, &mut bytes, &mut Vec::new(), "square.mtl"), Err(BrainviewError::ColorCountMismatch(4, 8))));
+        assert!(matches!(write_glb(&cb_mesh, &mut bytes), Err(BrainviewError::ColorCountMismatch(4, 8))));
+        assert!(matches!(write_stl(&cb_mesh, &mut bytes), Err(BrainviewError::ColorCountMismatch(4, 8))));
+    }
+}
@@ -0,0 +1,179 @@
+//! Compose figures that show several views of the meshes in a grid, like the classic 4-panel cortical figure.
+
+use image::{GenericImage, Rgba, RgbaImage};
+
+use crate::{ColoredBrainMesh, Hemisphere, SceneSettings};
+use crate::scene::{CameraSpec, ViewPreset};
+use crate::offscreen::{render_to_image};
+use crate::font::{draw_text, text_height, text_width};
+use crate::colorbar::{Colorbar};
+use crate::lighting::{Lighting};
+use crate::color::{rgba_f32_to_u8};
+use crate::error::{BrainviewError, Result};
+
+
+/// A single tile of a figure: a view of some of the meshes, with a title above it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FigureTile {
+    /// The indices of the meshes shown in this tile, in the mesh vector passed to `compose_figure`.
+    pub mesh_indices : Vec<usize>,
+    pub view : ViewPreset,
+    /// The hemisphere the lateral and medial views refer to.
+    pub view_hemisphere : Hemisphere,
+    pub title : String,
+}
+
+
+/// Describes the grid of a figure. Tiles are filled row by row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FigureLayout {
+    pub rows : u32,
+    pub cols : u32,
+    /// The size of the rendered view in each tile, without the title.
+    pub tile_size : (u32, u32),
+    /// The height of the title area above each tile. Set to 0 to omit the titles.
+    pub title_height : u32,
+    /// The size of a font pixel in image pixels.
+    pub title_scale : u32,
+    pub title_color : [u8; 4],
+    pub bg_color : [f32; 4],
+    pub tiles : Vec<FigureTile>,
+    /// A colorbar that is shown centered below the grid.
+    pub colorbar : Option<Colorbar>,
+    /// The lighting of the meshes, see `SceneSettings`.
+    pub lighting : Option<Lighting>,
+}
+
+
+impl FigureLayout {
+
+    /// A figure with one row per hemisphere, showing the lateral view on the left and the medial view on the right.
+    ///
+    /// Expects the mesh of the i-th hemisphere at index i of the meshes passed to `compose_figure`.
+    pub fn lateral_medial(hemis : &[Hemisphere]) -> Self {
+        let mut tiles : Vec<FigureTile> = Vec::with_capacity(hemis.len() * 2);
+        for (mesh_idx, hemi) in hemis.iter().enumerate() {
+            for view in [ViewPreset::Lateral, ViewPreset::Medial].iter() {
+                tiles.push(FigureTile { mesh_indices: vec![mesh_idx], view: *view, view_hemisphere: *hemi, title: format!("{} {:?}", hemi, view) });
+            }
+        }
+        FigureLayout {
+            rows : hemis.len() as u32,
+            cols : 2,
+            tile_size : (600, 450),
+            title_height : 40,
+            title_scale : 3,
+            title_color : [0, 0, 0, 255],
+            bg_color : [1.0, 1.0, 1.0, 1.0],
+            tiles,
+            colorbar : None,
+            lighting : Some(Lighting::default()),
+        }
+    }
+
+
+    /// The classic 4-panel figure: lateral and medial views of the left hemisphere in the first row, and of the right hemisphere in the second row.
+    ///
+    /// Expects the left hemisphere mesh at index 0 and the right hemisphere mesh at index 1 of the meshes passed to `compose_figure`.
+    pub fn four_panel() -> Self {
+        FigureLayout::lateral_medial(&Hemisphere::both())
+    }
+
+
+    /// The size of the whole figure in pixels, including the colorbar.
+    pub fn figure_size(&self) -> (u32, u32) {
+        let (cb_width, cb_height) = self.colorbar.as_ref().map_or((0, 0), |colorbar| colorbar.image_size());
+        ((self.cols * self.tile_size.0).max(cb_width), self.rows * (self.tile_size.1 + self.title_height) + cb_height)
+    }
+}
+
+
+/// Render several views of the meshes into a single image, arranged in a grid as described by the layout.
+///
+/// Each tile is rendered with the software rasterizer, see `render_to_image`, so this works without a window or GPU.
+pub fn compose_figure(meshes : &[ColoredBrainMesh], layout : &FigureLayout) -> Result<RgbaImage> {
+    if layout.tiles.len() > (layout.rows * layout.cols) as usize {
+        return Err(BrainviewError::InvalidFigureLayout(format!("{} tiles do not fit into a grid of {} rows and {} columns.", layout.tiles.len(), layout.rows, layout.cols)));
+    }
+
+    let (width, height) = layout.figure_size();
+    let mut figure = RgbaImage::from_pixel(width, height, Rgba(rgba_f32_to_u8(&layout.bg_color)));
+    let tile_settings = SceneSettings { bg_color: layout.bg_color, window_size: layout.tile_size, lighting: layout.lighting, ..Default::default() };
+
+    for (tile_idx, tile) in layout.tiles.iter().enumerate() {
+        let mut tile_meshes : Vec<ColoredBrainMesh> = Vec::with_capacity(tile.mesh_indices.len());
+        for mesh_idx in tile.mesh_indices.iter() {
+            match meshes.get(*mesh_idx) {
+                Some(cb_mesh) => tile_meshes.push(cb_mesh.clone()),
+                None => return Err(BrainviewError::InvalidFigureLayout(format!("Tile {} uses mesh index {}, but only {} meshes were given.", tile_idx, mesh_idx, meshes.len()))),
+            }
+        }
+        if tile_meshes.is_empty() {
+            return Err(BrainviewError::InvalidFigureLayout(format!("Tile {} does not show any meshes.", tile_idx)));
+        }
+
+        let tile_x = (tile_idx as u32 % layout.cols) * layout.tile_size.0;
+        let tile_y = (tile_idx as u32 / layout.cols) * (layout.tile_size.1 + layout.title_height);
+
+        let camera = CameraSpec::for_view(&tile_meshes, tile.view, tile.view_hemisphere)?;
+        let img = render_to_image(&tile_meshes, &tile_settings, &camera)?;
+        figure.copy_from(&img, tile_x, tile_y + layout.title_height).map_err(|e| BrainviewError::InvalidFigureLayout(e.to_string()))?;
+
+        if layout.title_height > 0 {
+            let title_x = tile_x as i64 + (layout.tile_size.0 as i64 - text_width(&tile.title, layout.title_scale) as i64) / 2;
+            let title_y = tile_y as i64 + (layout.title_height as i64 - text_height(layout.title_scale) as i64) / 2;
+            draw_text(&mut figure, title_x, title_y, &tile.title, layout.title_color, layout.title_scale);
+        }
+    }
+
+    if let Some(colorbar) = &layout.colorbar {
+        let cb_img = colorbar.to_image();
+        let cb_x = (width - cb_img.width()) / 2;
+        figure.copy_from(&cb_img, cb_x, height - cb_img.height()).map_err(|e| BrainviewError::InvalidFigureLayout(e.to_string()))?;
+    }
+    Ok(figure)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use neuroformats::BrainMesh;
+
+    /// A red triangle in the y-z plane, so it is seen in the lateral and medial views.
+    fn triangle_meshes() -> Vec<ColoredBrainMesh> {
+        let b_mesh = BrainMesh { vertices: vec![0.0, -10.0, -10.0, 0.0, 10.0, -10.0, 0.0, 0.0, 10.0], faces: vec![0, 1, 2] };
+        vec![ColoredBrainMesh::from_brainmesh_and_colors(&b_mesh, [255, 0, 0, 255].repeat(3)).unwrap().with_hemisphere(Some(Hemisphere::Lh))]
+    }
+
+    /// The lateral and medial views of the left hemisphere with tiny tiles.
+    fn small_layout() -> FigureLayout {
+        FigureLayout { tile_size: (40, 30), title_height: 10, title_scale: 1, lighting: None, ..FigureLayout::lateral_medial(&[Hemisphere::Lh]) }
+    }
+
+    #[test]
+    fn the_figure_has_the_size_of_the_grid() {
+        let layout = small_layout();
+        assert_eq!((80, 40), layout.figure_size());
+        let figure = compose_figure(&triangle_meshes(), &layout).unwrap();
+        assert_eq!((80, 40), figure.dimensions());
+        assert_eq!(&Rgba([255, 255, 255, 255]), figure.get_pixel(0, 0));
+        assert_eq!(&Rgba([255, 0, 0, 255]), figure.get_pixel(20, 25));
+        assert_eq!(&Rgba([255, 0, 0, 255]), figure.get_pixel(60, 25));
+    }
+
+    #[test]
+    fn tiles_which_do_not_fit_into_the_grid_are_rejected() {
+        let layout = FigureLayout { cols: 1, ..small_layout() };
+        assert!(matches!(compose_figure(&triangle_meshes(), &layout), Err(BrainviewError::InvalidFigureLayout(_))));
+    }
+
+    #[test]
+    fn tiles_with_invalid_mesh_indices_are_rejected() {
+        let mut layout = small_layout();
+        layout.tiles[1].mesh_indices = vec![1];
+        assert!(matches!(compose_figure(&triangle_meshes(), &layout), Err(BrainviewError::InvalidFigureLayout(_))));
+        layout.tiles[1].mesh_indices = Vec::new();
+        assert!(matches!(compose_figure(&triangle_meshes(), &layout), Err(BrainviewError::InvalidFigureLayout(_))));
+    }
+}
@@ -0,0 +1,116 @@
+//! A tiny built-in 5x7 pixel bitmap font, used to draw titles and labels without any font files.
+//!
+//! Only digits, upper case letters (lower case letters are drawn as upper case) and some punctuation are supported,
+//! other characters are drawn as a question mark.
+
+use image::{Rgba, RgbaImage};
+
+/// The width of a glyph in font pixels.
+pub const GLYPH_WIDTH : u32 = 5;
+
+/// The height of a glyph in font pixels.
+pub const GLYPH_HEIGHT : u32 = 7;
+
+
+/// Get the rows of the glyph for a character. The lowest 5 bits of each row are the pixels, the highest bit is the leftmost pixel.
+fn glyph(c : char) -> [u8; 7] {
+    match c.to_ascii_uppercase() {
+        ' ' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        'A' => [0x0E, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        ',' => [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '+' => [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        '_' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F],
+        '(' => [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02],
+        ')' => [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        '=' => [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00],
+        '%' => [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03],
+        _ => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    }
+}
+
+
+/// Compute the width in pixels of the given text at the given scale, i.e., size of a font pixel in image pixels.
+pub fn text_width(text : &str, scale : u32) -> u32 {
+    let num_chars = text.chars().count() as u32;
+    if num_chars == 0 { 0 } else { (num_chars * (GLYPH_WIDTH + 1) - 1) * scale }
+}
+
+
+/// Compute the height in pixels of a line of text at the given scale.
+pub fn text_height(scale : u32) -> u32 {
+    GLYPH_HEIGHT * scale
+}
+
+
+/// Get the positions of the font pixels that are set for the given text, relative to the top left corner of the text.
+///
+/// Each position is the top left corner of a square of size `scale`.
+pub fn text_pixels(text : &str, scale : u32) -> Vec<(u32, u32)> {
+    let mut pixels : Vec<(u32, u32)> = Vec::new();
+    for (char_idx, c) in text.chars().enumerate() {
+        let x_offset = char_idx as u32 * (GLYPH_WIDTH + 1);
+        for (row_idx, row) in glyph(c).iter().enumerate() {
+            for col_idx in 0..GLYPH_WIDTH {
+                if row & (1 << (GLYPH_WIDTH - 1 - col_idx)) != 0 {
+                    pixels.push(((x_offset + col_idx) * scale, row_idx as u32 * scale));
+                }
+            }
+        }
+    }
+    pixels
+}
+
+
+/// Draw text into an image, with the top left corner of the text at `(x, y)`. Parts outside of the image are clipped.
+pub fn draw_text(img : &mut RgbaImage, x : i64, y : i64, text : &str, color : [u8; 4], scale : u32) {
+    let (width, height) = (img.width() as i64, img.height() as i64);
+    for (px, py) in text_pixels(text, scale) {
+        for dy in 0..scale as i64 {
+            for dx in 0..scale as i64 {
+                let (ix, iy) = (x + px as i64 + dx, y + py as i64 + dy);
+                if ix >= 0 && iy >= 0 && ix < width && iy < height {
+                    img.put_pixel(ix as u32, iy as u32, Rgba(color));
+                }
+            }
+        }
+    }
+}
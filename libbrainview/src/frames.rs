@@ -0,0 +1,88 @@
+//! Per-vertex data with several frames, like a time series on the surface or a stack of subjects, that can be shown one frame at a time.
+
+use crate::color::{color_from_data, ColorMapping, ColorRange, ColorScale};
+use crate::error::{BrainviewError, Result};
+
+
+/// The frames of per-vertex data of a mesh, and the color mapping used for all of them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameData {
+    /// The frames, each with one value per vertex.
+    pub frames : Vec<Vec<f32>>,
+    /// The color mapping used for all frames. Its range is fixed, so colors are comparable between frames.
+    pub mapping : ColorMapping,
+    /// The index of the frame that is currently shown.
+    pub current : usize,
+}
+
+
+impl FrameData {
+
+    /// Create frame data, showing the first frame. If no color mapping is given, the default mapping is used.
+    ///
+    /// The value range of the mapping is resolved over all frames and then fixed. Returns a `NoValidData` error if there are no valid values.
+    pub fn new(frames : Vec<Vec<f32>>, mapping : Option<ColorMapping>) -> Result<Self> {
+        let mapping = mapping.unwrap_or_default();
+        let all_data : Vec<&[f32]> = frames.iter().map(|frame| frame.as_slice()).collect();
+        let (vmin, vmax) = mapping.range.resolve(&all_data).ok_or(BrainviewError::NoValidData)?;
+        Ok(FrameData { frames, mapping: ColorMapping { range: ColorRange::Fixed(vmin, vmax), ..mapping }, current: 0 })
+    }
+
+
+    /// The number of frames.
+    pub fn num_frames(&self) -> usize {
+        self.frames.len()
+    }
+
+
+    /// The data of the frame with the given index, or `None` if there is no such frame.
+    pub fn frame(&self, frame_idx : usize) -> Option<&[f32]> {
+        self.frames.get(frame_idx).map(|frame| frame.as_slice())
+    }
+
+
+    /// The RGBA vertex colors of the frame with the given index, 4 values per vertex, or `None` if there is no such frame.
+    pub fn frame_colors(&self, frame_idx : usize) -> Option<Vec<u8>> {
+        self.frame(frame_idx).map(|frame| color_from_data(frame.to_vec(), &self.mapping))
+    }
+
+
+    /// The color scale shared by all frames. The range of the mapping is fixed, so this is always `Some`.
+    pub fn color_scale(&self) -> Option<ColorScale> {
+        let all_data : Vec<&[f32]> = self.frames.iter().map(|frame| frame.as_slice()).collect();
+        self.mapping.resolve_scale(&all_data)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::color::{Colormap, OutOfRange};
+
+    #[test]
+    fn the_range_is_resolved_over_all_frames_and_fixed() {
+        let frames = vec![vec![0.0, 1.0, 2.0], vec![-2.0, f32::NAN, 4.0]];
+        let frame_data = FrameData::new(frames, Some(ColorMapping { range: ColorRange::MinMax, ..Default::default() })).unwrap();
+        assert_eq!(ColorRange::Fixed(-2.0, 4.0), frame_data.mapping.range);
+        assert_eq!(Some((-2.0, 4.0)), frame_data.color_scale().map(|scale| (scale.vmin, scale.vmax)));
+        assert_eq!(2, frame_data.num_frames());
+        assert_eq!(0, frame_data.current);
+    }
+
+    #[test]
+    fn frames_without_valid_values_are_rejected() {
+        assert!(matches!(FrameData::new(vec![vec![f32::NAN; 3]; 2], None), Err(BrainviewError::NoValidData)));
+        assert!(matches!(FrameData::new(Vec::new(), None), Err(BrainviewError::NoValidData)));
+    }
+
+    #[test]
+    fn the_colors_of_a_frame_use_the_shared_range() {
+        let mapping = ColorMapping { colormap: Colormap::from_name("heat").unwrap(), range: ColorRange::MinMax, out_of_range: OutOfRange::Clip };
+        let frame_data = FrameData::new(vec![vec![-1.0, 0.0], vec![1.0, 1.0]], Some(mapping)).unwrap();
+        assert_eq!(Some(vec![0, 255, 255, 255, 255, 0, 0, 255]), frame_data.frame_colors(0));
+        assert_eq!(Some(vec![255, 255, 0, 255, 255, 255, 0, 255]), frame_data.frame_colors(1));
+        assert_eq!(None, frame_data.frame_colors(2));
+        assert_eq!(None, frame_data.frame(2));
+    }
+}
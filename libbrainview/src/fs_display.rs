@@ -1,7 +1,8 @@
-/// Wrappers around neuroformats structs representing FreeSurfer data.
-/// These wrappers contain aditional information required for visualizing the data.
+//! Wrappers around neuroformats structs representing FreeSurfer data.
+//! These wrappers contain aditional information required for visualizing the data.
 
 use neuroformats::{FsLabel, FsAnnot, FsCurv};
+use crate::color::{ColorMapping};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct FsLabelDisplay {
@@ -9,7 +10,8 @@ pub struct FsLabelDisplay {
     pub num_surface_verts: usize,
     pub color_bin_inside: [u8; 4],
     pub color_bin_outside: [u8; 4],
-    // pub colormap: x,
+    /// The color mapping used for labels which are not binary.
+    pub mapping: ColorMapping,
 }
 
 
@@ -21,6 +23,6 @@ pub struct FsAnnotDisplay {
 
 pub struct FsCurvDisplay {
     pub curv: FsCurv,
-    // pub colormap: x,
+    pub mapping: ColorMapping,
 }
 
@@ -0,0 +1,130 @@
+//! Small 3D geometry helpers working on plain `[f32; 3]` vectors, used for computations on the CPU.
+
+use neuroformats::BrainMesh;
+
+
+/// Compute `a - b`.
+pub fn sub3(a: &[f32; 3], b: &[f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+
+/// Compute `a + b`.
+pub fn add3(a: &[f32; 3], b: &[f32; 3]) -> [f32; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+
+/// Compute `s * a`.
+pub fn scale3(a: &[f32; 3], s: f32) -> [f32; 3] {
+    [a[0] * s, a[1] * s, a[2] * s]
+}
+
+
+/// Compute the dot product of `a` and `b`.
+pub fn dot3(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+
+/// Compute the cross product of `a` and `b`.
+pub fn cross3(a: &[f32; 3], b: &[f32; 3]) -> [f32; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+
+/// Compute the length of `a`.
+pub fn length3(a: &[f32; 3]) -> f32 {
+    dot3(a, a).sqrt()
+}
+
+
+/// Scale `a` to unit length. Vectors of length zero are returned unchanged.
+pub fn normalize3(a: &[f32; 3]) -> [f32; 3] {
+    let len = length3(a);
+    if len > 0.0 { scale3(a, 1.0 / len) } else { *a }
+}
+
+
+/// Get the coordinates of the vertex with index `idx` from a flat coordinate vector, like `BrainMesh.vertices`.
+pub fn vertex_coords(vertices: &[f32], idx: usize) -> [f32; 3] {
+    [vertices[idx * 3], vertices[idx * 3 + 1], vertices[idx * 3 + 2]]
+}
+
+
+/// Compute area-weighted per-vertex normals of a mesh, as a flat vector with 3 values per vertex.
+///
+/// The normal of a vertex is the normalized sum of the normals of the faces it is part of, weighted by the face areas. The
+/// normals point to the side from which the vertices of the faces appear in counter-clockwise order, which is the outside for
+/// FreeSurfer surfaces. Vertices which are not part of any face get a zero vector.
+pub fn vertex_normals(mesh: &BrainMesh) -> Vec<f32> {
+    let mut normals : Vec<[f32; 3]> = vec![[0.0; 3]; mesh.num_vertices()];
+    for face in mesh.faces.chunks(3) {
+        let vidx = [face[0] as usize, face[1] as usize, face[2] as usize];
+        let p0 = vertex_coords(&mesh.vertices, vidx[0]);
+        let p1 = vertex_coords(&mesh.vertices, vidx[1]);
+        let p2 = vertex_coords(&mesh.vertices, vidx[2]);
+        // The length of the cross product is twice the face area, so summing it weights the faces by area.
+        let face_normal = cross3(&sub3(&p1, &p0), &sub3(&p2, &p0));
+        for v in vidx.iter() {
+            normals[*v] = add3(&normals[*v], &face_normal);
+        }
+    }
+    normals.iter().flat_map(|n| normalize3(n).to_vec()).collect()
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_close(expected : &[f32; 3], actual : &[f32; 3]) {
+        assert!(length3(&sub3(expected, actual)) < 1e-6, "expected {:?}, got {:?}", expected, actual);
+    }
+
+    #[test]
+    fn the_normals_of_a_flat_quad_are_unit_z_vectors() {
+        let mesh = BrainMesh { vertices: vec![0.0, 0.0, 0.0, 2.0, 0.0, 0.0, 2.0, 1.0, 0.0, 0.0, 1.0, 0.0], faces: vec![0, 1, 2, 0, 2, 3] };
+        let normals = vertex_normals(&mesh);
+        assert_eq!(12, normals.len());
+        for idx in 0..4 {
+            assert_close(&[0.0, 0.0, 1.0], &vertex_coords(&normals, idx));
+        }
+    }
+
+    #[test]
+    fn the_normals_of_a_tetrahedron_point_outwards() {
+        let mesh = BrainMesh {
+            vertices: vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+            faces: vec![0, 2, 1, 0, 1, 3, 0, 3, 2, 1, 2, 3],
+        };
+        let normals = vertex_normals(&mesh);
+        let centroid = [0.25, 0.25, 0.25];
+        for idx in 0..4 {
+            let normal = vertex_coords(&normals, idx);
+            assert!((length3(&normal) - 1.0).abs() < 1e-6);
+            assert!(dot3(&normal, &sub3(&vertex_coords(&mesh.vertices, idx), &centroid)) > 0.0, "normal of vertex {} points inwards", idx);
+        }
+        let n = 1.0 / 3.0f32.sqrt();
+        assert_close(&[-n, -n, -n], &vertex_coords(&normals, 0));
+    }
+
+    #[test]
+    fn larger_faces_contribute_more_to_the_vertex_normals() {
+        // Vertex 0 is part of a large face with normal +z and a small face with normal +y.
+        let mesh = BrainMesh {
+            vertices: vec![0.0, 0.0, 0.0, 10.0, 0.0, 0.0, 0.0, 10.0, 0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0],
+            faces: vec![0, 1, 2, 0, 3, 4],
+        };
+        let normals = vertex_normals(&mesh);
+        // The face areas are 50 and 0.5, so the weighted sum is (0, 1, 100).
+        let len = (1.0f32 + 100.0 * 100.0).sqrt();
+        assert_close(&[0.0, 1.0 / len, 100.0 / len], &vertex_coords(&normals, 0));
+    }
+
+    #[test]
+    fn vertices_without_faces_get_a_zero_normal() {
+        let mesh = BrainMesh { vertices: vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 5.0, 5.0, 5.0], faces: vec![0, 1, 2] };
+        assert_eq!([0.0, 0.0, 0.0], vertex_coords(&vertex_normals(&mesh), 3));
+    }
+}
@@ -0,0 +1,538 @@
+//! Reading GIFTI files, the XML based file format for surface meshes and per-vertex data used by CAT12, HCP, FSL and others.
+//!
+//! A GIFTI file contains a list of data arrays, each with an intent that describes its content, like `NIFTI_INTENT_POINTSET` for the
+//! vertex coordinates of a surface or `NIFTI_INTENT_TRIANGLE` for its faces. The data of an array can be encoded as ASCII text, as base64
+//! encoded binary data, optionally zlib-compressed, or stored in an external file.
+//!
+//! Per-vertex data is stored in `.func.gii` and `.shape.gii` files, with one data array per map or time point, and parcellations in
+//! `.label.gii` files, with a label table that assigns region names and colors to the integer keys stored for the vertices.
+
+use std::fs;
+use std::fmt;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use flate2::read::{GzDecoder, ZlibDecoder};
+use xml::reader::{EventReader, XmlEvent};
+
+use neuroformats::{BrainMesh, FsAnnot, FsAnnotColortable};
+
+use crate::error::{BrainviewError, Result};
+
+
+/// The intent of the data arrays containing the vertex coordinates of a surface.
+pub const INTENT_POINTSET : &str = "NIFTI_INTENT_POINTSET";
+
+/// The intent of the data arrays containing the faces of a surface, as vertex indices.
+pub const INTENT_TRIANGLE : &str = "NIFTI_INTENT_TRIANGLE";
+
+
+/// The metadata entry that contains the name of a data array, e.g., the name of a map in HCP files.
+pub const META_NAME : &str = "Name";
+
+
+/// The values of a GIFTI data array, in row major order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GiftiData {
+    UInt8(Vec<u8>),
+    Int32(Vec<i32>),
+    Float32(Vec<f32>),
+}
+
+
+impl GiftiData {
+
+    /// The number of values.
+    pub fn len(&self) -> usize {
+        match self {
+            GiftiData::UInt8(values) => values.len(),
+            GiftiData::Int32(values) => values.len(),
+            GiftiData::Float32(values) => values.len(),
+        }
+    }
+
+
+    /// Whether there are no values.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+
+    /// The values as `f32`, independent of the data type.
+    pub fn to_f32(&self) -> Vec<f32> {
+        match self {
+            GiftiData::UInt8(values) => values.iter().map(|v| *v as f32).collect(),
+            GiftiData::Int32(values) => values.iter().map(|v| *v as f32).collect(),
+            GiftiData::Float32(values) => values.clone(),
+        }
+    }
+
+
+    /// The values as `i32`, independent of the data type. Float values are truncated.
+    pub fn to_i32(&self) -> Vec<i32> {
+        match self {
+            GiftiData::UInt8(values) => values.iter().map(|v| *v as i32).collect(),
+            GiftiData::Int32(values) => values.clone(),
+            GiftiData::Float32(values) => values.iter().map(|v| *v as i32).collect(),
+        }
+    }
+}
+
+
+/// A data array of a GIFTI file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GiftiDataArray {
+    /// The intent of the array, like `NIFTI_INTENT_POINTSET` or `NIFTI_INTENT_SHAPE`.
+    pub intent : String,
+    /// The dimensions of the array, e.g., `[num_vertices, 3]` for the vertex coordinates of a surface.
+    pub dims : Vec<usize>,
+    /// The values, in row major order.
+    pub data : GiftiData,
+    /// The metadata of the array, as name and value pairs.
+    pub meta : Vec<(String, String)>,
+}
+
+
+impl GiftiDataArray {
+
+    /// The value of the metadata entry with the given name, if any.
+    pub fn meta_value(&self, name : &str) -> Option<&str> {
+        self.meta.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+    }
+}
+
+
+/// An entry of the label table of a GIFTI file: a region with its name and display color.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GiftiLabel {
+    /// The key of the region, which is stored for the vertices in the region.
+    pub key : i32,
+    pub name : String,
+    /// The RGBA display color, in range `0..1`.
+    pub color : [f32; 4],
+}
+
+
+/// The selection of a data array in a GIFTI file: by its index, starting at 0, or by its name, see `META_NAME`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GiftiArray {
+    Index(usize),
+    Name(String),
+}
+
+
+impl Default for GiftiArray {
+
+    /// The first data array.
+    fn default() -> Self {
+        GiftiArray::Index(0)
+    }
+}
+
+
+impl FromStr for GiftiArray {
+    type Err = String;
+
+    /// Parse a data array selection: a number is an index, everything else a name.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s.parse::<usize>() {
+            Ok(idx) => GiftiArray::Index(idx),
+            Err(_) => GiftiArray::Name(String::from(s)),
+        })
+    }
+}
+
+
+impl fmt::Display for GiftiArray {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GiftiArray::Index(idx) => write!(f, "with index {}", idx),
+            GiftiArray::Name(name) => write!(f, "named '{}'", name),
+        }
+    }
+}
+
+
+/// The contents of a GIFTI file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GiftiImage {
+    /// The metadata of the file, as name and value pairs.
+    pub meta : Vec<(String, String)>,
+    /// The regions of a parcellation. Empty for files which do not contain one.
+    pub label_table : Vec<GiftiLabel>,
+    pub data_arrays : Vec<GiftiDataArray>,
+}
+
+
+impl GiftiImage {
+
+    /// The first data array with the given intent, if any.
+    pub fn data_array_with_intent(&self, intent : &str) -> Option<&GiftiDataArray> {
+        self.data_arrays.iter().find(|da| da.intent == intent)
+    }
+
+
+    /// The selected data array, if it exists.
+    pub fn data_array(&self, array : &GiftiArray) -> Option<&GiftiDataArray> {
+        match array {
+            GiftiArray::Index(idx) => self.data_arrays.get(*idx),
+            GiftiArray::Name(name) => self.data_arrays.iter().find(|da| da.meta_value(META_NAME) == Some(name.as_str())),
+        }
+    }
+
+
+    /// The names of the data arrays, see `META_NAME`. Arrays without a name get an empty string.
+    pub fn data_array_names(&self) -> Vec<String> {
+        self.data_arrays.iter().map(|da| da.meta_value(META_NAME).unwrap_or("").to_string()).collect()
+    }
+}
+
+
+/// Read a GIFTI file. Returns a `MissingFile` error if the file does not exist, and an `InvalidFile` error if it cannot be parsed.
+pub fn read_gifti<P: AsRef<Path>>(path : P) -> Result<GiftiImage> {
+    let path = path.as_ref();
+    if !path.is_file() {
+        return Err(BrainviewError::MissingFile(path.to_path_buf()));
+    }
+    let file = fs::File::open(path)?;
+    parse_gifti(path, std::io::BufReader::new(file)).map_err(|msg| BrainviewError::InvalidFile(path.to_path_buf(), msg))
+}
+
+
+/// Parse the XML content of a GIFTI file. The path of the file is only used to find external data files.
+fn parse_gifti<R: Read>(path : &Path, source : R) -> std::result::Result<GiftiImage, String> {
+    let reader = EventReader::new(source);
+    let mut image = GiftiImage { meta: Vec::new(), label_table: Vec::new(), data_arrays: Vec::new() };
+    let mut elements : Vec<String> = Vec::new();     // The names of the currently open elements.
+    let mut attributes : Vec<(String, String)> = Vec::new();   // The attributes of the currently open data array.
+    let mut label_attributes : Vec<(String, String)> = Vec::new();
+    let mut meta : Vec<(String, String)> = Vec::new();
+    let (mut md_name, mut md_value, mut text) = (String::new(), String::new(), String::new());
+
+    for event in reader {
+        match event.map_err(|err| err.to_string())? {
+            XmlEvent::StartElement { name, attributes: attrs, .. } => {
+                let attrs = attrs.into_iter().map(|attr| (attr.name.local_name, attr.value)).collect();
+                match name.local_name.as_str() {
+                    "DataArray" => attributes = attrs,
+                    "Label" => label_attributes = attrs,
+                    _ => {},
+                }
+                elements.push(name.local_name);
+                text.clear();
+            },
+            XmlEvent::Characters(chars) | XmlEvent::CData(chars) => text.push_str(&chars),
+            XmlEvent::EndElement { name } => {
+                elements.pop();
+                match name.local_name.as_str() {
+                    "Name" => md_name = text.trim().to_string(),
+                    "Value" => md_value = text.trim().to_string(),
+                    "MD" => meta.push((std::mem::take(&mut md_name), std::mem::take(&mut md_value))),
+                    "Label" => image.label_table.push(parse_label(&label_attributes, &text)?),
+                    "MetaData" if elements.last().map(|e| e.as_str()) == Some("GIFTI") => image.meta = std::mem::take(&mut meta),
+                    "Data" => {
+                        let (dims, data) = decode_data_array(path, &attributes, &text)?;
+                        let intent = attribute(&attributes, "Intent").unwrap_or("NIFTI_INTENT_NONE").to_string();
+                        image.data_arrays.push(GiftiDataArray { intent, dims, data, meta: Vec::new() });
+                    },
+                    "DataArray" => {
+                        // The metadata of an array may come before or after its data.
+                        if let Some(da) = image.data_arrays.last_mut() {
+                            da.meta = std::mem::take(&mut meta);
+                        }
+                    },
+                    _ => {},
+                }
+                text.clear();
+            },
+            _ => {},
+        }
+    }
+    Ok(image)
+}
+
+
+/// Read a surface mesh from a GIFTI file, like the `.surf.gii` files of CAT12 and HCP. The file must contain a pointset and a triangle array.
+pub fn read_gifti_surface<P: AsRef<Path>>(path : P) -> Result<BrainMesh> {
+    let path = path.as_ref();
+    let image = read_gifti(path)?;
+    let missing = |intent : &str| BrainviewError::InvalidFile(path.to_path_buf(), format!("No data array with intent {}, the file does not contain a surface.", intent));
+    let vertices = image.data_array_with_intent(INTENT_POINTSET).ok_or_else(|| missing(INTENT_POINTSET))?.data.to_f32();
+    let faces = image.data_array_with_intent(INTENT_TRIANGLE).ok_or_else(|| missing(INTENT_TRIANGLE))?.data.to_i32();
+    Ok(BrainMesh { vertices, faces })
+}
+
+
+/// Read one data array of per-vertex data from a GIFTI file, like a `.func.gii` or `.shape.gii` file, as `f32` values.
+///
+/// Returns a `NoSuchDataArray` error if the file does not contain the selected array.
+pub fn read_gifti_data<P: AsRef<Path>>(path : P, array : &GiftiArray) -> Result<Vec<f32>> {
+    let path = path.as_ref();
+    let image = read_gifti(path)?;
+    let data_array = image.data_array(array).ok_or_else(|| BrainviewError::NoSuchDataArray(path.to_path_buf(), array.to_string()))?;
+    Ok(data_array.data.to_f32())
+}
+
+
+/// Read all data arrays of per-vertex data from a GIFTI file, e.g., the time points of a `.func.gii` file, as `f32` values.
+pub fn read_gifti_data_arrays<P: AsRef<Path>>(path : P) -> Result<Vec<Vec<f32>>> {
+    Ok(read_gifti(path)?.data_arrays.iter().map(|da| da.data.to_f32()).collect())
+}
+
+
+/// Read a parcellation from a GIFTI file, like a `.label.gii` file, as a FreeSurfer parcellation.
+///
+/// The keys of the label table are used as the labels of the regions, so the parcellation can be used like one read from an annot
+/// file, e.g., with `FsAnnotDisplay` and to show the region names of picked vertices. Returns a `NoSuchDataArray` error if the file
+/// does not contain the selected array, and an `InvalidFile` error if it has no label table.
+pub fn read_gifti_annot<P: AsRef<Path>>(path : P, array : &GiftiArray) -> Result<FsAnnot> {
+    let path = path.as_ref();
+    let image = read_gifti(path)?;
+    if image.label_table.is_empty() {
+        return Err(BrainviewError::InvalidFile(path.to_path_buf(), String::from("No label table, the file does not contain a parcellation.")));
+    }
+    let data_array = image.data_array(array).ok_or_else(|| BrainviewError::NoSuchDataArray(path.to_path_buf(), array.to_string()))?;
+    let vertex_labels = data_array.data.to_i32();
+    let labels = &image.label_table;
+    let channel = |c : usize| -> Vec<i32> { labels.iter().map(|l| (l.color[c].clamp(0.0, 1.0) * 255.0).round() as i32).collect() };
+    let colortable = FsAnnotColortable {
+        id : labels.iter().map(|l| l.key).collect(),
+        name : labels.iter().map(|l| l.name.clone()).collect(),
+        r : channel(0),
+        g : channel(1),
+        b : channel(2),
+        a : vec![0; labels.len()],  // A transparency in FreeSurfer color tables, 0 is opaque.
+        label : labels.iter().map(|l| l.key).collect(),
+    };
+    Ok(FsAnnot { vertex_indices: (0..vertex_labels.len() as i32).collect(), vertex_labels, colortable })
+}
+
+
+/// Parse an entry of a label table, given the attributes and text of its `Label` element. Older files use `Index` instead of `Key`.
+pub(crate) fn parse_label(attributes : &[(String, String)], text : &str) -> std::result::Result<GiftiLabel, String> {
+    let key = attribute(attributes, "Key").or_else(|| attribute(attributes, "Index")).ok_or_else(|| String::from("Label without key."))?;
+    let key = key.trim().parse::<i32>().map_err(|_| format!("Invalid label key '{}'.", key))?;
+    let mut color = [0.0, 0.0, 0.0, 1.0];
+    for (c, name) in ["Red", "Green", "Blue", "Alpha"].iter().enumerate() {
+        if let Some(value) = attribute(attributes, name) {
+            color[c] = value.trim().parse::<f32>().map_err(|_| format!("Invalid {} value '{}' of label {}.", name, value, key))?;
+        }
+    }
+    Ok(GiftiLabel { key, name: text.trim().to_string(), color })
+}
+
+
+/// Get the value of the attribute with the given name.
+pub(crate) fn attribute<'a>(attributes : &'a [(String, String)], name : &str) -> Option<&'a str> {
+    attributes.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+}
+
+
+/// Decode the data of a data array, given its attributes and the text of its `Data` element. Returns the dimensions and the values in row major order.
+fn decode_data_array(path : &Path, attributes : &[(String, String)], text : &str) -> std::result::Result<(Vec<usize>, GiftiData), String> {
+    let dimensionality : usize = attribute(attributes, "Dimensionality").unwrap_or("1").parse().map_err(|_| String::from("Invalid Dimensionality."))?;
+    let dims = (0..dimensionality).map(|d| {
+        attribute(attributes, &format!("Dim{}", d)).ok_or_else(|| format!("Missing Dim{}.", d))?.parse::<usize>().map_err(|_| format!("Invalid Dim{}.", d))
+    }).collect::<std::result::Result<Vec<usize>, String>>()?;
+    let num_values : usize = dims.iter().product();
+    let data_type = attribute(attributes, "DataType").unwrap_or("");
+    let value_size = match data_type {
+        "NIFTI_TYPE_UINT8" => 1,
+        "NIFTI_TYPE_INT32" | "NIFTI_TYPE_FLOAT32" => 4,
+        _ => return Err(format!("Unsupported data type '{}'.", data_type)),
+    };
+
+    let encoding = attribute(attributes, "Encoding").unwrap_or("ASCII");
+    let data = if encoding == "ASCII" {
+        let tokens : Vec<&str> = text.split_whitespace().collect();
+        let invalid = |_| String::from("Invalid ASCII data.");
+        match data_type {
+            "NIFTI_TYPE_UINT8" => GiftiData::UInt8(tokens.iter().map(|t| t.parse::<u8>()).collect::<std::result::Result<_, _>>().map_err(invalid)?),
+            "NIFTI_TYPE_INT32" => GiftiData::Int32(tokens.iter().map(|t| t.parse::<i32>()).collect::<std::result::Result<_, _>>().map_err(invalid)?),
+            _ => GiftiData::Float32(tokens.iter().map(|t| t.parse::<f32>()).collect::<std::result::Result<_, _>>().map_err(|_| String::from("Invalid ASCII data."))?),
+        }
+    } else {
+        let bytes = match encoding {
+            "Base64Binary" => decode_base64(text)?,
+            "GZipBase64Binary" => decompress(&decode_base64(text)?)?,
+            "ExternalFileBinary" => read_external_file(path, attributes, num_values * value_size)?,
+            _ => return Err(format!("Unsupported encoding '{}'.", encoding)),
+        };
+        if bytes.len() < num_values * value_size {
+            return Err(format!("Expected {} bytes of data, but found {}.", num_values * value_size, bytes.len()));
+        }
+        let is_big_endian = attribute(attributes, "Endian") == Some("BigEndian");
+        let words = bytes.chunks_exact(4).take(num_values).map(|w| {
+            let w = [w[0], w[1], w[2], w[3]];
+            if is_big_endian { u32::from_be_bytes(w) } else { u32::from_le_bytes(w) }
+        });
+        match data_type {
+            "NIFTI_TYPE_UINT8" => GiftiData::UInt8(bytes[..num_values].to_vec()),
+            "NIFTI_TYPE_INT32" => GiftiData::Int32(words.map(|w| w as i32).collect()),
+            _ => GiftiData::Float32(words.map(f32::from_bits).collect()),
+        }
+    };
+    if data.len() != num_values {
+        return Err(format!("Expected {} values, but found {}.", num_values, data.len()));
+    }
+
+    let is_column_major = attribute(attributes, "ArrayIndexingOrder") == Some("ColumnMajorOrder");
+    let data = if is_column_major && dims.len() == 2 { transpose(data, dims[0], dims[1]) } else { data };
+    Ok((dims, data))
+}
+
+
+/// Decode base64 text, ignoring whitespace.
+fn decode_base64(text : &str) -> std::result::Result<Vec<u8>, String> {
+    let text : String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    base64::decode(text).map_err(|err| format!("Invalid base64 data: {}", err))
+}
+
+
+/// Decompress data compressed with zlib, as written by most software, or with gzip, as the name of the GIFTI encoding suggests.
+fn decompress(bytes : &[u8]) -> std::result::Result<Vec<u8>, String> {
+    let mut decompressed = Vec::new();
+    let result = if bytes.starts_with(&[0x1f, 0x8b]) {
+        GzDecoder::new(bytes).read_to_end(&mut decompressed)
+    } else {
+        ZlibDecoder::new(bytes).read_to_end(&mut decompressed)
+    };
+    result.map_err(|err| format!("Invalid compressed data: {}", err))?;
+    Ok(decompressed)
+}
+
+
+/// Read the data of a data array from an external file, which is given relative to the directory of the GIFTI file.
+fn read_external_file(path : &Path, attributes : &[(String, String)], num_bytes : usize) -> std::result::Result<Vec<u8>, String> {
+    let file_name = attribute(attributes, "ExternalFileName").ok_or_else(|| String::from("Missing ExternalFileName."))?;
+    let offset : u64 = attribute(attributes, "ExternalFileOffset").filter(|o| !o.is_empty()).unwrap_or("0").parse().map_err(|_| String::from("Invalid ExternalFileOffset."))?;
+    let external_path : PathBuf = path.parent().unwrap_or_else(|| Path::new("")).join(file_name);
+    let mut bytes = vec![0; num_bytes];
+    let mut file = fs::File::open(&external_path).map_err(|err| format!("Could not open external file '{}': {}", external_path.display(), err))?;
+    file.seek(SeekFrom::Start(offset)).and_then(|_| file.read_exact(&mut bytes)).map_err(|err| format!("Could not read external file '{}': {}", external_path.display(), err))?;
+    Ok(bytes)
+}
+
+
+/// Transpose a 2D array with the given dimensions from column major to row major order.
+fn transpose(data : GiftiData, rows : usize, cols : usize) -> GiftiData {
+    fn transposed<T: Copy>(values : &[T], rows : usize, cols : usize) -> Vec<T> {
+        (0..rows * cols).map(|i| values[(i % cols) * rows + i / cols]).collect()
+    }
+    match data {
+        GiftiData::UInt8(values) => GiftiData::UInt8(transposed(&values, rows, cols)),
+        GiftiData::Int32(values) => GiftiData::Int32(transposed(&values, rows, cols)),
+        GiftiData::Float32(values) => GiftiData::Float32(transposed(&values, rows, cols)),
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+
+    /// A GIFTI file with the given data arrays, given as their attributes, metadata and data text.
+    fn gifti_xml(data_arrays : &[(&str, &str, &str)]) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<GIFTI Version=\"1.0\" NumberOfDataArrays=\"1\">\n  <MetaData/>\n  <LabelTable/>\n");
+        for (attributes, name, data) in data_arrays.iter() {
+            xml.push_str(&format!("  <DataArray {}>\n    <MetaData><MD><Name>Name</Name><Value>{}</Value></MD></MetaData>\n    <Data>{}</Data>\n  </DataArray>\n", attributes, name, data));
+        }
+        xml.push_str("</GIFTI>\n");
+        xml
+    }
+
+    fn parse(xml : &str) -> GiftiImage {
+        parse_gifti(Path::new("test.func.gii"), xml.as_bytes()).unwrap()
+    }
+
+    /// The attributes of a 1D float array with the given number of values and encoding.
+    fn float_attributes(num_values : usize, encoding : &str) -> String {
+        format!("Intent=\"NIFTI_INTENT_SHAPE\" DataType=\"NIFTI_TYPE_FLOAT32\" ArrayIndexingOrder=\"RowMajorOrder\" Dimensionality=\"1\" Dim0=\"{}\" Encoding=\"{}\" Endian=\"LittleEndian\"", num_values, encoding)
+    }
+
+    fn float_bytes(values : &[f32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    const VALUES : [f32; 4] = [1.5, -2.0, 0.25, 100.0];
+
+    #[test]
+    fn ascii_data_arrays_are_read() {
+        let image = parse(&gifti_xml(&[(&float_attributes(4, "ASCII"), "thickness", "1.5 -2.0\n0.25 100")]));
+        assert_eq!(1, image.data_arrays.len());
+        let da = &image.data_arrays[0];
+        assert_eq!("NIFTI_INTENT_SHAPE", da.intent);
+        assert_eq!(vec![4], da.dims);
+        assert_eq!(GiftiData::Float32(VALUES.to_vec()), da.data);
+        assert_eq!(Some("thickness"), da.meta_value(META_NAME));
+    }
+
+    #[test]
+    fn base64_data_arrays_are_read() {
+        let data = base64::encode(float_bytes(&VALUES));
+        let image = parse(&gifti_xml(&[(&float_attributes(4, "Base64Binary"), "thickness", &data)]));
+        assert_eq!(GiftiData::Float32(VALUES.to_vec()), image.data_arrays[0].data);
+    }
+
+    #[test]
+    fn compressed_base64_data_arrays_are_read() {
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&float_bytes(&VALUES)).unwrap();
+        let data = base64::encode(encoder.finish().unwrap());
+        let image = parse(&gifti_xml(&[(&float_attributes(4, "GZipBase64Binary"), "thickness", &data)]));
+        assert_eq!(GiftiData::Float32(VALUES.to_vec()), image.data_arrays[0].data);
+    }
+
+    #[test]
+    fn big_endian_int_data_arrays_are_read() {
+        let attributes = "Intent=\"NIFTI_INTENT_LABEL\" DataType=\"NIFTI_TYPE_INT32\" Dimensionality=\"1\" Dim0=\"3\" Encoding=\"Base64Binary\" Endian=\"BigEndian\"";
+        let data = base64::encode([7i32, -1, 65536].iter().flat_map(|v| v.to_be_bytes()).collect::<Vec<u8>>());
+        let image = parse(&gifti_xml(&[(attributes, "labels", &data)]));
+        assert_eq!(GiftiData::Int32(vec![7, -1, 65536]), image.data_arrays[0].data);
+    }
+
+    #[test]
+    fn column_major_arrays_are_transposed_to_row_major_order() {
+        // The three vertices (1, 4), (2, 5) and (3, 6), stored column by column.
+        let attributes = "Intent=\"NIFTI_INTENT_POINTSET\" DataType=\"NIFTI_TYPE_FLOAT32\" ArrayIndexingOrder=\"ColumnMajorOrder\" Dimensionality=\"2\" Dim0=\"3\" Dim1=\"2\" Encoding=\"ASCII\"";
+        let image = parse(&gifti_xml(&[(attributes, "coords", "1 2 3 4 5 6")]));
+        assert_eq!(vec![3, 2], image.data_arrays[0].dims);
+        assert_eq!(GiftiData::Float32(vec![1.0, 4.0, 2.0, 5.0, 3.0, 6.0]), image.data_arrays[0].data);
+    }
+
+    #[test]
+    fn data_arrays_are_selected_by_index_and_by_name() {
+        let attributes = float_attributes(2, "ASCII");
+        let image = parse(&gifti_xml(&[(&attributes, "map A", "1 2"), (&attributes, "map B", "3 4")]));
+        assert_eq!(vec![String::from("map A"), String::from("map B")], image.data_array_names());
+        assert_eq!(GiftiData::Float32(vec![3.0, 4.0]), image.data_array(&"1".parse::<GiftiArray>().unwrap()).unwrap().data);
+        assert_eq!(GiftiData::Float32(vec![1.0, 2.0]), image.data_array(&"map A".parse::<GiftiArray>().unwrap()).unwrap().data);
+        assert_eq!(GiftiData::Float32(vec![1.0, 2.0]), image.data_array(&GiftiArray::default()).unwrap().data);
+        assert!(image.data_array(&GiftiArray::Index(2)).is_none());
+        assert!(image.data_array(&GiftiArray::Name(String::from("map C"))).is_none());
+    }
+
+    #[test]
+    fn reading_a_missing_data_array_is_an_error() {
+        let path = std::env::temp_dir().join(format!("brainview_test_{}.func.gii", std::process::id()));
+        let attributes = float_attributes(2, "ASCII");
+        fs::write(&path, gifti_xml(&[(&attributes, "map A", "1 2")])).unwrap();
+        let by_name = read_gifti_data(&path, &GiftiArray::Name(String::from("map B")));
+        let by_index = read_gifti_data(&path, &GiftiArray::Index(1));
+        let existing = read_gifti_data(&path, &GiftiArray::Name(String::from("map A")));
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(by_name, Err(BrainviewError::NoSuchDataArray(_, ref array)) if array == "named 'map B'"));
+        assert!(matches!(by_index, Err(BrainviewError::NoSuchDataArray(_, ref array)) if array == "with index 1"));
+        assert_eq!(vec![1.0, 2.0], existing.unwrap());
+    }
+
+    #[test]
+    fn invalid_data_is_rejected() {
+        let xml = gifti_xml(&[(&float_attributes(3, "ASCII"), "thickness", "1 2")]);
+        assert!(parse_gifti(Path::new("test.func.gii"), xml.as_bytes()).unwrap_err().contains("Expected 3 values"));
+        let xml = gifti_xml(&[(&float_attributes(2, "Base64Binary"), "thickness", "not base64!")]);
+        assert!(parse_gifti(Path::new("test.func.gii"), xml.as_bytes()).is_err());
+    }
+}
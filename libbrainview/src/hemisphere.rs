@@ -0,0 +1,84 @@
+//! Brain hemispheres and the FreeSurfer file name conventions that come with them.
+
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// A cortical hemisphere.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Hemisphere {
+    Lh,
+    Rh,
+}
+
+
+impl Hemisphere {
+
+    /// The file name prefix FreeSurfer uses for this hemisphere, i.e., `lh` or `rh`.
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            Hemisphere::Lh => "lh",
+            Hemisphere::Rh => "rh",
+        }
+    }
+
+
+    /// Construct a FreeSurfer file name for this hemisphere, e.g., `lh.white` for the name `white`.
+    pub fn file_name(&self, name: &str) -> String {
+        format!("{}.{}", self.prefix(), name)
+    }
+
+
+    /// The other hemisphere.
+    pub fn other(&self) -> Hemisphere {
+        match self {
+            Hemisphere::Lh => Hemisphere::Rh,
+            Hemisphere::Rh => Hemisphere::Lh,
+        }
+    }
+
+
+    /// Detect the hemisphere from a file name or path, using the FreeSurfer prefixes like in `lh.white` and the HCP infixes like
+    /// in `100307.L.inflated.32k_fs_LR.surf.gii`. Returns `None` if the file name contains neither.
+    pub fn from_file_name(file_name : &str) -> Option<Hemisphere> {
+        let name = Path::new(file_name).file_name()?.to_str()?;
+        let parts : Vec<&str> = name.split('.').collect();
+        match parts.first() {
+            Some(&"lh") => return Some(Hemisphere::Lh),
+            Some(&"rh") => return Some(Hemisphere::Rh),
+            _ => {},
+        }
+        let hcp_parts = parts.get(1..parts.len().saturating_sub(1)).unwrap_or(&[]);
+        match (hcp_parts.contains(&"L"), hcp_parts.contains(&"R")) {
+            (true, false) => Some(Hemisphere::Lh),
+            (false, true) => Some(Hemisphere::Rh),
+            _ => None,
+        }
+    }
+
+
+    /// Both hemispheres, left first.
+    pub fn both() -> Vec<Hemisphere> {
+        vec![Hemisphere::Lh, Hemisphere::Rh]
+    }
+}
+
+
+impl fmt::Display for Hemisphere {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.prefix())
+    }
+}
+
+
+impl FromStr for Hemisphere {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "lh" => Ok(Hemisphere::Lh),
+            "rh" => Ok(Hemisphere::Rh),
+            _ => Err(format!("Invalid hemisphere '{}', must be 'lh' or 'rh'.", s)),
+        }
+    }
+}
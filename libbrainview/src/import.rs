@@ -0,0 +1,478 @@
+//! Reading surface meshes from the file formats of other neuroimaging software and 3D tools, so they can be displayed like FreeSurfer surfaces.
+//!
+//! Supported are GIFTI surfaces (`.gii`, e.g., from CAT12 and HCP, see the `gifti` module), PLY (ASCII and binary), OBJ and legacy VTK
+//! polydata files (ASCII and binary, e.g., from FSL). Only the geometry is read: vertex colors and other vertex properties are ignored,
+//! and polygons with more than 3 vertices are split into triangles.
+
+use std::fs;
+use std::path::Path;
+
+use neuroformats::{BrainMesh, read_surf};
+
+use crate::error::{BrainviewError, Result};
+use crate::gifti::{read_gifti_surface};
+use crate::util::{read_file};
+
+
+/// Read a surface mesh from a file, with the format determined by the file extension: GIFTI for `.gii`, PLY for `.ply`, OBJ for `.obj`
+/// and VTK for `.vtk`. All other files are read as FreeSurfer surfaces, like `lh.white`.
+///
+/// Returns a `MissingFile` error if the file does not exist, and an `InvalidFile` or `Parse` error if it cannot be read.
+pub fn read_mesh<P: AsRef<Path>>(path : P) -> Result<BrainMesh> {
+    let path = path.as_ref();
+    let ext = path.extension().map(|ext| ext.to_string_lossy().to_lowercase()).unwrap_or_default();
+    match ext.as_str() {
+        "gii" => read_gifti_surface(path),
+        "ply" => read_ply(path),
+        "obj" => read_obj(path),
+        "vtk" => read_vtk(path),
+        _ => Ok(read_file(path, |p| read_surf(p))?.mesh),
+    }
+}
+
+
+/// Read a surface mesh from a PLY file, in ASCII or binary format.
+pub fn read_ply<P: AsRef<Path>>(path : P) -> Result<BrainMesh> {
+    read_mesh_file(path.as_ref(), parse_ply)
+}
+
+
+/// Read a surface mesh from a Wavefront OBJ file.
+pub fn read_obj<P: AsRef<Path>>(path : P) -> Result<BrainMesh> {
+    read_mesh_file(path.as_ref(), parse_obj)
+}
+
+
+/// Read a surface mesh from a legacy VTK polydata file, in ASCII or binary format.
+pub fn read_vtk<P: AsRef<Path>>(path : P) -> Result<BrainMesh> {
+    read_mesh_file(path.as_ref(), parse_vtk)
+}
+
+
+/// Read a file and parse its content into a mesh with the given parser, then check that the faces only reference existing vertices.
+fn read_mesh_file(path : &Path, parser : fn(&[u8]) -> std::result::Result<BrainMesh, String>) -> Result<BrainMesh> {
+    if !path.is_file() {
+        return Err(BrainviewError::MissingFile(path.to_path_buf()));
+    }
+    let bytes = fs::read(path)?;
+    parse_mesh(&bytes, parser).map_err(|msg| BrainviewError::InvalidFile(path.to_path_buf(), msg))
+}
+
+
+/// Parse a mesh with the given parser, and make sure all faces reference existing vertices.
+fn parse_mesh(bytes : &[u8], parser : fn(&[u8]) -> std::result::Result<BrainMesh, String>) -> std::result::Result<BrainMesh, String> {
+    let mesh = parser(bytes)?;
+    let num_vertices = mesh.vertices.len() / 3;
+    if let Some(vidx) = mesh.faces.iter().find(|vidx| **vidx < 0 || **vidx as usize >= num_vertices) {
+        return Err(format!("A face references vertex {}, but the mesh has {} vertices.", vidx, num_vertices));
+    }
+    Ok(mesh)
+}
+
+
+/// The numeric types used in PLY and VTK files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScalarType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    I64,
+    F32,
+    F64,
+}
+
+
+impl ScalarType {
+
+    /// The type for a type name used in PLY or VTK files, like `uchar`, `float32` or `vtktypeint64`.
+    fn from_name(name : &str) -> std::result::Result<ScalarType, String> {
+        match name {
+            "char" | "int8" => Ok(ScalarType::I8),
+            "uchar" | "uint8" | "unsigned_char" => Ok(ScalarType::U8),
+            "short" | "int16" => Ok(ScalarType::I16),
+            "ushort" | "uint16" | "unsigned_short" => Ok(ScalarType::U16),
+            "int" | "int32" | "vtktypeint32" => Ok(ScalarType::I32),
+            "uint" | "uint32" | "unsigned_int" => Ok(ScalarType::U32),
+            "long" | "vtkIdType" | "vtktypeint64" => Ok(ScalarType::I64),
+            "float" | "float32" => Ok(ScalarType::F32),
+            "double" | "float64" => Ok(ScalarType::F64),
+            _ => Err(format!("Unsupported data type '{}'.", name)),
+        }
+    }
+
+
+    /// The size of a value of this type in binary files, in bytes.
+    fn size(&self) -> usize {
+        match self {
+            ScalarType::I8 | ScalarType::U8 => 1,
+            ScalarType::I16 | ScalarType::U16 => 2,
+            ScalarType::I32 | ScalarType::U32 | ScalarType::F32 => 4,
+            ScalarType::I64 | ScalarType::F64 => 8,
+        }
+    }
+}
+
+
+/// A position in the content of a file, from which text tokens or binary values are read.
+struct Cursor<'a> {
+    bytes : &'a [u8],
+    pos : usize,
+}
+
+
+impl<'a> Cursor<'a> {
+
+    fn new(bytes : &'a [u8]) -> Self {
+        Cursor { bytes, pos: 0 }
+    }
+
+
+    /// Read the next whitespace separated text token, or `None` at the end of the content.
+    fn next_token(&mut self) -> Option<&'a str> {
+        while self.pos < self.bytes.len() && self.bytes[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+        let start = self.pos;
+        while self.pos < self.bytes.len() && !self.bytes[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+        if start == self.pos { None } else { std::str::from_utf8(&self.bytes[start..self.pos]).ok() }
+    }
+
+
+    /// Read the rest of the current line, without the line break, or `None` at the end of the content.
+    fn next_line(&mut self) -> Option<&'a str> {
+        if self.pos >= self.bytes.len() {
+            return None;
+        }
+        let start = self.pos;
+        while self.pos < self.bytes.len() && self.bytes[self.pos] != b'\n' {
+            self.pos += 1;
+        }
+        let line = &self.bytes[start..self.pos];
+        self.pos += 1;
+        std::str::from_utf8(line).ok().map(|line| line.trim_end_matches('\r'))
+    }
+
+
+    /// Read the next value of the given type, as a text token or in binary format with the given byte order.
+    fn next_value(&mut self, scalar_type : ScalarType, binary : Option<Endian>) -> std::result::Result<f64, String> {
+        let endian = match binary {
+            Some(endian) => endian,
+            None => {
+                let token = self.next_token().ok_or_else(|| String::from("Unexpected end of file."))?;
+                return token.parse::<f64>().map_err(|_| format!("Invalid number '{}'.", token));
+            },
+        };
+        let size = scalar_type.size();
+        if self.pos + size > self.bytes.len() {
+            return Err(String::from("Unexpected end of file."));
+        }
+        let mut b = [0u8; 8];
+        b[..size].copy_from_slice(&self.bytes[self.pos..self.pos + size]);
+        if endian == Endian::Big {
+            b[..size].reverse();
+        }
+        self.pos += size;
+        Ok(match scalar_type {
+            ScalarType::I8 => b[0] as i8 as f64,
+            ScalarType::U8 => b[0] as f64,
+            ScalarType::I16 => i16::from_le_bytes([b[0], b[1]]) as f64,
+            ScalarType::U16 => u16::from_le_bytes([b[0], b[1]]) as f64,
+            ScalarType::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            ScalarType::U32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            ScalarType::I64 => i64::from_le_bytes(b) as f64,
+            ScalarType::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            ScalarType::F64 => f64::from_le_bytes(b),
+        })
+    }
+}
+
+
+/// The byte order of binary data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Endian {
+    Little,
+    Big,
+}
+
+
+/// Split a polygon into triangles sharing its first vertex, and append them to the faces.
+fn push_polygon(faces : &mut Vec<i32>, polygon : &[i32]) {
+    for i in 1..polygon.len().saturating_sub(1) {
+        faces.extend_from_slice(&[polygon[0], polygon[i], polygon[i + 1]]);
+    }
+}
+
+
+/// A property of an element in a PLY file: a scalar, or a list with the type of its length and of its items.
+enum PlyProperty {
+    Scalar(String, ScalarType),
+    List(String, ScalarType, ScalarType),
+}
+
+
+/// Parse the content of a PLY file. The coordinates are read from the `x`, `y` and `z` properties of the `vertex` element, and the
+/// faces from the `vertex_indices` or `vertex_index` list of the `face` element. All other elements and properties are skipped.
+pub(crate) fn parse_ply(bytes : &[u8]) -> std::result::Result<BrainMesh, String> {
+    let mut cursor = Cursor::new(bytes);
+    if cursor.next_line() != Some("ply") {
+        return Err(String::from("Not a PLY file."));
+    }
+    let mut binary : Option<Endian> = None;
+    let mut elements : Vec<(String, usize, Vec<PlyProperty>)> = Vec::new();
+    loop {
+        let line = cursor.next_line().ok_or_else(|| String::from("Missing end_header."))?;
+        let tokens : Vec<&str> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            ["end_header"] => break,
+            ["format", "ascii", _] => binary = None,
+            ["format", "binary_little_endian", _] => binary = Some(Endian::Little),
+            ["format", "binary_big_endian", _] => binary = Some(Endian::Big),
+            ["element", name, count] => elements.push((name.to_string(), count.parse().map_err(|_| format!("Invalid element count '{}'.", count))?, Vec::new())),
+            ["property", "list", count_type, item_type, name] => {
+                let property = PlyProperty::List(name.to_string(), ScalarType::from_name(count_type)?, ScalarType::from_name(item_type)?);
+                elements.last_mut().ok_or_else(|| String::from("Property before element."))?.2.push(property);
+            },
+            ["property", scalar_type, name] => {
+                let property = PlyProperty::Scalar(name.to_string(), ScalarType::from_name(scalar_type)?);
+                elements.last_mut().ok_or_else(|| String::from("Property before element."))?.2.push(property);
+            },
+            _ => {},  // Comments and obj_info lines.
+        }
+    }
+
+    let mut vertices : Vec<f32> = Vec::new();
+    let mut faces : Vec<i32> = Vec::new();
+    for (element, count, properties) in elements.iter() {
+        for _ in 0..*count {
+            let mut coords = [0.0f32; 3];
+            for property in properties.iter() {
+                match property {
+                    PlyProperty::Scalar(name, scalar_type) => {
+                        let value = cursor.next_value(*scalar_type, binary)?;
+                        if element == "vertex" {
+                            match name.as_str() {
+                                "x" => coords[0] = value as f32,
+                                "y" => coords[1] = value as f32,
+                                "z" => coords[2] = value as f32,
+                                _ => {},
+                            }
+                        }
+                    },
+                    PlyProperty::List(name, count_type, item_type) => {
+                        let num_items = cursor.next_value(*count_type, binary)? as usize;
+                        let items = (0..num_items).map(|_| cursor.next_value(*item_type, binary).map(|v| v as i32)).collect::<std::result::Result<Vec<i32>, String>>()?;
+                        if element == "face" && (name == "vertex_indices" || name == "vertex_index") {
+                            push_polygon(&mut faces, &items);
+                        }
+                    },
+                }
+            }
+            if element == "vertex" {
+                vertices.extend_from_slice(&coords);
+            }
+        }
+    }
+    Ok(BrainMesh { vertices, faces })
+}
+
+
+/// Parse the content of an OBJ file. Only vertices (`v`) and faces (`f`) are used, texture coordinates and normals of the faces are ignored.
+pub(crate) fn parse_obj(bytes : &[u8]) -> std::result::Result<BrainMesh, String> {
+    let text = std::str::from_utf8(bytes).map_err(|_| String::from("Not a text file."))?;
+    let mut vertices : Vec<f32> = Vec::new();
+    let mut faces : Vec<i32> = Vec::new();
+    for line in text.lines() {
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("v") => {
+                for _ in 0..3 {
+                    let token = tokens.next().ok_or_else(|| format!("Invalid vertex '{}'.", line))?;
+                    vertices.push(token.parse().map_err(|_| format!("Invalid vertex '{}'.", line))?);
+                }
+            },
+            Some("f") => {
+                // Vertices are given as `v`, `v/vt`, `v//vn` or `v/vt/vn`, with indices starting at 1. Negative indices count back from the last vertex.
+                let num_vertices = (vertices.len() / 3) as i32;
+                let polygon = tokens.map(|token| {
+                    let vidx : i32 = token.split('/').next().unwrap_or("").parse().map_err(|_| format!("Invalid face '{}'.", line))?;
+                    Ok(if vidx < 0 { num_vertices + vidx } else { vidx - 1 })
+                }).collect::<std::result::Result<Vec<i32>, String>>()?;
+                push_polygon(&mut faces, &polygon);
+            },
+            _ => {},
+        }
+    }
+    Ok(BrainMesh { vertices, faces })
+}
+
+
+/// Parse the content of a legacy VTK polydata file. Both the cell format of VTK versions before 5.1 (a vertex count before the vertices
+/// of each polygon) and the format with `OFFSETS` and `CONNECTIVITY` arrays are supported. Data attributes like `POINT_DATA` are ignored.
+fn parse_vtk(bytes : &[u8]) -> std::result::Result<BrainMesh, String> {
+    let mut cursor = Cursor::new(bytes);
+    if !cursor.next_line().is_some_and(|line| line.starts_with("# vtk DataFile")) {
+        return Err(String::from("Not a legacy VTK file."));
+    }
+    cursor.next_line();  // The title.
+    let binary = match cursor.next_line().map(|line| line.trim()) {
+        Some("ASCII") => None,
+        Some("BINARY") => Some(Endian::Big),
+        _ => return Err(String::from("Missing ASCII or BINARY.")),
+    };
+    let parse_count = |token : Option<&str>| -> std::result::Result<usize, String> {
+        token.and_then(|t| t.parse().ok()).ok_or_else(|| String::from("Invalid count."))
+    };
+
+    let mut vertices : Vec<f32> = Vec::new();
+    let mut faces : Vec<i32> = Vec::new();
+    while let Some(token) = cursor.next_token() {
+        match token {
+            "DATASET" => {
+                let dataset = cursor.next_token().unwrap_or("");
+                if dataset != "POLYDATA" {
+                    return Err(format!("Unsupported dataset type '{}', only POLYDATA is supported.", dataset));
+                }
+            },
+            "POINTS" => {
+                let num_points = parse_count(cursor.next_token())?;
+                let scalar_type = ScalarType::from_name(cursor.next_token().unwrap_or(""))?;
+                cursor.next_line();
+                for _ in 0..num_points * 3 {
+                    vertices.push(cursor.next_value(scalar_type, binary)? as f32);
+                }
+            },
+            "POLYGONS" | "LINES" | "VERTICES" | "TRIANGLE_STRIPS" => {
+                let is_polygons = token == "POLYGONS";
+                let num_cells = parse_count(cursor.next_token())?;
+                let size = parse_count(cursor.next_token())?;
+                cursor.next_line();
+                let mut polygons : Vec<Vec<i32>> = Vec::with_capacity(num_cells);
+                if cursor.bytes[cursor.pos..].starts_with(b"OFFSETS") {
+                    // VTK 5.1 format: `num_cells` offsets into the `size` connectivity values.
+                    cursor.next_token();
+                    let offset_type = ScalarType::from_name(cursor.next_token().unwrap_or(""))?;
+                    cursor.next_line();
+                    let offsets = (0..num_cells).map(|_| cursor.next_value(offset_type, binary).map(|v| v as usize)).collect::<std::result::Result<Vec<usize>, String>>()?;
+                    if cursor.next_token() != Some("CONNECTIVITY") {
+                        return Err(String::from("Missing CONNECTIVITY."));
+                    }
+                    let connectivity_type = ScalarType::from_name(cursor.next_token().unwrap_or(""))?;
+                    cursor.next_line();
+                    let connectivity = (0..size).map(|_| cursor.next_value(connectivity_type, binary).map(|v| v as i32)).collect::<std::result::Result<Vec<i32>, String>>()?;
+                    for window in offsets.windows(2) {
+                        polygons.push(connectivity.get(window[0]..window[1]).ok_or_else(|| String::from("Invalid offsets."))?.to_vec());
+                    }
+                } else {
+                    for _ in 0..num_cells {
+                        let num_vertices = cursor.next_value(ScalarType::I32, binary)? as usize;
+                        polygons.push((0..num_vertices).map(|_| cursor.next_value(ScalarType::I32, binary).map(|v| v as i32)).collect::<std::result::Result<Vec<i32>, String>>()?);
+                    }
+                }
+                if is_polygons {
+                    for polygon in polygons.iter() {
+                        push_polygon(&mut faces, polygon);
+                    }
+                }
+            },
+            "POINT_DATA" | "CELL_DATA" => break,
+            _ => {},
+        }
+    }
+    Ok(BrainMesh { vertices, faces })
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The vertices of a unit square in the z = 0 plane.
+    const SQUARE_VERTICES : [f32; 12] = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0];
+
+    /// The faces of the unit square, after splitting the quad `0 1 2 3` into triangles.
+    const SQUARE_FACES : [i32; 6] = [0, 1, 2, 0, 2, 3];
+
+    #[test]
+    fn an_ascii_ply_file_is_parsed() {
+        let ply = "ply\nformat ascii 1.0\ncomment a unit square\nelement vertex 4\nproperty float x\nproperty float y\nproperty float z\nproperty uchar red\n\
+            element face 1\nproperty list uchar int vertex_indices\nend_header\n0 0 0 255\n1 0 0 255\n1 1 0 255\n0 1 0 255\n4 0 1 2 3\n";
+        let mesh = parse_mesh(ply.as_bytes(), parse_ply).unwrap();
+        assert_eq!(SQUARE_VERTICES.to_vec(), mesh.vertices);
+        assert_eq!(SQUARE_FACES.to_vec(), mesh.faces);
+    }
+
+    #[test]
+    fn a_binary_little_endian_ply_file_is_parsed() {
+        let mut ply : Vec<u8> = b"ply\nformat binary_little_endian 1.0\nelement vertex 4\nproperty double x\nproperty double y\nproperty double z\n\
+            element face 2\nproperty list uchar uint vertex_index\nend_header\n".to_vec();
+        for coord in SQUARE_VERTICES.iter() {
+            ply.extend_from_slice(&(*coord as f64).to_le_bytes());
+        }
+        for face in SQUARE_FACES.chunks(3) {
+            ply.push(3);
+            for vidx in face.iter() {
+                ply.extend_from_slice(&(*vidx as u32).to_le_bytes());
+            }
+        }
+        let mesh = parse_mesh(&ply, parse_ply).unwrap();
+        assert_eq!(SQUARE_VERTICES.to_vec(), mesh.vertices);
+        assert_eq!(SQUARE_FACES.to_vec(), mesh.faces);
+    }
+
+    #[test]
+    fn an_obj_file_with_texture_and_normal_indices_is_parsed() {
+        let obj = "# a unit square\nv 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nvn 0 0 1\nf 1/1/1 2/1/1 3/1/1\nf 1//1 3//1 4//1\n";
+        let mesh = parse_mesh(obj.as_bytes(), parse_obj).unwrap();
+        assert_eq!(SQUARE_VERTICES.to_vec(), mesh.vertices);
+        assert_eq!(SQUARE_FACES.to_vec(), mesh.faces);
+    }
+
+    #[test]
+    fn negative_obj_indices_count_back_from_the_last_vertex() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf -4 -3 -2 -1\n";
+        let mesh = parse_mesh(obj.as_bytes(), parse_obj).unwrap();
+        assert_eq!(SQUARE_FACES.to_vec(), mesh.faces);
+    }
+
+    #[test]
+    fn a_legacy_vtk_file_is_parsed() {
+        let vtk = "# vtk DataFile Version 3.0\na unit square\nASCII\nDATASET POLYDATA\nPOINTS 4 float\n0 0 0 1 0 0 1 1 0\n0 1 0\n\
+            POLYGONS 1 5\n4 0 1 2 3\nPOINT_DATA 4\nSCALARS thickness float 1\nLOOKUP_TABLE default\n1 2 3 4\n";
+        let mesh = parse_mesh(vtk.as_bytes(), parse_vtk).unwrap();
+        assert_eq!(SQUARE_VERTICES.to_vec(), mesh.vertices);
+        assert_eq!(SQUARE_FACES.to_vec(), mesh.faces);
+    }
+
+    #[test]
+    fn a_vtk_5_1_file_with_offsets_and_connectivity_is_parsed() {
+        let vtk = "# vtk DataFile Version 5.1\na unit square\nASCII\nDATASET POLYDATA\nPOINTS 4 float\n0 0 0 1 0 0 1 1 0 0 1 0\n\
+            LINES 2 2\nOFFSETS vtktypeint64\n0 2\nCONNECTIVITY vtktypeint64\n0 1\n\
+            POLYGONS 3 6\nOFFSETS vtktypeint64\n0 3 6\nCONNECTIVITY vtktypeint64\n0 1 2 0 2 3\n";
+        let mesh = parse_mesh(vtk.as_bytes(), parse_vtk).unwrap();
+        assert_eq!(SQUARE_VERTICES.to_vec(), mesh.vertices);
+        assert_eq!(SQUARE_FACES.to_vec(), mesh.faces);
+    }
+
+    #[test]
+    fn faces_with_out_of_range_vertex_indices_are_rejected() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 1 1 0\nf 1 2 4\n";
+        assert!(parse_mesh(obj.as_bytes(), parse_obj).unwrap_err().contains("vertex 3"));
+        let obj = "v 0 0 0\nv 1 0 0\nv 1 1 0\nf -4 1 2\n";
+        assert!(parse_mesh(obj.as_bytes(), parse_obj).is_err());
+        let ply = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\n\
+            element face 1\nproperty list uchar int vertex_indices\nend_header\n0 0 0\n1 0 0\n1 1 0\n3 0 1 3\n";
+        assert!(parse_mesh(ply.as_bytes(), parse_ply).is_err());
+    }
+
+    #[test]
+    fn files_in_other_formats_are_rejected() {
+        assert!(parse_mesh(b"solid square\nendsolid\n", parse_ply).is_err());
+        assert!(parse_mesh(b"ply\nformat ascii 1.0\nelement vertex 1\n", parse_ply).is_err());
+        assert!(parse_mesh(b"# vtk DataFile Version 3.0\ngrid\nASCII\nDATASET STRUCTURED_POINTS\n", parse_vtk).is_err());
+    }
+}
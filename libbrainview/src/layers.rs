@@ -0,0 +1,246 @@
+//! Stacks of per-vertex color layers that are composited into the final vertex colors of a mesh.
+//!
+//! This allows to show several overlays on one mesh, e.g., a label on top of a thickness map on top of a curvature background.
+
+use neuroformats::{FsAnnot, FsLabel};
+
+use crate::color::{ColorMapping, color_from_data, curv_background_colors};
+use crate::outline::{annot_outline_colors};
+use crate::topology::{MeshTopology};
+
+
+/// Determines how the colors of a layer are combined with the colors of the layers below it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendMode {
+    /// The layer color replaces the color below it.
+    Normal,
+    /// The colors are multiplied, which darkens. Useful to add shading, like a curvature pattern, to a colored layer below.
+    Multiply,
+    /// The inverted colors are multiplied and inverted again, which lightens.
+    Screen,
+    /// The colors are added.
+    Add,
+}
+
+
+impl BlendMode {
+
+    /// Blend a source color channel value onto a destination channel value, both in range `0..1`.
+    fn blend(&self, dst : f32, src : f32) -> f32 {
+        match self {
+            BlendMode::Normal => src,
+            BlendMode::Multiply => dst * src,
+            BlendMode::Screen => 1.0 - (1.0 - dst) * (1.0 - src),
+            BlendMode::Add => (dst + src).min(1.0),
+        }
+    }
+}
+
+
+/// A single layer of per-vertex colors.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorLayer {
+    pub name : String,
+    /// The RGBA colors, 4 values per vertex. The alpha channel is the per-vertex opacity, vertices with alpha 0 do not cover the layers below.
+    pub colors : Vec<u8>,
+    /// The opacity of the whole layer, in range `0..1`. It is multiplied with the per-vertex alpha values.
+    pub opacity : f32,
+    pub blend_mode : BlendMode,
+    pub visible : bool,
+}
+
+
+impl ColorLayer {
+
+    /// Create a fully opaque and visible layer with normal blending from RGBA vertex colors.
+    pub fn new(name : &str, colors : Vec<u8>) -> Self {
+        ColorLayer { name: String::from(name), colors, opacity: 1.0, blend_mode: BlendMode::Normal, visible: true }
+    }
+
+
+    /// Create a layer from per-vertex data, mapped to colors as described by the mapping.
+    pub fn from_data(name : &str, data : Vec<f32>, mapping : &ColorMapping) -> Self {
+        ColorLayer::new(name, color_from_data(data, mapping))
+    }
+
+
+    /// Create a grayscale background layer from curvature data like `?h.curv` or `?h.sulc`, see `curv_background_colors`.
+    pub fn from_curv(name : &str, curv : &[f32]) -> Self {
+        ColorLayer::new(name, curv_background_colors(curv))
+    }
+
+
+    /// Create a layer from a label: vertices in the label get the given color, all other vertices are transparent.
+    pub fn from_label(name : &str, label : &FsLabel, num_surface_verts : usize, color : [u8; 4]) -> Self {
+        let mut colors : Vec<u8> = vec![0; num_surface_verts * 4];
+        for (idx, in_label) in label.is_surface_vertex_in_label(num_surface_verts).iter().enumerate() {
+            if *in_label {
+                colors[idx * 4..idx * 4 + 4].copy_from_slice(&color);
+            }
+        }
+        ColorLayer::new(name, colors)
+    }
+
+
+    /// Create a layer showing the outlines of the regions of a parcellation, see `annot_outline_colors`. The vertices inside the regions are transparent.
+    pub fn from_annot_outline(name : &str, topology : &MeshTopology, annot : &FsAnnot, width : usize, color : Option<[u8; 4]>) -> Self {
+        ColorLayer::new(name, annot_outline_colors(topology, annot, width, color))
+    }
+
+
+    /// Set the opacity of the layer, see the `opacity` field.
+    pub fn with_opacity(self, opacity : f32) -> Self {
+        ColorLayer { opacity, ..self }
+    }
+
+
+    /// Set the blend mode of the layer.
+    pub fn with_blend_mode(self, blend_mode : BlendMode) -> Self {
+        ColorLayer { blend_mode, ..self }
+    }
+}
+
+
+/// A stack of color layers. The first layer is at the bottom.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LayerStack {
+    pub layers : Vec<ColorLayer>,
+}
+
+
+impl LayerStack {
+
+    /// Create an empty layer stack.
+    pub fn new() -> Self {
+        LayerStack { layers: Vec::new() }
+    }
+
+
+    /// Add a layer on top of the stack.
+    pub fn push(&mut self, layer : ColorLayer) {
+        self.layers.push(layer);
+    }
+
+
+    /// Whether the stack contains no layers.
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+
+    /// The number of layers in the stack.
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+
+    /// Toggle the visibility of the layer at the given index. Returns the new visibility, or `None` if there is no such layer.
+    pub fn toggle(&mut self, layer_idx : usize) -> Option<bool> {
+        let layer = self.layers.get_mut(layer_idx)?;
+        layer.visible = !layer.visible;
+        Some(layer.visible)
+    }
+
+
+    /// Composite the visible layers into a single set of opaque RGBA vertex colors, 4 values per vertex.
+    ///
+    /// Vertices which are not covered by any layer get the `base_color`. Layers with less than `num_vertices` colors only cover the
+    /// vertices they have colors for.
+    pub fn composite(&self, num_vertices : usize, base_color : [u8; 4]) -> Vec<u8> {
+        let base = [base_color[0] as f32 / 255.0, base_color[1] as f32 / 255.0, base_color[2] as f32 / 255.0];
+        let mut rgb : Vec<[f32; 3]> = vec![base; num_vertices];
+        for layer in self.layers.iter().filter(|l| l.visible && l.opacity > 0.0) {
+            for (idx, dst) in rgb.iter_mut().enumerate().take(layer.colors.len() / 4) {
+                let src = &layer.colors[idx * 4..idx * 4 + 4];
+                let alpha = (src[3] as f32 / 255.0) * layer.opacity;
+                if alpha <= 0.0 {
+                    continue;
+                }
+                for c in 0..3 {
+                    let blended = layer.blend_mode.blend(dst[c], src[c] as f32 / 255.0);
+                    dst[c] = dst[c] * (1.0 - alpha) + blended * alpha;
+                }
+            }
+        }
+
+        let mut colors : Vec<u8> = Vec::with_capacity(num_vertices * 4);
+        for col in rgb.iter() {
+            for c in col.iter() {
+                colors.push((c.clamp(0.0, 1.0) * 255.0).round() as u8);
+            }
+            colors.push(255);
+        }
+        colors
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const WHITE : [u8; 4] = [255, 255, 255, 255];
+
+    /// A layer with the same color for all of the given number of vertices.
+    fn uniform_layer(name : &str, color : [u8; 4], num_vertices : usize) -> ColorLayer {
+        ColorLayer::new(name, color.repeat(num_vertices))
+    }
+
+    /// A stack with a gray bottom layer for 2 vertices and the given layer on top.
+    fn gray_and(layer : ColorLayer) -> LayerStack {
+        let mut stack = LayerStack::new();
+        stack.push(uniform_layer("gray", [128, 128, 128, 255], 2));
+        stack.push(layer);
+        stack
+    }
+
+    #[test]
+    fn an_empty_stack_has_the_base_color() {
+        assert_eq!([10, 20, 30, 255].repeat(3), LayerStack::new().composite(3, [10, 20, 30, 255]));
+    }
+
+    #[test]
+    fn opaque_layers_cover_the_layers_below() {
+        let stack = gray_and(uniform_layer("red", [255, 0, 0, 255], 2));
+        assert_eq!([255, 0, 0, 255].repeat(2), stack.composite(2, WHITE));
+    }
+
+    #[test]
+    fn transparent_vertices_and_missing_colors_do_not_cover_the_layers_below() {
+        let mut stack = LayerStack::new();
+        stack.push(uniform_layer("red", [255, 0, 0, 255], 2));
+        stack.push(ColorLayer::new("label", vec![0, 0, 255, 0, 0, 0, 255, 255]));
+        stack.push(ColorLayer::new("short", vec![0, 255, 0, 255]));
+        // The label only covers vertex 1, the short layer only vertex 0.
+        assert_eq!(vec![0, 255, 0, 255, 0, 0, 255, 255], stack.composite(2, WHITE));
+        // Vertices beyond all layers get the base color.
+        assert_eq!(vec![0, 0, 0, 255], stack.composite(3, [0, 0, 0, 255])[8..].to_vec());
+    }
+
+    #[test]
+    fn the_layer_opacity_mixes_the_colors() {
+        let stack = gray_and(uniform_layer("white", WHITE, 2).with_opacity(0.5));
+        assert_eq!([192, 192, 192, 255].repeat(2), stack.composite(2, WHITE));
+        let stack = gray_and(uniform_layer("white", WHITE, 2).with_opacity(0.0));
+        assert_eq!([128, 128, 128, 255].repeat(2), stack.composite(2, WHITE));
+    }
+
+    #[test]
+    fn the_blend_modes_combine_the_colors() {
+        let layer = uniform_layer("layer", [128, 255, 0, 255], 2);
+        assert_eq!([64, 128, 0, 255].repeat(2), gray_and(layer.clone().with_blend_mode(BlendMode::Multiply)).composite(2, WHITE));
+        assert_eq!([192, 255, 128, 255].repeat(2), gray_and(layer.clone().with_blend_mode(BlendMode::Screen)).composite(2, WHITE));
+        assert_eq!([255, 255, 128, 255].repeat(2), gray_and(layer.with_blend_mode(BlendMode::Add)).composite(2, WHITE));
+    }
+
+    #[test]
+    fn hidden_layers_are_not_composited() {
+        let mut stack = gray_and(uniform_layer("red", [255, 0, 0, 255], 2));
+        assert_eq!(Some(false), stack.toggle(1));
+        assert_eq!([128, 128, 128, 255].repeat(2), stack.composite(2, WHITE));
+        assert_eq!(Some(true), stack.toggle(1));
+        assert_eq!([255, 0, 0, 255].repeat(2), stack.composite(2, WHITE));
+        assert_eq!(None, stack.toggle(2));
+        assert_eq!(2, stack.len());
+    }
+}
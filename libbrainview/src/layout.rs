@@ -0,0 +1,211 @@
+//! Arranging the meshes of both hemispheres in a scene.
+//!
+//! Meshes are shown at their native coordinates by default. This works for the white or pial surfaces, but inflated and spherical
+//! surfaces of the two hemispheres overlap, so they have to be moved apart to show both of them.
+
+use std::str::FromStr;
+
+use crate::brainmesh::{ColoredBrainMesh};
+use crate::hemisphere::{Hemisphere};
+
+
+/// The default gap between the hemispheres in a layout, in the units of the surface coordinates, i.e., mm for FreeSurfer surfaces.
+pub const DEFAULT_HEMISPHERE_GAP : f32 = 10.0;
+
+
+/// Determines where the meshes of the two hemispheres are placed in a scene, see `apply_hemisphere_layout`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HemisphereLayout {
+    /// Show the meshes at their native coordinates.
+    Native,
+    /// Move the hemispheres apart along the x axis, so that there is at least the given gap between them. Hemispheres which are already
+    /// at least the gap apart are not moved. Note that the white and pial surfaces of the two hemispheres are usually only a few mm apart,
+    /// so they are moved as well, and only their gap is kept unchanged if it is larger than the given one.
+    Separated(f32),
+    /// Show the lateral sides of both hemispheres next to each other in the lateral view of the left hemisphere. The right hemisphere is rotated
+    /// by 180 degrees around the z axis, so its lateral side faces -x like the one of the left hemisphere, and placed posterior of the left hemisphere
+    /// with the given gap, i.e., to its right in the view. The medial view of the left hemisphere shows both medial sides in the same way.
+    SideBySide(f32),
+}
+
+
+impl Default for HemisphereLayout {
+
+    /// The default layout: the native coordinates.
+    fn default() -> Self {
+        HemisphereLayout::Native
+    }
+}
+
+
+impl FromStr for HemisphereLayout {
+    type Err = String;
+
+    /// Parse a layout from strings like `native`, `separated`, `separated:GAP`, `side-by-side` or `side-by-side:GAP`.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let parts : Vec<&str> = s.split(':').collect();
+        let mut values : Vec<f32> = Vec::with_capacity(parts.len() - 1);
+        for p in parts[1..].iter() {
+            values.push(p.parse::<f32>().map_err(|_| format!("Invalid number '{}' in hemisphere layout '{}'.", p, s))?);
+        }
+        match (parts[0], values.len()) {
+            ("native", 0) => Ok(HemisphereLayout::Native),
+            ("separated", 0) => Ok(HemisphereLayout::Separated(DEFAULT_HEMISPHERE_GAP)),
+            ("separated", 1) => Ok(HemisphereLayout::Separated(values[0])),
+            ("side-by-side", 0) => Ok(HemisphereLayout::SideBySide(DEFAULT_HEMISPHERE_GAP)),
+            ("side-by-side", 1) => Ok(HemisphereLayout::SideBySide(values[0])),
+            _ => Err(format!("Invalid hemisphere layout '{}', must be one of native, separated, separated:GAP, side-by-side or side-by-side:GAP.", s)),
+        }
+    }
+}
+
+
+/// The hemisphere of a mesh: its `hemisphere` tag if set, otherwise detected from the side of the x axis its center is on.
+///
+/// Returns `None` for untagged meshes centered at x = 0, like spherical surfaces, and for empty meshes.
+pub fn mesh_hemisphere(cb_mesh : &ColoredBrainMesh) -> Option<Hemisphere> {
+    if cb_mesh.hemisphere.is_some() {
+        return cb_mesh.hemisphere;
+    }
+    let (min, max) = coords_minmax(&[cb_mesh])?;
+    let center_x = (min[0] + max[0]) / 2.0;
+    if center_x < 0.0 {
+        Some(Hemisphere::Lh)
+    } else if center_x > 0.0 {
+        Some(Hemisphere::Rh)
+    } else {
+        None
+    }
+}
+
+
+/// Move the meshes of the hemispheres according to the layout, see `HemisphereLayout`. The hemisphere of each mesh is determined with `mesh_hemisphere`.
+///
+/// All meshes of a hemisphere are moved together, so several meshes of the same hemisphere, like a surface and a label mesh, keep their positions
+/// relative to each other. Nothing is moved unless there are meshes of both hemispheres. Note that this changes the vertex coordinates of the meshes.
+pub fn apply_hemisphere_layout(cb_meshes : &mut [ColoredBrainMesh], layout : HemisphereLayout) {
+    let hemis : Vec<Option<Hemisphere>> = cb_meshes.iter().map(mesh_hemisphere).collect();
+    let (lh_minmax, rh_minmax) = {
+        let meshes_of = |hemi : Hemisphere| -> Vec<&ColoredBrainMesh> { cb_meshes.iter().zip(hemis.iter()).filter(|(_, h)| **h == Some(hemi)).map(|(m, _)| m).collect() };
+        (coords_minmax(&meshes_of(Hemisphere::Lh)), coords_minmax(&meshes_of(Hemisphere::Rh)))
+    };
+    let ((lh_min, lh_max), (rh_min, rh_max)) = match (lh_minmax, rh_minmax) {
+        (Some(lh), Some(rh)) => (lh, rh),
+        _ => return,
+    };
+
+    match layout {
+        HemisphereLayout::Native => {},
+        HemisphereLayout::Separated(gap) => {
+            // Move both hemispheres away from each other by the same distance, if they are closer than the gap.
+            let overlap = (lh_max[0] + gap - rh_min[0]).max(0.0);
+            for (cb_mesh, hemi) in cb_meshes.iter_mut().zip(hemis.iter()) {
+                match hemi {
+                    Some(Hemisphere::Lh) => transform_vertices(cb_mesh, |v| [v[0] - overlap / 2.0, v[1], v[2]]),
+                    Some(Hemisphere::Rh) => transform_vertices(cb_mesh, |v| [v[0] + overlap / 2.0, v[1], v[2]]),
+                    None => {},
+                }
+            }
+        },
+        HemisphereLayout::SideBySide(gap) => {
+            // Rotating by 180 degrees around the z axis through the center of the right hemisphere mirrors x and y around that center. Then
+            // align the lateral side with the one of the left hemisphere, and move it posterior of the left hemisphere.
+            let rh_center = [(rh_min[0] + rh_max[0]) / 2.0, (rh_min[1] + rh_max[1]) / 2.0];
+            let rh_lateral_x = 2.0 * rh_center[0] - rh_max[0];
+            let rh_rotated_max_y = 2.0 * rh_center[1] - rh_min[1];
+            let dx = lh_min[0] - rh_lateral_x;
+            let dy = lh_min[1] - gap - rh_rotated_max_y;
+            for (cb_mesh, hemi) in cb_meshes.iter_mut().zip(hemis.iter()) {
+                if *hemi == Some(Hemisphere::Rh) {
+                    transform_vertices(cb_mesh, |v| [2.0 * rh_center[0] - v[0] + dx, 2.0 * rh_center[1] - v[1] + dy, v[2]]);
+                }
+            }
+        },
+    }
+}
+
+
+/// Apply a transformation to all vertex coordinates of a mesh.
+fn transform_vertices<F: Fn([f32; 3]) -> [f32; 3]>(cb_mesh : &mut ColoredBrainMesh, transform : F) {
+    for v in cb_mesh.mesh.vertices.chunks_mut(3) {
+        let t = transform([v[0], v[1], v[2]]);
+        v.copy_from_slice(&t);
+    }
+}
+
+
+/// The minimal and maximal vertex coordinates over several meshes, or `None` if they have no vertices.
+fn coords_minmax(cb_meshes : &[&ColoredBrainMesh]) -> Option<([f32; 3], [f32; 3])> {
+    let mut coords = cb_meshes.iter().flat_map(|cb_mesh| cb_mesh.mesh.vertices.chunks(3)).peekable();
+    coords.peek()?;
+    let mut min = [f32::INFINITY; 3];
+    let mut max = [f32::NEG_INFINITY; 3];
+    for v in coords {
+        for d in 0..3 {
+            min[d] = min[d].min(v[d]);
+            max[d] = max[d].max(v[d]);
+        }
+    }
+    Some((min, max))
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use neuroformats::BrainMesh;
+
+    /// A mesh with a single triangle spanning the x range `min_x..max_x`.
+    fn triangle_mesh(min_x : f32, max_x : f32) -> ColoredBrainMesh {
+        let b_mesh = BrainMesh { vertices: vec![min_x, 0.0, 0.0, max_x, 0.0, 0.0, min_x, 1.0, 0.0], faces: vec![0, 1, 2] };
+        ColoredBrainMesh::from_brainmesh_and_colors(&b_mesh, vec![255; 12]).unwrap()
+    }
+
+    fn x_range(cb_mesh : &ColoredBrainMesh) -> (f32, f32) {
+        let (min, max) = coords_minmax(&[cb_mesh]).unwrap();
+        (min[0], max[0])
+    }
+
+    #[test]
+    fn layouts_are_parsed_from_strings() {
+        assert_eq!(Ok(HemisphereLayout::Native), "native".parse::<HemisphereLayout>());
+        assert_eq!(Ok(HemisphereLayout::Separated(DEFAULT_HEMISPHERE_GAP)), "separated".parse::<HemisphereLayout>());
+        assert_eq!(Ok(HemisphereLayout::SideBySide(20.0)), "side-by-side:20".parse::<HemisphereLayout>());
+        assert!("separated:wide".parse::<HemisphereLayout>().is_err());
+        assert!("stacked".parse::<HemisphereLayout>().is_err());
+    }
+
+    #[test]
+    fn hemispheres_closer_than_the_gap_are_moved_apart() {
+        let mut cb_meshes = vec![triangle_mesh(-50.0, -1.0), triangle_mesh(1.0, 50.0)];
+        apply_hemisphere_layout(&mut cb_meshes, HemisphereLayout::Separated(10.0));
+        assert_eq!((-54.0, -5.0), x_range(&cb_meshes[0]));
+        assert_eq!((5.0, 54.0), x_range(&cb_meshes[1]));
+    }
+
+    #[test]
+    fn hemispheres_further_apart_than_the_gap_are_not_moved() {
+        let mut cb_meshes = vec![triangle_mesh(-50.0, -10.0), triangle_mesh(10.0, 50.0)];
+        apply_hemisphere_layout(&mut cb_meshes, HemisphereLayout::Separated(10.0));
+        assert_eq!((-50.0, -10.0), x_range(&cb_meshes[0]));
+        assert_eq!((10.0, 50.0), x_range(&cb_meshes[1]));
+    }
+
+    #[test]
+    fn side_by_side_rotates_the_right_hemisphere_and_places_it_posterior() {
+        let mut cb_meshes = vec![triangle_mesh(-50.0, -1.0), triangle_mesh(1.0, 50.0)];
+        let lh_vertices = cb_meshes[0].mesh.vertices.clone();
+        apply_hemisphere_layout(&mut cb_meshes, HemisphereLayout::SideBySide(10.0));
+        assert_eq!(lh_vertices, cb_meshes[0].mesh.vertices);
+        // Rotated by 180 degrees: x and y are mirrored, so the lateral vertex at x = 50 is now at the lateral side x = -50 of the left hemisphere,
+        // and the vertex at y = 1 is now below the others. The maximal y is the gap of 10 below the minimal y of the left hemisphere.
+        assert_eq!(vec![-1.0, -10.0, 0.0, -50.0, -10.0, 0.0, -1.0, -11.0, 0.0], cb_meshes[1].mesh.vertices);
+    }
+
+    #[test]
+    fn a_single_hemisphere_is_not_moved() {
+        let mut cb_meshes = vec![triangle_mesh(1.0, 50.0)];
+        apply_hemisphere_layout(&mut cb_meshes, HemisphereLayout::SideBySide(10.0));
+        assert_eq!((1.0, 50.0), x_range(&cb_meshes[0]));
+    }
+}
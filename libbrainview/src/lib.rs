@@ -7,15 +7,50 @@ pub mod threed_adapter;
 pub mod scene;
 pub mod fs_display;
 pub mod vertexcolor;
+pub mod hemisphere;
+pub mod geometry;
+pub mod offscreen;
+pub mod font;
+pub mod figure;
+pub mod layers;
+pub mod colorbar;
+pub mod outline;
+pub mod topology;
+pub mod lighting;
+pub mod smoothing;
+pub mod picking;
+pub mod subject;
+pub mod vertexdata;
+pub mod frames;
+pub mod export;
+pub mod gifti;
+pub mod import;
+pub mod cifti;
+pub mod layout;
 
-pub use color::{color_from_data};
-pub use util::{f32tou32, scale_to_01};
+pub use color::{color_from_data, Colormap, ColorMapping, ColorRange, ColorScale, OutOfRange, Threshold};
+pub use util::{f32tou32, fit_colors_to_vertex_count, fit_to_vertex_count, rgb_to_opaque_rgba, scale_to_01, scale_to_range};
 pub use brainmesh::{ColoredBrainMesh};
-pub use threed_adapter::{mesh_from_colored_brain_mesh, brain_mesh_aabb};
-pub use scene::{scene, SceneSettings};
+pub use threed_adapter::{mesh_from_colored_brain_mesh, brain_mesh_aabb, GpuBrainMesh};
+pub use scene::{scene, SceneSettings, CameraSpec, ViewPreset};
 pub use fs_display::{FsAnnotDisplay, FsCurvDisplay, FsLabelDisplay};
 pub use vertexcolor::{VertexColor};
-
-
-
-
+pub use hemisphere::{Hemisphere};
+pub use offscreen::{render_to_image};
+pub use figure::{compose_figure, FigureLayout, FigureTile};
+pub use layers::{BlendMode, ColorLayer, LayerStack};
+pub use colorbar::{draw_colorbar, Colorbar, ColorbarOrientation};
+pub use outline::{annot_outline_colors, region_boundary_vertices};
+pub use topology::{MeshTopology};
+pub use lighting::{Lighting};
+pub use geometry::{vertex_normals};
+pub use smoothing::{smooth_fwhm, smooth_nn};
+pub use picking::{pick_vertex, ray_triangle_intersection, PickResult};
+pub use subject::{Measure, Space, Subject, SubjectsDir};
+pub use vertexdata::{is_gifti_file, is_mgh_file, read_mgh_frame, read_mgh_frames, read_vertex_data, read_vertex_data_array};
+pub use frames::{FrameData};
+pub use export::{export_mesh, face_colors, write_glb, write_obj, write_ply, write_stl, MeshFormat};
+pub use gifti::{read_gifti, read_gifti_annot, read_gifti_data, read_gifti_data_arrays, read_gifti_surface, GiftiArray, GiftiData, GiftiDataArray, GiftiImage, GiftiLabel};
+pub use import::{read_mesh, read_obj, read_ply, read_vtk};
+pub use cifti::{read_cifti, CiftiBrainModel, CiftiImage, CiftiMap, STRUCTURE_CORTEX_LEFT, STRUCTURE_CORTEX_RIGHT};
+pub use layout::{apply_hemisphere_layout, mesh_hemisphere, HemisphereLayout, DEFAULT_HEMISPHERE_GAP};
@@ -0,0 +1,112 @@
+//! Lighting of the meshes with an ambient and a directional light, using the Phong reflection model.
+//!
+//! Lighting makes the gyri and sulci of the cortex much easier to read than flat vertex colors. The same model is
+//! used by the interactive `scene()`, on the GPU, and by the offscreen renderer, on the CPU.
+
+use crate::geometry::{dot3, normalize3, scale3, sub3};
+use crate::scene::{CameraSpec};
+
+
+/// The fragment shader for lit meshes with per-vertex colors, used with a three-d `MeshProgram`.
+pub(crate) const LIT_VERTEX_COLOR_SHADER : &str = include_str!("shaders/lit_vertex_color.frag");
+
+
+/// Settings for the lighting of the meshes. The intensities are factors applied to the vertex colors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lighting {
+    pub ambient : f32,
+    pub diffuse : f32,
+    pub specular : f32,
+    /// The shininess of the surface: higher values result in smaller highlights.
+    pub specular_power : f32,
+    /// The direction the directional light shines in, in world coordinates. If `None`, the light shines from the camera
+    /// towards its target, i.e., it moves with the camera like a headlight.
+    pub direction : Option<[f32; 3]>,
+}
+
+
+impl Default for Lighting {
+
+    /// A headlight with moderate ambient light and a slight highlight.
+    fn default() -> Self {
+        Lighting {
+            ambient : 0.35,
+            diffuse : 0.65,
+            specular : 0.15,
+            specular_power : 20.0,
+            direction : None,
+        }
+    }
+}
+
+
+impl Lighting {
+
+    /// No shading: the meshes are shown with their flat vertex colors.
+    pub fn flat() -> Self {
+        Lighting { ambient: 1.0, diffuse: 0.0, specular: 0.0, ..Default::default() }
+    }
+
+
+    /// The normalized direction the light shines in, for a camera looking from `eye` at `target`.
+    pub fn light_direction(&self, eye : &[f32; 3], target : &[f32; 3]) -> [f32; 3] {
+        normalize3(&self.direction.unwrap_or_else(|| sub3(target, eye)))
+    }
+
+
+    /// Compute the lit color of a surface point with the given RGBA color (in range `0..1`), normal and position, seen by the camera.
+    ///
+    /// Both sides of the surface are lit: a normal pointing away from the camera is flipped. The alpha value is not changed.
+    pub fn shade(&self, color : &[f32; 4], normal : &[f32; 3], position : &[f32; 3], camera : &CameraSpec) -> [f32; 4] {
+        let to_eye = normalize3(&sub3(&camera.position, position));
+        let normal = normalize3(normal);
+        let normal = if dot3(&normal, &to_eye) < 0.0 { scale3(&normal, -1.0) } else { normal };
+        let to_light = scale3(&self.light_direction(&camera.position, &camera.target), -1.0);
+        let diffuse_factor = dot3(&normal, &to_light).max(0.0);
+        let specular_factor = if diffuse_factor > 0.0 {
+            let reflected = sub3(&scale3(&normal, 2.0 * dot3(&normal, &to_light)), &to_light);
+            dot3(&reflected, &to_eye).max(0.0).powf(self.specular_power)
+        } else {
+            0.0
+        };
+        let intensity = self.ambient + self.diffuse * diffuse_factor;
+        let highlight = self.specular * specular_factor;
+        [
+            (color[0] * intensity + highlight).min(1.0),
+            (color[1] * intensity + highlight).min(1.0),
+            (color[2] * intensity + highlight).min(1.0),
+            color[3],
+        ]
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A camera on the z axis looking at the origin.
+    fn camera_on_z_axis() -> CameraSpec {
+        CameraSpec { position: [0.0, 0.0, 10.0], target: [0.0, 0.0, 0.0], up: [0.0, 1.0, 0.0], fov_y_degrees: 45.0, z_near: 0.1, z_far: 100.0 }
+    }
+
+    #[test]
+    fn a_surface_lit_from_behind_only_gets_ambient_light() {
+        // The light shines along +z, i.e., from behind the surface facing the camera.
+        let lighting = Lighting { direction: Some([0.0, 0.0, 1.0]), ..Default::default() };
+        let color = [0.5, 0.25, 1.0, 0.75];
+        let shaded = lighting.shade(&color, &[0.0, 0.0, 1.0], &[0.0, 0.0, 0.0], &camera_on_z_axis());
+        assert_eq!([0.5 * lighting.ambient, 0.25 * lighting.ambient, 1.0 * lighting.ambient, 0.75], shaded);
+    }
+
+    #[test]
+    fn a_headlight_lights_a_surface_facing_the_camera_fully() {
+        let lighting = Lighting { ambient: 0.2, diffuse: 0.8, specular: 0.0, specular_power: 1.0, direction: None };
+        let shaded = lighting.shade(&[0.5, 0.5, 0.5, 1.0], &[0.0, 0.0, 1.0], &[0.0, 0.0, 0.0], &camera_on_z_axis());
+        for channel in shaded[0..3].iter() {
+            assert!((channel - 0.5).abs() < 1e-6);
+        }
+        // Normals pointing away from the camera are flipped, so the back side is lit the same way.
+        assert_eq!(shaded, lighting.shade(&[0.5, 0.5, 0.5, 1.0], &[0.0, 0.0, -1.0], &[0.0, 0.0, 0.0], &camera_on_z_axis()));
+    }
+}
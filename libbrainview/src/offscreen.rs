@@ -0,0 +1,362 @@
+//! Offscreen rendering of scenes to images, without a window.
+//!
+//! This uses a small software rasterizer that draws the triangles of the meshes with per-vertex color
+//! interpolation and a z-buffer on the CPU. It is a lot slower than the interactive `scene()`, but it
+//! does not need a GPU or a display, so it can be used on headless cluster nodes and in CI.
+
+use image::{Rgba, RgbaImage};
+
+use crate::{ColoredBrainMesh, SceneSettings};
+use crate::scene::{CameraSpec};
+use crate::geometry::{cross3, dot3, normalize3, sub3, vertex_coords, vertex_normals};
+use crate::lighting::{Lighting};
+use crate::color::{rgba_f32_to_u8};
+use crate::colorbar::{draw_colorbar};
+use crate::error::{BrainviewError, Result};
+
+
+/// A vertex projected into image space. The `depth` is the distance from the camera along the view direction.
+#[derive(Debug, Clone, Copy)]
+struct ScreenVertex {
+    x : f32,
+    y : f32,
+    depth : f32,
+}
+
+
+/// A perspective projection from world coordinates into image coordinates (origin top left) for a camera.
+struct Projection {
+    eye : [f32; 3],
+    right : [f32; 3],
+    up : [f32; 3],
+    forward : [f32; 3],
+    focal_x : f32,
+    focal_y : f32,
+    width : f32,
+    height : f32,
+}
+
+
+impl Projection {
+
+    fn new(camera : &CameraSpec, width : u32, height : u32) -> Self {
+        let forward = normalize3(&sub3(&camera.target, &camera.position));
+        let right = normalize3(&cross3(&forward, &camera.up));
+        let up = cross3(&right, &forward);
+        let focal_y = 1.0 / (camera.fov_y_degrees.to_radians() / 2.0).tan();
+        let aspect = width as f32 / height as f32;
+        Projection {
+            eye : camera.position,
+            right,
+            up,
+            forward,
+            focal_x : focal_y / aspect,
+            focal_y,
+            width : width as f32,
+            height : height as f32,
+        }
+    }
+
+
+    /// The distance of a point from the camera along the view direction.
+    fn depth(&self, p : &[f32; 3]) -> f32 {
+        dot3(&sub3(p, &self.eye), &self.forward)
+    }
+
+
+    fn project(&self, p : &[f32; 3]) -> ScreenVertex {
+        let rel = sub3(p, &self.eye);
+        let depth = dot3(&rel, &self.forward);
+        let x_ndc = self.focal_x * dot3(&rel, &self.right) / depth;
+        let y_ndc = self.focal_y * dot3(&rel, &self.up) / depth;
+        ScreenVertex {
+            x : (x_ndc + 1.0) * 0.5 * self.width,
+            y : (1.0 - y_ndc) * 0.5 * self.height,
+            depth,
+        }
+    }
+}
+
+
+/// Color and depth buffers for the software rasterizer. Colors are RGBA in range `0..1`.
+struct Rasterizer {
+    width : usize,
+    height : usize,
+    color : Vec<[f32; 4]>,
+    depth : Vec<f32>,
+}
+
+
+/// The edge function: positive if `(px, py)` is left of the edge from `a` to `b`, in image coordinates.
+fn edge(a : &ScreenVertex, b : &ScreenVertex, px : f32, py : f32) -> f32 {
+    (b.x - a.x) * (py - a.y) - (b.y - a.y) * (px - a.x)
+}
+
+
+impl Rasterizer {
+
+    fn new(width : u32, height : u32) -> Self {
+        let num_pixels = (width as usize) * (height as usize);
+        Rasterizer {
+            width : width as usize,
+            height : height as usize,
+            color : vec![[0.0; 4]; num_pixels],
+            depth : vec![f32::INFINITY; num_pixels],
+        }
+    }
+
+
+    /// Draw a triangle, interpolating the vertex colors perspective-correctly and keeping the closest fragment per pixel.
+    fn draw_triangle(&mut self, v : &[ScreenVertex; 3], colors : &[[f32; 4]; 3]) {
+        let area = edge(&v[0], &v[1], v[2].x, v[2].y);
+        if area.abs() < 1e-12 {
+            return;
+        }
+        let min_x = v[0].x.min(v[1].x).min(v[2].x).floor().max(0.0) as usize;
+        let min_y = v[0].y.min(v[1].y).min(v[2].y).floor().max(0.0) as usize;
+        let max_x = v[0].x.max(v[1].x).max(v[2].x).ceil().min(self.width as f32 - 1.0);
+        let max_y = v[0].y.max(v[1].y).max(v[2].y).ceil().min(self.height as f32 - 1.0);
+        if max_x < 0.0 || max_y < 0.0 {
+            return;
+        }
+        let (max_x, max_y) = (max_x as usize, max_y as usize);
+
+        for py in min_y..=max_y {
+            for px in min_x..=max_x {
+                let (sx, sy) = (px as f32 + 0.5, py as f32 + 0.5);
+                let w0 = edge(&v[1], &v[2], sx, sy) / area;
+                let w1 = edge(&v[2], &v[0], sx, sy) / area;
+                let w2 = edge(&v[0], &v[1], sx, sy) / area;
+                if w0 < 0.0 || w1 < 0.0 || w2 < 0.0 {
+                    continue;
+                }
+
+                // Perspective-correct interpolation: interpolate 1/depth linearly in screen space.
+                let iz = [w0 / v[0].depth, w1 / v[1].depth, w2 / v[2].depth];
+                let iz_sum = iz[0] + iz[1] + iz[2];
+                let depth = 1.0 / iz_sum;
+                let idx = py * self.width + px;
+                if depth >= self.depth[idx] {
+                    continue;
+                }
+                let mut col = [0.0f32; 4];
+                for (c, channel) in col.iter_mut().enumerate() {
+                    *channel = (iz[0] * colors[0][c] + iz[1] * colors[1][c] + iz[2] * colors[2][c]) / iz_sum;
+                }
+                // Fully transparent fragments, like values outside of the color range with `OutOfRange::Transparent`, must not hide what is behind them.
+                if col[3] <= 0.0 {
+                    continue;
+                }
+                self.depth[idx] = depth;
+                self.color[idx] = col;
+            }
+        }
+    }
+
+
+    /// Compose the rendered fragments over the background color and return the final image.
+    fn into_image(self, bg_color : &[f32; 4]) -> RgbaImage {
+        let mut img = RgbaImage::new(self.width as u32, self.height as u32);
+        for (idx, col) in self.color.iter().enumerate() {
+            let px = if self.depth[idx].is_finite() {
+                let a = col[3];
+                [col[0] * a + bg_color[0] * (1.0 - a), col[1] * a + bg_color[1] * (1.0 - a), col[2] * a + bg_color[2] * (1.0 - a), 1.0]
+            } else {
+                *bg_color
+            };
+            let x = (idx % self.width) as u32;
+            let y = (idx / self.width) as u32;
+            img.put_pixel(x, y, Rgba(rgba_f32_to_u8(&px)));
+        }
+        img
+    }
+}
+
+
+/// Get the RGBA colors of all vertices, in range `0..1`. With lighting, the colors are lit per vertex and interpolated over the faces (Gouraud shading).
+fn vertex_colors(cb_mesh : &ColoredBrainMesh, lighting : Option<&Lighting>, camera : &CameraSpec) -> Vec<[f32; 4]> {
+    let colors = cb_mesh.vertex_colors.chunks(4).map(|c| [c[0] as f32 / 255.0, c[1] as f32 / 255.0, c[2] as f32 / 255.0, c[3] as f32 / 255.0]);
+    match lighting {
+        Some(lighting) => {
+            let normals = vertex_normals(&cb_mesh.mesh);
+            colors.enumerate().map(|(idx, color)| lighting.shade(&color, &vertex_coords(&normals, idx), &vertex_coords(&cb_mesh.mesh.vertices, idx), camera)).collect()
+        },
+        None => colors.collect(),
+    }
+}
+
+
+/// A corner of a triangle before projection: its world coordinates and its color.
+type Corner = ([f32; 3], [f32; 4]);
+
+
+/// Clip a triangle against the near plane of the camera, keeping the part in front of it, so triangles reaching behind the camera are
+/// still drawn. Returns the corners of the clipped polygon: 3 or 4 corners, or none if the triangle is completely behind the near plane.
+fn clip_near(corners : &[Corner; 3], projection : &Projection, z_near : f32) -> Vec<Corner> {
+    let mut polygon : Vec<Corner> = Vec::with_capacity(4);
+    for i in 0..3 {
+        let (a, b) = (&corners[i], &corners[(i + 1) % 3]);
+        let (depth_a, depth_b) = (projection.depth(&a.0), projection.depth(&b.0));
+        if depth_a > z_near {
+            polygon.push(*a);
+        }
+        // Add the point where the edge crosses the near plane.
+        if (depth_a > z_near) != (depth_b > z_near) {
+            let t = (depth_a - z_near) / (depth_a - depth_b);
+            let mut point = *a;
+            for d in 0..3 {
+                point.0[d] += t * (b.0[d] - a.0[d]);
+            }
+            for c in 0..4 {
+                point.1[c] += t * (b.1[c] - a.1[c]);
+            }
+            polygon.push(point);
+        }
+    }
+    polygon
+}
+
+
+/// Render the given meshes into an image, without opening a window.
+///
+/// The image size, background color, lighting and colorbar are taken from the `SceneSettings`. This uses a software rasterizer
+/// and works without a GPU. Triangles reaching behind the camera are clipped at the near plane, fully transparent parts of the
+/// meshes are not drawn. Use `CameraSpec::for_meshes` to get the same camera the interactive `scene()` starts with.
+///
+/// Returns an `EmptyScene` error if there are no meshes, and a `ColorCountMismatch` error if a mesh does not have 4 color values per vertex.
+pub fn render_to_image(meshes : &Vec<ColoredBrainMesh>, scenesettings : &SceneSettings, camera : &CameraSpec) -> Result<RgbaImage> {
+    if meshes.is_empty() {
+        return Err(BrainviewError::EmptyScene);
+    }
+    if let Some(cb_mesh) = meshes.iter().find(|cb_mesh| cb_mesh.vertex_colors.len() != cb_mesh.mesh.num_vertices() * 4) {
+        return Err(BrainviewError::ColorCountMismatch(cb_mesh.mesh.num_vertices(), cb_mesh.vertex_colors.len()));
+    }
+    let (width, height) = scenesettings.window_size;
+    let projection = Projection::new(camera, width, height);
+    let mut rasterizer = Rasterizer::new(width, height);
+
+    for cb_mesh in meshes {
+        let screen_verts : Vec<ScreenVertex> = (0..cb_mesh.mesh.num_vertices()).map(|idx| projection.project(&vertex_coords(&cb_mesh.mesh.vertices, idx))).collect();
+        let colors = vertex_colors(cb_mesh, scenesettings.lighting.as_ref(), camera);
+        for face in cb_mesh.mesh.faces.chunks(3) {
+            let vidx = [face[0] as usize, face[1] as usize, face[2] as usize];
+            let tri = [screen_verts[vidx[0]], screen_verts[vidx[1]], screen_verts[vidx[2]]];
+            let tri_colors = [colors[vidx[0]], colors[vidx[1]], colors[vidx[2]]];
+            // Skip triangles beyond the far plane, and clip triangles which are partly behind the near plane.
+            if tri.iter().all(|v| v.depth > camera.z_far) {
+                continue;
+            }
+            if tri.iter().all(|v| v.depth > camera.z_near) {
+                rasterizer.draw_triangle(&tri, &tri_colors);
+                continue;
+            }
+            let corners = [0, 1, 2].map(|i| (vertex_coords(&cb_mesh.mesh.vertices, vidx[i]), tri_colors[i]));
+            let polygon = clip_near(&corners, &projection, camera.z_near);
+            for i in 1..polygon.len().saturating_sub(1) {
+                let clipped = [polygon[0], polygon[i], polygon[i + 1]];
+                rasterizer.draw_triangle(&clipped.map(|corner| projection.project(&corner.0)), &clipped.map(|corner| corner.1));
+            }
+        }
+    }
+    let mut img = rasterizer.into_image(&scenesettings.bg_color);
+    if let Some(colorbar) = &scenesettings.colorbar {
+        draw_colorbar(&mut img, colorbar);
+    }
+    Ok(img)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use neuroformats::BrainMesh;
+
+    const RED : [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+    const BLUE : [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+    const WHITE : [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+    /// A triangle in image coordinates covering the upper left half of a 4x4 image, at the given depth.
+    fn upper_left_triangle(depth : f32) -> [ScreenVertex; 3] {
+        [ScreenVertex { x: 0.0, y: 0.0, depth }, ScreenVertex { x: 4.0, y: 0.0, depth }, ScreenVertex { x: 0.0, y: 4.0, depth }]
+    }
+
+    /// A camera on the z axis looking at the origin.
+    fn camera_on_z_axis(z : f32) -> CameraSpec {
+        CameraSpec { position: [0.0, 0.0, z], target: [0.0, 0.0, 0.0], up: [0.0, 1.0, 0.0], fov_y_degrees: 90.0, z_near: 0.1, z_far: 100.0 }
+    }
+
+    #[test]
+    fn a_triangle_covers_the_pixels_with_centers_inside_of_it() {
+        let mut rasterizer = Rasterizer::new(4, 4);
+        rasterizer.draw_triangle(&upper_left_triangle(1.0), &[RED; 3]);
+        let img = rasterizer.into_image(&WHITE);
+
+        for y in 0..4 {
+            for x in 0..4 {
+                let expected = if x + y < 4 { [255, 0, 0, 255] } else { [255, 255, 255, 255] };
+                assert_eq!(Rgba(expected), *img.get_pixel(x, y), "pixel {} {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn the_z_buffer_keeps_the_nearer_triangle() {
+        for near_first in [true, false].iter() {
+            let mut rasterizer = Rasterizer::new(4, 4);
+            let (near, far) = ((upper_left_triangle(1.0), RED), (upper_left_triangle(2.0), BLUE));
+            let order = if *near_first { [near, far] } else { [far, near] };
+            for (tri, color) in order.iter() {
+                rasterizer.draw_triangle(tri, &[*color; 3]);
+            }
+            assert_eq!(Rgba([255, 0, 0, 255]), *rasterizer.into_image(&WHITE).get_pixel(0, 0));
+        }
+    }
+
+    #[test]
+    fn fully_transparent_triangles_do_not_hide_the_ones_behind_them() {
+        let mut rasterizer = Rasterizer::new(4, 4);
+        rasterizer.draw_triangle(&upper_left_triangle(1.0), &[[1.0, 0.0, 0.0, 0.0]; 3]);
+        rasterizer.draw_triangle(&upper_left_triangle(2.0), &[BLUE; 3]);
+        assert_eq!(Rgba([0, 0, 255, 255]), *rasterizer.into_image(&WHITE).get_pixel(0, 0));
+    }
+
+    #[test]
+    fn the_rendered_image_has_the_requested_size() {
+        let b_mesh = BrainMesh { vertices: vec![-1.0, -1.0, 0.0, 1.0, -1.0, 0.0, 0.0, 1.0, 0.0], faces: vec![0, 1, 2] };
+        let meshes = vec![ColoredBrainMesh::from_brainmesh_and_colors(&b_mesh, [255, 0, 0, 255].repeat(3)).unwrap()];
+        let settings = SceneSettings { window_size: (7, 5), lighting: None, ..Default::default() };
+        let img = render_to_image(&meshes, &settings, &camera_on_z_axis(5.0)).unwrap();
+
+        assert_eq!((7, 5), img.dimensions());
+        assert_eq!(Rgba([255, 0, 0, 255]), *img.get_pixel(3, 2));
+    }
+
+    #[test]
+    fn triangles_reaching_behind_the_camera_are_clipped_instead_of_dropped() {
+        // A large triangle in the plane y = -1 below the camera, reaching from far in front of it to behind it.
+        let b_mesh = BrainMesh { vertices: vec![-50.0, -1.0, -50.0, 50.0, -1.0, -50.0, 0.0, -1.0, 50.0], faces: vec![0, 1, 2] };
+        let meshes = vec![ColoredBrainMesh::from_brainmesh_and_colors(&b_mesh, [255, 0, 0, 255].repeat(3)).unwrap()];
+        let settings = SceneSettings { window_size: (20, 20), lighting: None, ..Default::default() };
+        let img = render_to_image(&meshes, &settings, &CameraSpec { target: [0.0, 0.0, -1.0], ..camera_on_z_axis(0.0) }).unwrap();
+
+        // The floor fills the lower half of the image, the upper half shows the background.
+        assert_eq!(Rgba([255, 0, 0, 255]), *img.get_pixel(10, 18));
+        assert_eq!(Rgba([255, 255, 255, 255]), *img.get_pixel(10, 2));
+    }
+
+    #[test]
+    fn rendering_an_empty_scene_is_an_error() {
+        let result = render_to_image(&Vec::new(), &SceneSettings::default(), &camera_on_z_axis(5.0));
+        assert!(matches!(result, Err(BrainviewError::EmptyScene)));
+    }
+
+    #[test]
+    fn rendering_a_mesh_without_one_color_per_vertex_is_an_error() {
+        let b_mesh = BrainMesh { vertices: vec![-1.0, -1.0, 0.0, 1.0, -1.0, 0.0, 0.0, 1.0, 0.0], faces: vec![0, 1, 2] };
+        let mut cb_mesh = ColoredBrainMesh::from_brainmesh_and_colors(&b_mesh, [255, 0, 0, 255].repeat(3)).unwrap();
+        cb_mesh.vertex_colors.truncate(8);
+        let settings = SceneSettings { window_size: (7, 5), ..Default::default() };
+        let result = render_to_image(&vec![cb_mesh], &settings, &camera_on_z_axis(5.0));
+        assert!(matches!(result, Err(BrainviewError::ColorCountMismatch(3, 8))));
+    }
+}
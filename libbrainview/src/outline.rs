@@ -0,0 +1,138 @@
+//! Outlines of surface regions, like the boundaries between the regions of a brain atlas parcellation.
+//!
+//! Drawn on top of other data, e.g., as a `ColorLayer`, the outlines show where effects fall relative to the atlas regions.
+
+use std::collections::VecDeque;
+
+use neuroformats::{FsAnnot};
+
+use crate::topology::{MeshTopology};
+
+
+/// Determine the vertices on the boundaries between regions.
+///
+/// The `vertex_regions` assign a region identifier to each vertex of the mesh with the given topology, e.g., the `vertex_labels` of an `FsAnnot`.
+/// A vertex is on a boundary if one of its neighbors belongs to another region, so boundaries are drawn on both sides of the border. The `width` is the width of the boundary
+/// in edge hops: 1 selects only the vertices at the border, larger values also select the vertices up to `width - 1` edges away from them.
+/// A width of 0 selects no vertices.
+pub fn region_boundary_vertices(topology : &MeshTopology, vertex_regions : &[i32], width : usize) -> Vec<bool> {
+    let mut on_boundary : Vec<bool> = vec![false; topology.num_vertices];
+    if width == 0 {
+        return on_boundary;
+    }
+
+    let neighbors = &topology.neighbors;
+    let mut queue : VecDeque<(usize, usize)> = VecDeque::new();
+    for (vidx, vneighbors) in neighbors.iter().enumerate() {
+        if vneighbors.iter().any(|n| vertex_regions.get(*n) != vertex_regions.get(vidx)) {
+            on_boundary[vidx] = true;
+            queue.push_back((vidx, 1));
+        }
+    }
+
+    // Grow the boundary by breadth-first search until it has the requested width.
+    while let Some((vidx, dist)) = queue.pop_front() {
+        if dist >= width {
+            continue;
+        }
+        for n in neighbors[vidx].iter() {
+            if !on_boundary[*n] {
+                on_boundary[*n] = true;
+                queue.push_back((*n, dist + 1));
+            }
+        }
+    }
+    on_boundary
+}
+
+
+/// Compute RGBA vertex colors showing the outlines of the regions of a parcellation, 4 values per vertex.
+///
+/// Vertices on the region boundaries (see `region_boundary_vertices`) get the given color, or the color of their region if no color is given.
+/// All other vertices are fully transparent.
+pub fn annot_outline_colors(topology : &MeshTopology, annot : &FsAnnot, width : usize, color : Option<[u8; 4]>) -> Vec<u8> {
+    let region_colors = annot.vertex_colors(false, 0);
+    let on_boundary = region_boundary_vertices(topology, &annot.vertex_labels, width);
+    let mut colors : Vec<u8> = vec![0; on_boundary.len() * 4];
+    for (vidx, _) in on_boundary.iter().enumerate().filter(|(_, b)| **b) {
+        let vcolor = match color {
+            Some(color) => color,
+            None if vidx * 3 + 2 < region_colors.len() => [region_colors[vidx * 3], region_colors[vidx * 3 + 1], region_colors[vidx * 3 + 2], 255],
+            None => continue,
+        };
+        colors[vidx * 4..vidx * 4 + 4].copy_from_slice(&vcolor);
+    }
+    colors
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use neuroformats::{BrainMesh, FsAnnotColortable};
+
+    /// A strip of 4 quads made of 8 triangles, with 5 vertices on the top row (0 to 4) and 5 on the bottom row (5 to 9).
+    fn strip_topology() -> MeshTopology {
+        let mut vertices : Vec<f32> = Vec::new();
+        for y in [1.0, 0.0].iter() {
+            for x in 0..5 {
+                vertices.extend_from_slice(&[x as f32, *y, 0.0]);
+            }
+        }
+        let faces : Vec<i32> = (0..4).flat_map(|i| vec![i, i + 5, i + 1, i + 1, i + 5, i + 6]).collect();
+        MeshTopology::from_mesh(&BrainMesh { vertices, faces })
+    }
+
+    /// A parcellation of the strip with a red region for the first 2 columns and a blue one for the other 3.
+    fn strip_annot() -> FsAnnot {
+        let colortable = FsAnnotColortable {
+            id: vec![0, 1],
+            name: vec![String::from("left"), String::from("right")],
+            r: vec![255, 0],
+            g: vec![0, 0],
+            b: vec![0, 255],
+            a: vec![0, 0],
+            label: vec![100, 200],
+        };
+        let vertex_labels = [100, 100, 200, 200, 200].repeat(2);
+        FsAnnot { vertex_indices: (0..10).collect(), vertex_labels, colortable }
+    }
+
+    fn selected(on_boundary : &[bool]) -> Vec<usize> {
+        on_boundary.iter().enumerate().filter(|(_, b)| **b).map(|(vidx, _)| vidx).collect()
+    }
+
+    #[test]
+    fn the_boundary_is_on_both_sides_of_the_border() {
+        let boundary = region_boundary_vertices(&strip_topology(), &strip_annot().vertex_labels, 1);
+        assert_eq!(vec![1, 2, 6, 7], selected(&boundary));
+    }
+
+    #[test]
+    fn wider_boundaries_include_the_neighbors() {
+        let boundary = region_boundary_vertices(&strip_topology(), &strip_annot().vertex_labels, 2);
+        assert_eq!(vec![0, 1, 2, 3, 5, 6, 7, 8], selected(&boundary));
+        assert!(selected(&region_boundary_vertices(&strip_topology(), &strip_annot().vertex_labels, 0)).is_empty());
+    }
+
+    #[test]
+    fn a_single_region_has_no_boundary() {
+        let boundary = region_boundary_vertices(&strip_topology(), &[100; 10], 3);
+        assert_eq!(vec![false; 10], boundary);
+    }
+
+    #[test]
+    fn outlines_get_the_region_colors_or_the_given_color() {
+        let topology = strip_topology();
+        let colors = annot_outline_colors(&topology, &strip_annot(), 1, None);
+        assert_eq!(40, colors.len());
+        assert_eq!(vec![0; 4], colors[0..4].to_vec());
+        assert_eq!(vec![255, 0, 0, 255], colors[4..8].to_vec());
+        assert_eq!(vec![0, 0, 255, 255], colors[8..12].to_vec());
+
+        let colors = annot_outline_colors(&topology, &strip_annot(), 1, Some([1, 2, 3, 4]));
+        assert_eq!(vec![1, 2, 3, 4], colors[4..8].to_vec());
+        assert_eq!(vec![1, 2, 3, 4], colors[8..12].to_vec());
+        assert_eq!(vec![0; 4], colors[12..16].to_vec());
+    }
+}
@@ -0,0 +1,172 @@
+//! Picking of mesh vertices by casting a ray into the scene, e.g., to find the vertex under the mouse cursor.
+//!
+//! The ray casting is done on the CPU with the `BrainMesh` data, so it does not depend on a rendering context.
+
+use crate::brainmesh::{ColoredBrainMesh};
+use crate::geometry::{add3, cross3, dot3, length3, scale3, sub3, vertex_coords};
+
+
+/// Rays closer to parallel to a triangle than this are considered to miss it.
+const PARALLEL_EPSILON : f32 = 1e-8;
+
+
+/// The result of picking a vertex: the mesh and vertex that were hit, and where.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PickResult {
+    /// The index of the mesh in the list of picked meshes.
+    pub mesh_index : usize,
+    /// The index of the face hit by the ray.
+    pub face_index : usize,
+    /// The index of the vertex of the hit face which is closest to the hit point.
+    pub vertex_index : usize,
+    /// The coordinates of the picked vertex.
+    pub vertex_coords : [f32; 3],
+    /// The point where the ray hit the face.
+    pub hit_point : [f32; 3],
+    /// The distance from the ray origin to the hit point, in units of the ray direction.
+    pub distance : f32,
+}
+
+
+impl PickResult {
+
+    /// Describe the picked vertex of the given mesh in a few lines of text: the vertex index, its coordinates and, if available, its data value and atlas region.
+    pub fn info_lines(&self, cb_mesh : &ColoredBrainMesh) -> Vec<String> {
+        let mut lines : Vec<String> = vec![
+            format!("Vertex {} of mesh {}", self.vertex_index, self.mesh_index),
+            format!("Coords {:.2} {:.2} {:.2}", self.vertex_coords[0], self.vertex_coords[1], self.vertex_coords[2]),
+        ];
+        if let Some(value) = cb_mesh.vertex_data_value(self.vertex_index) {
+            lines.push(format!("Value {:.4}", value));
+        }
+        if let Some(region) = cb_mesh.vertex_region_name(self.vertex_index) {
+            lines.push(format!("Region {}", region));
+        }
+        lines
+    }
+}
+
+
+/// Compute the intersection of a ray with a triangle using the Möller–Trumbore algorithm.
+///
+/// Returns the distance from the ray origin to the intersection point in units of the direction vector, or `None` if the ray misses the
+/// triangle or only its backwards extension hits it. Both sides of the triangle are hit.
+pub fn ray_triangle_intersection(origin : &[f32; 3], direction : &[f32; 3], v0 : &[f32; 3], v1 : &[f32; 3], v2 : &[f32; 3]) -> Option<f32> {
+    let edge1 = sub3(v1, v0);
+    let edge2 = sub3(v2, v0);
+    let p = cross3(direction, &edge2);
+    let det = dot3(&edge1, &p);
+    if det.abs() < PARALLEL_EPSILON {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let t_vec = sub3(origin, v0);
+    let u = dot3(&t_vec, &p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = cross3(&t_vec, &edge1);
+    let v = dot3(direction, &q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = dot3(&edge2, &q) * inv_det;
+    if t > 0.0 { Some(t) } else { None }
+}
+
+
+/// Find the vertex hit by a ray: the vertex closest to the point where the ray first hits a face of one of the meshes.
+///
+/// The ray is given in the coordinates of the meshes. Returns `None` if the ray misses all meshes.
+pub fn pick_vertex(meshes : &[ColoredBrainMesh], origin : &[f32; 3], direction : &[f32; 3]) -> Option<PickResult> {
+    let mut closest : Option<(usize, usize, f32)> = None;
+    for (mesh_idx, cb_mesh) in meshes.iter().enumerate() {
+        let vertices = &cb_mesh.mesh.vertices;
+        for (face_idx, face) in cb_mesh.mesh.faces.chunks(3).enumerate() {
+            let v0 = vertex_coords(vertices, face[0] as usize);
+            let v1 = vertex_coords(vertices, face[1] as usize);
+            let v2 = vertex_coords(vertices, face[2] as usize);
+            if let Some(t) = ray_triangle_intersection(origin, direction, &v0, &v1, &v2) {
+                if closest.is_none_or(|(_, _, closest_t)| t < closest_t) {
+                    closest = Some((mesh_idx, face_idx, t));
+                }
+            }
+        }
+    }
+
+    let (mesh_index, face_index, distance) = closest?;
+    let mesh = &meshes[mesh_index].mesh;
+    let hit_point = add3(origin, &scale3(direction, distance));
+    let vertex_index = mesh.faces[face_index * 3..face_index * 3 + 3].iter().map(|v| *v as usize)
+        .min_by(|a, b| {
+            let dist_a = length3(&sub3(&vertex_coords(&mesh.vertices, *a), &hit_point));
+            let dist_b = length3(&sub3(&vertex_coords(&mesh.vertices, *b), &hit_point));
+            dist_a.partial_cmp(&dist_b).unwrap_or(std::cmp::Ordering::Equal)
+        })?;
+    Some(PickResult { mesh_index, face_index, vertex_index, vertex_coords: vertex_coords(&mesh.vertices, vertex_index), hit_point, distance })
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use neuroformats::BrainMesh;
+
+    const V0 : [f32; 3] = [0.0, 0.0, 0.0];
+    const V1 : [f32; 3] = [1.0, 0.0, 0.0];
+    const V2 : [f32; 3] = [0.0, 1.0, 0.0];
+
+    /// A mesh with a single triangle in the plane z = `z`, with the corners of `V0`, `V1` and `V2`.
+    fn triangle_mesh(z : f32) -> ColoredBrainMesh {
+        let b_mesh = BrainMesh { vertices: vec![0.0, 0.0, z, 1.0, 0.0, z, 0.0, 1.0, z], faces: vec![0, 1, 2] };
+        ColoredBrainMesh::from_brainmesh_and_colors(&b_mesh, vec![255; 12]).unwrap()
+    }
+
+    #[test]
+    fn a_ray_through_a_triangle_hits_it() {
+        let t = ray_triangle_intersection(&[0.25, 0.25, 5.0], &[0.0, 0.0, -1.0], &V0, &V1, &V2);
+        assert_eq!(Some(5.0), t);
+    }
+
+    #[test]
+    fn a_ray_next_to_a_triangle_misses_it() {
+        assert_eq!(None, ray_triangle_intersection(&[0.75, 0.75, 5.0], &[0.0, 0.0, -1.0], &V0, &V1, &V2));
+        assert_eq!(None, ray_triangle_intersection(&[-0.1, 0.5, 5.0], &[0.0, 0.0, -1.0], &V0, &V1, &V2));
+    }
+
+    #[test]
+    fn a_ray_parallel_to_a_triangle_misses_it() {
+        assert_eq!(None, ray_triangle_intersection(&[-1.0, 0.25, 0.0], &[1.0, 0.0, 0.0], &V0, &V1, &V2));
+    }
+
+    #[test]
+    fn a_triangle_behind_the_ray_origin_is_not_hit() {
+        assert_eq!(None, ray_triangle_intersection(&[0.25, 0.25, 5.0], &[0.0, 0.0, 1.0], &V0, &V1, &V2));
+    }
+
+    #[test]
+    fn the_nearest_of_two_stacked_triangles_is_picked() {
+        let meshes = vec![triangle_mesh(0.0), triangle_mesh(2.0)];
+        let pick = pick_vertex(&meshes, &[0.2, 0.2, 10.0], &[0.0, 0.0, -1.0]).unwrap();
+        assert_eq!(1, pick.mesh_index);
+        assert_eq!(8.0, pick.distance);
+
+        let pick = pick_vertex(&meshes, &[0.2, 0.2, -10.0], &[0.0, 0.0, 1.0]).unwrap();
+        assert_eq!(0, pick.mesh_index);
+        assert_eq!(10.0, pick.distance);
+    }
+
+    #[test]
+    fn the_picked_vertex_is_the_corner_closest_to_the_hit_point() {
+        let meshes = vec![triangle_mesh(0.0)];
+        let pick = pick_vertex(&meshes, &[0.7, 0.1, 1.0], &[0.0, 0.0, -1.0]).unwrap();
+        assert_eq!(0, pick.face_index);
+        assert_eq!(1, pick.vertex_index);
+        assert_eq!(V1, pick.vertex_coords);
+        assert_eq!([0.7, 0.1, 0.0], pick.hit_point);
+
+        let pick = pick_vertex(&meshes, &[0.1, 0.8, 1.0], &[0.0, 0.0, -1.0]).unwrap();
+        assert_eq!(2, pick.vertex_index);
+        assert!(pick_vertex(&meshes, &[2.0, 2.0, 1.0], &[0.0, 0.0, -1.0]).is_none());
+    }
+}
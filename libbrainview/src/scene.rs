@@ -1,6 +1,16 @@
 
+use std::str::FromStr;
+
 use three_d::*;
-use crate::{ColoredBrainMesh, mesh_from_colored_brain_mesh, brain_mesh_aabb};
+use crate::{ColoredBrainMesh, Hemisphere, GpuBrainMesh, brain_mesh_aabb};
+use crate::threed_adapter::{textured_quad_from_image, threed_vec3};
+use crate::colorbar::{Colorbar};
+use crate::lighting::{Lighting, LIT_VERTEX_COLOR_SHADER};
+use crate::geometry::{add3, length3, normalize3, scale3, sub3, vertex_coords};
+use crate::picking::{pick_vertex};
+use crate::layout::{apply_hemisphere_layout, HemisphereLayout};
+use crate::font::{draw_text, text_height, text_width};
+use crate::error::{BrainviewError, Result};
 
 /// Settings, like background color, that can be used to customize the appearance of a scene.
 pub struct SceneSettings {
@@ -11,13 +21,73 @@ pub struct SceneSettings {
     pub cam_pan_speed: f32,
     pub cam_zoom_speed_keys: f32,
     pub auto_rotate_speed_factor: f64,
+    pub view: Option<ViewPreset>,
+    pub view_hemisphere: Hemisphere,
+    /// A colorbar that is shown in the bottom right corner, see `Colorbar::for_mesh`.
+    pub colorbar: Option<Colorbar>,
+    /// The lighting of the meshes. If `None`, the meshes are rendered with their flat vertex colors.
+    pub lighting: Option<Lighting>,
+    /// The number of frames per second shown when playing meshes with several frames of data, see `ColoredBrainMesh::from_brainmesh_and_frames`.
+    pub frame_rate: f32,
+    /// Whether meshes with several frames of data start playing them as an animation.
+    pub play_frames: bool,
+    /// Where the meshes of the two hemispheres are placed, e.g., moved apart for inflated surfaces. See `apply_hemisphere_layout`.
+    pub hemisphere_layout: HemisphereLayout,
+}
+
+
+/// The standard anatomical views used in neuroscience figures.
+///
+/// The camera positions assume FreeSurfer RAS coordinates: the x axis points to the right, y to anterior and z to superior. The
+/// lateral and medial views depend on the hemisphere, e.g., the lateral view of the left hemisphere looks from -x.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViewPreset {
+    Lateral,
+    Medial,
+    Dorsal,
+    Ventral,
+    Anterior,
+    Posterior,
+}
+
+
+impl ViewPreset {
+
+    /// The direction from the scene center to the camera and the camera up vector for this view of the given hemisphere.
+    pub fn direction_and_up(&self, hemi : Hemisphere) -> ([f32; 3], [f32; 3]) {
+        match (self, hemi) {
+            (ViewPreset::Lateral, Hemisphere::Lh) | (ViewPreset::Medial, Hemisphere::Rh) => ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+            (ViewPreset::Lateral, Hemisphere::Rh) | (ViewPreset::Medial, Hemisphere::Lh) => ([1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+            (ViewPreset::Dorsal, _) => ([0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+            (ViewPreset::Ventral, _) => ([0.0, 0.0, -1.0], [0.0, 1.0, 0.0]),
+            (ViewPreset::Anterior, _) => ([0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+            (ViewPreset::Posterior, _) => ([0.0, -1.0, 0.0], [0.0, 0.0, 1.0]),
+        }
+    }
 }
 
 
-impl SceneSettings {
+impl FromStr for ViewPreset {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "lateral" => Ok(ViewPreset::Lateral),
+            "medial" => Ok(ViewPreset::Medial),
+            "dorsal" => Ok(ViewPreset::Dorsal),
+            "ventral" => Ok(ViewPreset::Ventral),
+            "anterior" => Ok(ViewPreset::Anterior),
+            "posterior" => Ok(ViewPreset::Posterior),
+            _ => Err(format!("Invalid view '{}', must be one of lateral, medial, dorsal, ventral, anterior or posterior.", s)),
+        }
+    }
+}
+
+
+impl Default for SceneSettings {
 
     /// The default scene settings.
-    pub fn default() -> Self {
+    fn default() -> Self {
         SceneSettings {
             bg_color : [1.0, 1.0, 1.0, 1.0],
             window_size : (1280, 720),
@@ -26,6 +96,13 @@ impl SceneSettings {
             cam_pan_speed: 5.0,
             cam_zoom_speed_keys: 5.0,
             auto_rotate_speed_factor: 0.0005,
+            view: Some(ViewPreset::Lateral),
+            view_hemisphere: Hemisphere::Lh,
+            colorbar: None,
+            lighting: Some(Lighting::default()),
+            frame_rate: 2.0,
+            play_frames: false,
+            hemisphere_layout: HemisphereLayout::default(),
         }
     }
 }
@@ -33,21 +110,21 @@ impl SceneSettings {
 
 /// Compute the center of the whole scene, i.e., over all meshes. Used to determine 
 /// where the camera should look.
-fn compute_meshes_center(cb_meshes: &Vec<ColoredBrainMesh>) -> (f32, f32, f32) {
-    let (min_x, max_x, min_y, max_y, min_z, max_z) = compute_meshes_minmax_coords(cb_meshes);
+fn compute_meshes_center(cb_meshes: &Vec<ColoredBrainMesh>) -> Result<(f32, f32, f32)> {
+    let (min_x, max_x, min_y, max_y, min_z, max_z) = compute_meshes_minmax_coords(cb_meshes)?;
     let cx = (min_x + max_x) / 2.0;
     let cy = (min_y + max_y) / 2.0;
     let cz = (min_z + max_z) / 2.0;
-    (cx, cy, cz)
+    Ok((cx, cy, cz))
 }
 
 
 /// Compute the min max coords (like an axis-aligned bounding box) for the whole scene, i.e.,
 /// over all meshes. Used to determine how far the camera
-/// should be from the scene center to see everything.
-fn compute_meshes_minmax_coords(cb_meshes: &Vec<ColoredBrainMesh>) -> (f32, f32, f32, f32, f32, f32) {
+/// should be from the scene center to see everything. Returns an `EmptyScene` error if there are no meshes.
+fn compute_meshes_minmax_coords(cb_meshes: &Vec<ColoredBrainMesh>) -> Result<(f32, f32, f32, f32, f32, f32)> {
     if cb_meshes.is_empty() {
-        panic!("Mesh list must no be empty.");
+        Err(BrainviewError::EmptyScene)
     } else {
         let mut min_x = f32::INFINITY;
         let mut min_y = f32::INFINITY;
@@ -56,7 +133,7 @@ fn compute_meshes_minmax_coords(cb_meshes: &Vec<ColoredBrainMesh>) -> (f32, f32,
         let mut max_y = f32::NEG_INFINITY;
         let mut max_z = f32::NEG_INFINITY;
         for cb_mesh in cb_meshes {
-            let aabb = brain_mesh_aabb(&cb_mesh).unwrap();
+            let aabb = brain_mesh_aabb(cb_mesh)?;
             if aabb.min[0] < min_x { min_x =  aabb.min[0] };
             if aabb.min[1] < min_y { min_y =  aabb.min[1] };
             if aabb.min[2] < min_z { min_z =  aabb.min[2] };
@@ -65,148 +142,416 @@ fn compute_meshes_minmax_coords(cb_meshes: &Vec<ColoredBrainMesh>) -> (f32, f32,
             if aabb.max[1] > max_y { max_y =  aabb.max[1] };
             if aabb.max[2] > max_z { max_z =  aabb.max[2] };
         }
-        (min_x, max_x, min_y, max_y, min_z, max_z)
+        Ok((min_x, max_x, min_y, max_y, min_z, max_z))
     }    
 }
 
 
 /// Compute the radius of the whole scene, i.e., over all meshes. Used to determine how far the camera
 /// should be from the scene center to see everything.
-fn compute_meshes_max_radius(cb_meshes: &Vec<ColoredBrainMesh>) -> f32 {
-    let mm = compute_meshes_minmax_coords(cb_meshes);
+fn compute_meshes_max_radius(cb_meshes: &Vec<ColoredBrainMesh>) -> Result<f32> {
+    let mm = compute_meshes_minmax_coords(cb_meshes)?;
     let dx = mm.1 - mm.0;
     let dy = mm.3 - mm.2;
     let dz = mm.5 - mm.4;
     let maxd_xy = if dx > dy { dx } else { dy };
     let max = if maxd_xy > dz { maxd_xy } else { dz }; 
-    max / 2.0
+    Ok(max / 2.0)
+}
+
+
+/// A camera position and orientation that does not depend on a rendering context. Used to
+/// setup the camera of the interactive scene and for offscreen rendering.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraSpec {
+    pub position : [f32; 3],
+    pub target : [f32; 3],
+    pub up : [f32; 3],
+    pub fov_y_degrees : f32,
+    pub z_near : f32,
+    pub z_far : f32,
+}
+
+
+impl CameraSpec {
+
+    /// A camera that looks at the scene center from the given direction, from a distance that shows all meshes.
+    ///
+    /// Returns an `EmptyScene` error if there are no meshes. The same holds for the other constructors that depend on the meshes.
+    pub fn looking_from(cb_meshes: &Vec<ColoredBrainMesh>, direction : &[f32; 3], up : &[f32; 3]) -> Result<CameraSpec> {
+        let sc = compute_meshes_center(cb_meshes)?;
+        let scene_center = [sc.0, sc.1, sc.2];
+        let scene_radius = compute_meshes_max_radius(cb_meshes)? * 3.0;
+        Ok(CameraSpec {
+            position : add3(&scene_center, &scale3(&normalize3(direction), scene_radius)),
+            target : scene_center,
+            up : *up,
+            fov_y_degrees : 45.0,
+            z_near : 0.1,
+            z_far : 1000.0,
+        })
+    }
+
+
+    /// The default camera for the given meshes, which looks at the scene from an oblique angle.
+    pub fn for_meshes(cb_meshes: &Vec<ColoredBrainMesh>) -> Result<CameraSpec> {
+        CameraSpec::looking_from(cb_meshes, &[0.6, 0.3, 1.0], &[0.0, 1.0, 0.0])
+    }
+
+
+    /// The camera for a standard anatomical view of the given hemisphere.
+    pub fn for_view(cb_meshes: &Vec<ColoredBrainMesh>, view : ViewPreset, hemi : Hemisphere) -> Result<CameraSpec> {
+        let (direction, up) = view.direction_and_up(hemi);
+        CameraSpec::looking_from(cb_meshes, &direction, &up)
+    }
+
+
+    /// The camera the scene starts with for the given settings: the view preset if one is set, the default camera otherwise.
+    pub fn for_settings(cb_meshes: &Vec<ColoredBrainMesh>, scenesettings : &SceneSettings) -> Result<CameraSpec> {
+        match scenesettings.view {
+            Some(view) => CameraSpec::for_view(cb_meshes, view, scenesettings.view_hemisphere),
+            None => CameraSpec::for_meshes(cb_meshes),
+        }
+    }
+
+
+    /// The distance between the camera position and its target.
+    pub fn distance(&self) -> f32 {
+        length3(&sub3(&self.position, &self.target))
+    }
 }
 
 
 /// Open a window and render a scene containing the given meshes.
 ///
 /// The SceneSettings are optional, they can be used to customize the visualization. One can navigate in the scene,
-/// zoom the camera, etc with the mouse or with key controls.
-pub fn scene(meshes : Vec<ColoredBrainMesh>, scenesettings : Option<SceneSettings>) { 
-    let scenesettings = scenesettings.unwrap_or(SceneSettings::default());
+/// zoom the camera, etc with the mouse or with key controls. The keys `1` to `6` switch to the lateral, medial, dorsal,
+/// ventral, anterior and posterior views, and `H` switches the hemisphere that the lateral and medial views refer to.
+/// For meshes with color layers, `L` selects the next layer and `V` toggles the visibility of the selected layer in all meshes, the
+/// selected layer is shown in the top left corner.
+/// Clicking a mesh without dragging picks the vertex under the cursor: its index, coordinates, data value and atlas region are shown
+/// in the top left corner and printed. For meshes with several frames of data, `N` and `B` show the next and previous frame, and
+/// `Space` plays or pauses them as an animation at the frame rate of the settings. All meshes show the same frame index.
+///
+/// Returns an error if there are no meshes, or if the window or the GPU resources cannot be created. Errors during rendering
+/// are printed and close the window.
+pub fn scene(meshes : Vec<ColoredBrainMesh>, scenesettings : Option<SceneSettings>) -> Result<()> {
+    let mut meshes = meshes;
+    let scenesettings = scenesettings.unwrap_or_default();
+
+    // Picked vertices are reported with their native coordinates, not the ones they are shown at.
+    let native_vertices : Vec<Vec<f32>> = meshes.iter().map(|cbm| cbm.mesh.vertices.clone()).collect();
+    apply_hemisphere_layout(&mut meshes, scenesettings.hemisphere_layout);
 
     // Prepare window
-    let window = Window::new(&scenesettings.window_title, Some(scenesettings.window_size)).unwrap();    
+    let window = Window::new(&scenesettings.window_title, Some(scenesettings.window_size))?;
     let context = window.gl();
    
 
     // Setup camera
-    let sc = compute_meshes_center(&meshes);
-    let scene_center = vec3(sc.0, sc.1, sc.2);
-    let scene_radius = compute_meshes_max_radius(&meshes) * 3.0;
-    let cam_move_speed : f32 = scene_radius / 40.;
-    let mut camera = CameraControl::new(Camera::new_perspective(&context, scene_center + scene_radius * vec3(0.6, 0.3, 1.0).normalize(), scene_center, vec3(0.0, 1.0, 0.0),
-                                             degrees(45.0), window.viewport().aspect(), 0.1, 1000.0).unwrap());
+    let cam_spec = CameraSpec::for_settings(&meshes, &scenesettings)?;
+    let cam_move_speed : f32 = cam_spec.distance() / 40.;
+    let mut camera = CameraControl::new(Camera::new_perspective(&context, threed_vec3(&cam_spec.position), threed_vec3(&cam_spec.target), threed_vec3(&cam_spec.up),
+                                             degrees(cam_spec.fov_y_degrees), window.viewport().aspect(), cam_spec.z_near, cam_spec.z_far)?);
                                              
-    // Get meshes into three-d datastructure and copy to GPU for rendering:
-    let mut threed_meshes : Vec<Mesh> = Vec::with_capacity(meshes.len());
+    // Copy meshes to the GPU for rendering. Only their colors are updated later, e.g., when showing another frame of data.
+    let mut threed_meshes : Vec<GpuBrainMesh> = Vec::with_capacity(meshes.len());
     for cbm in meshes.iter() {
-        threed_meshes.push(mesh_from_colored_brain_mesh(&cbm, &context).unwrap());
+        threed_meshes.push(GpuBrainMesh::new(cbm, &context)?);
     }
+
+    let lit_program = MeshProgram::new(&context, LIT_VERTEX_COLOR_SHADER)?;
+
+    // Overlays like the colorbar are rendered into an image, and shown on a quad with a fixed orthographic camera in their own viewport.
+    let overlay_camera = Camera::new_orthographic(&context, vec3(0.0, 0.0, 1.0), vec3(0.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0), 2.0, 2.0, 10.0)?;
+    let colorbar_overlay = scenesettings.colorbar.as_ref().map(|colorbar| ScreenOverlay::new(&colorbar.to_image(), &context)).transpose()?;
+    let mut info_overlay : Option<ScreenOverlay> = None;   // Information on the picked vertex or the selected layer.
                                          
 
     // Render loop.
     let mut is_cam_mouse_rotating = false;     // Whether the user is currently rotating the cam with the mouse.
+    let mut has_mouse_dragged = false;         // Whether the mouse was moved since the button was pressed. A click without dragging picks a vertex.
     let mut are_meshes_auto_rotating = true;   // Whether the brain mesh is auto-rotating. Can be toggled on/off.
+    let mut auto_rotate_axis = threed_vec3(&cam_spec.up);  // The meshes rotate around the up axis of the current view.
+    let mut current_view = scenesettings.view;
+    let mut view_hemi = scenesettings.view_hemisphere;
+    let num_layers = meshes.iter().map(|m| m.layers.len()).max().unwrap_or(0);
+    let mut selected_layer : usize = 0;
+    let num_frames = meshes.iter().filter_map(|m| m.frames.as_ref().map(|f| f.num_frames())).max().unwrap_or(0);
+    let mut current_frame = meshes.iter().find_map(|m| m.frames.as_ref().map(|f| f.current)).unwrap_or(0);
+    let mut is_playing_frames = scenesettings.play_frames && num_frames > 1;
+    let frame_duration_ms = 1000.0 / scenesettings.frame_rate.max(0.001) as f64;
+    let mut time_since_frame_ms = 0.0;
     
     window.render_loop(move |frame_input|
     {
-        camera.set_aspect(frame_input.viewport.aspect()).unwrap();
-
-        for event in frame_input.events.iter() {
-            match event {
-                Event::MouseClick { state, button, .. } => {
-                    is_cam_mouse_rotating = *button == MouseButton::Left && *state == State::Pressed;
-                },
-                Event::MouseMotion { delta, .. } => {
-                    if is_cam_mouse_rotating {
-                        camera.rotate_around_up((delta.0 as f32) * scenesettings.mouse_rotate_speed_factor, (delta.1 as f32) * scenesettings.mouse_rotate_speed_factor).unwrap();
-                    }
-                },
-                Event::MouseWheel { delta, .. } => {
-                    camera.zoom(delta.1 as f32).unwrap();
-                },
-                Event::Key { state, kind, .. } => {
-                    if *kind == Key::P && *state == State::Pressed
-                    {
-                        are_meshes_auto_rotating = !are_meshes_auto_rotating;
-                    }
+        // The render loop only ends when the program exits, so errors cannot be returned from it. They are printed, and close the window.
+        let mut render_frame = || -> Result<()> {
+            camera.set_aspect(frame_input.viewport.aspect())?;
+            let mut next_frame : Option<usize> = None;
+            if is_playing_frames {
+                time_since_frame_ms += frame_input.elapsed_time;
+                if time_since_frame_ms >= frame_duration_ms {
+                    time_since_frame_ms = 0.0;
+                    next_frame = Some((current_frame + 1) % num_frames);
+                }
+            }
+            let transformation = if are_meshes_auto_rotating { Mat4::from_axis_angle(auto_rotate_axis, radians((frame_input.accumulated_time * scenesettings.auto_rotate_speed_factor) as f32)) } else { Mat4::identity()};
 
-                    // WASD cam controls, R+F is up/down. This movement direction is currently independent of 
-                    // the view direction: one always moves along the x/y/z axes. This is rather unintuitive.
-                    if *kind == Key::W && *state == State::Pressed
-                    {
-                        camera.translate(&vec3(cam_move_speed, 0.0, 0.0)).unwrap();
-                    }
-                    if *kind == Key::S && *state == State::Pressed
-                    {
-                        camera.translate(&vec3(-cam_move_speed, 0.0, 0.0)).unwrap();
-                    }
-                    if *kind == Key::A && *state == State::Pressed
-                    {
-                        camera.translate(&vec3(0.0, cam_move_speed, 0.0)).unwrap();
-                    }
-                    if *kind == Key::D && *state == State::Pressed
-                    {
-                        camera.translate(&vec3(0.0, -cam_move_speed, 0.0)).unwrap();
-                    }
-                    if *kind == Key::R && *state == State::Pressed
-                    {
-                        camera.translate(&vec3(0.0, 0.0, cam_move_speed)).unwrap();
-                    }
-                    if *kind == Key::F && *state == State::Pressed
-                    {
-                        camera.translate(&vec3(0.0, 0.0,  -cam_move_speed)).unwrap();
-                    }
+            for event in frame_input.events.iter() {
+                match event {
+                    Event::MouseClick { state, button, position, .. } => {
+                        if *button == MouseButton::Left && *state == State::Released && !has_mouse_dragged {
+                            // Cast a ray through the clicked pixel, transformed into the coordinates of the (possibly rotated) meshes.
+                            let screen_pos = (position.0 / frame_input.window_width as f64, position.1 / frame_input.window_height as f64);
+                            let inverse = transformation.invert().unwrap_or_else(Mat4::identity);
+                            let origin = (inverse * camera.position().extend(1.0)).truncate();
+                            let direction = (inverse * camera.view_direction_at(screen_pos).extend(0.0)).truncate();
+                            match pick_vertex(&meshes, &[origin.x, origin.y, origin.z], &[direction.x, direction.y, direction.z]) {
+                                Some(mut pick) => {
+                                    pick.vertex_coords = vertex_coords(&native_vertices[pick.mesh_index], pick.vertex_index);
+                                    let lines = pick.info_lines(&meshes[pick.mesh_index]);
+                                    println!("{}", lines.join(", "));
+                                    info_overlay = Some(ScreenOverlay::new(&text_overlay_image(&lines, &scenesettings.bg_color), &context)?);
+                                },
+                                None => info_overlay = None,
+                            }
+                        }
+                        is_cam_mouse_rotating = *button == MouseButton::Left && *state == State::Pressed;
+                        has_mouse_dragged = false;
+                    },
+                    Event::MouseMotion { delta, .. } if is_cam_mouse_rotating => {
+                        has_mouse_dragged = true;
+                        camera.rotate_around_up((delta.0 as f32) * scenesettings.mouse_rotate_speed_factor, (delta.1 as f32) * scenesettings.mouse_rotate_speed_factor)?;
+                    },
+                    Event::MouseWheel { delta, .. } => {
+                        camera.zoom(delta.1 as f32)?;
+                    },
+                    Event::Key { state, kind, .. } => {
+                        if *kind == Key::P && *state == State::Pressed
+                        {
+                            are_meshes_auto_rotating = !are_meshes_auto_rotating;
+                        }
 
-                    // Pan controls.
-                    if *kind == Key::ArrowLeft && *state == State::Pressed
-                    {
-                        camera.pan(scenesettings.cam_pan_speed, 0.0).unwrap();
-                    }
-                    if *kind == Key::ArrowRight && *state == State::Pressed
-                    {
-                        camera.pan(-scenesettings.cam_pan_speed, 0.0).unwrap();
-                    }
-                    if *kind == Key::ArrowUp && *state == State::Pressed
-                    {
-                        camera.pan(0.0, scenesettings.cam_pan_speed).unwrap();
-                    }
-                    if *kind == Key::ArrowDown && *state == State::Pressed
-                    {
-                        camera.pan(0.0, -scenesettings.cam_pan_speed).unwrap();
-                    }
+                        // View presets. Selecting one stops the auto-rotation, so the view stays as requested.
+                        if *state == State::Pressed {
+                            let mut new_view = match *kind {
+                                Key::Num1 => Some(ViewPreset::Lateral),
+                                Key::Num2 => Some(ViewPreset::Medial),
+                                Key::Num3 => Some(ViewPreset::Dorsal),
+                                Key::Num4 => Some(ViewPreset::Ventral),
+                                Key::Num5 => Some(ViewPreset::Anterior),
+                                Key::Num6 => Some(ViewPreset::Posterior),
+                                _ => None,
+                            };
+                            if *kind == Key::H {
+                                view_hemi = view_hemi.other();
+                                new_view = current_view;
+                            }
+                            if let Some(view) = new_view {
+                                let view_cam = CameraSpec::for_view(&meshes, view, view_hemi)?;
+                                camera.set_view(threed_vec3(&view_cam.position), threed_vec3(&view_cam.target), threed_vec3(&view_cam.up))?;
+                                auto_rotate_axis = threed_vec3(&view_cam.up);
+                                are_meshes_auto_rotating = false;
+                                current_view = Some(view);
+                            }
+                        }
 
-                    // Zoom via keys instead of mouse
-                    if *kind == Key::PageUp && *state == State::Pressed
-                    {
-                        camera.zoom(scenesettings.cam_zoom_speed_keys).unwrap();
-                    }
-                    if *kind == Key::PageDown && *state == State::Pressed
-                    {
-                        camera.zoom(-scenesettings.cam_zoom_speed_keys).unwrap();
-                    }
+                        // Layer controls. The name and visibility of the selected layer are shown in the information overlay, and printed.
+                        if *kind == Key::L && *state == State::Pressed && num_layers > 0
+                        {
+                            selected_layer = (selected_layer + 1) % num_layers;
+                            if let Some(line) = layer_state(&meshes, selected_layer) {
+                                println!("{}", line);
+                                info_overlay = Some(ScreenOverlay::new(&text_overlay_image(&[line], &scenesettings.bg_color), &context)?);
+                            }
+                        }
+                        if *kind == Key::V && *state == State::Pressed && num_layers > 0
+                        {
+                            for (mesh_idx, cbm) in meshes.iter_mut().enumerate() {
+                                if cbm.toggle_layer(selected_layer).is_some() {
+                                    threed_meshes[mesh_idx].update_colors(&cbm.vertex_colors);
+                                }
+                            }
+                            if let Some(line) = layer_state(&meshes, selected_layer) {
+                                println!("{}", line);
+                                info_overlay = Some(ScreenOverlay::new(&text_overlay_image(&[line], &scenesettings.bg_color), &context)?);
+                            }
+                        }
+
+                        // Frame controls.
+                        if *kind == Key::N && *state == State::Pressed && num_frames > 0
+                        {
+                            next_frame = Some((current_frame + 1) % num_frames);
+                        }
+                        if *kind == Key::B && *state == State::Pressed && num_frames > 0
+                        {
+                            next_frame = Some((current_frame + num_frames - 1) % num_frames);
+                        }
+                        if *kind == Key::Space && *state == State::Pressed && num_frames > 1
+                        {
+                            is_playing_frames = !is_playing_frames;
+                            time_since_frame_ms = 0.0;
+                        }
+
+                        // WASD cam controls, R+F is up/down. This movement direction is currently independent of 
+                        // the view direction: one always moves along the x/y/z axes. This is rather unintuitive.
+                        if *kind == Key::W && *state == State::Pressed
+                        {
+                            camera.translate(&vec3(cam_move_speed, 0.0, 0.0))?;
+                        }
+                        if *kind == Key::S && *state == State::Pressed
+                        {
+                            camera.translate(&vec3(-cam_move_speed, 0.0, 0.0))?;
+                        }
+                        if *kind == Key::A && *state == State::Pressed
+                        {
+                            camera.translate(&vec3(0.0, cam_move_speed, 0.0))?;
+                        }
+                        if *kind == Key::D && *state == State::Pressed
+                        {
+                            camera.translate(&vec3(0.0, -cam_move_speed, 0.0))?;
+                        }
+                        if *kind == Key::R && *state == State::Pressed
+                        {
+                            camera.translate(&vec3(0.0, 0.0, cam_move_speed))?;
+                        }
+                        if *kind == Key::F && *state == State::Pressed
+                        {
+                            camera.translate(&vec3(0.0, 0.0,  -cam_move_speed))?;
+                        }
+
+                        // Pan controls.
+                        if *kind == Key::ArrowLeft && *state == State::Pressed
+                        {
+                            camera.pan(scenesettings.cam_pan_speed, 0.0)?;
+                        }
+                        if *kind == Key::ArrowRight && *state == State::Pressed
+                        {
+                            camera.pan(-scenesettings.cam_pan_speed, 0.0)?;
+                        }
+                        if *kind == Key::ArrowUp && *state == State::Pressed
+                        {
+                            camera.pan(0.0, scenesettings.cam_pan_speed)?;
+                        }
+                        if *kind == Key::ArrowDown && *state == State::Pressed
+                        {
+                            camera.pan(0.0, -scenesettings.cam_pan_speed)?;
+                        }
+
+                        // Zoom via keys instead of mouse
+                        if *kind == Key::PageUp && *state == State::Pressed
+                        {
+                            camera.zoom(scenesettings.cam_zoom_speed_keys)?;
+                        }
+                        if *kind == Key::PageDown && *state == State::Pressed
+                        {
+                            camera.zoom(-scenesettings.cam_zoom_speed_keys)?;
+                        }
                     
-                },
-                _ => {}
+                    },
+                    _ => {}
+                }
             }
-        }
 
-        // Do the actual rendering.
-        Screen::write(&context, &ClearState::color_and_depth(scenesettings.bg_color[0], scenesettings.bg_color[1], scenesettings.bg_color[2], scenesettings.bg_color[3], 1.0), || {
-            let transformation = if are_meshes_auto_rotating { Mat4::from_angle_y(radians((frame_input.accumulated_time * scenesettings.auto_rotate_speed_factor) as f32)) } else { Mat4::identity()};
-            for mesh in threed_meshes.iter() {
-                mesh.render_color(RenderStates::default(), frame_input.viewport, &transformation, &camera)?;
+            // Show the next frame in all meshes which have frames. Meshes with fewer frames keep their last one.
+            if let Some(frame_idx) = next_frame {
+                current_frame = frame_idx;
+                for (mesh_idx, cbm) in meshes.iter_mut().enumerate() {
+                    if cbm.frames.as_ref().is_some_and(|f| frame_idx < f.num_frames()) {
+                        cbm.show_frame(frame_idx)?;
+                        threed_meshes[mesh_idx].update_colors(&cbm.vertex_colors);
+                    }
+                }
+                println!("Frame {}/{}", current_frame + 1, num_frames);
             }
+
+            // Do the actual rendering.
+            let lighting = scenesettings.lighting.unwrap_or_else(Lighting::flat);
+            Screen::write(&context, &ClearState::color_and_depth(scenesettings.bg_color[0], scenesettings.bg_color[1], scenesettings.bg_color[2], scenesettings.bg_color[3], 1.0), || {
+                let eye = [camera.position().x, camera.position().y, camera.position().z];
+                let target = [camera.target().x, camera.target().y, camera.target().z];
+                lit_program.use_uniform_float("ambient", &lighting.ambient)?;
+                lit_program.use_uniform_float("diffuse", &lighting.diffuse)?;
+                lit_program.use_uniform_float("specular", &lighting.specular)?;
+                lit_program.use_uniform_float("specularPower", &lighting.specular_power)?;
+                lit_program.use_uniform_vec3("lightDirection", &threed_vec3(&lighting.light_direction(&eye, &target)))?;
+                lit_program.use_uniform_vec3("eyePosition", camera.position())?;
+                for mesh in threed_meshes.iter() {
+                    mesh.render(&lit_program, RenderStates::default(), frame_input.viewport, &transformation, &camera)?;
+                }
+                if let Some(overlay) = &colorbar_overlay {
+                    if overlay.width <= frame_input.viewport.width && overlay.height <= frame_input.viewport.height {
+                        overlay.render((frame_input.viewport.width - overlay.width) as i32, 0, &overlay_camera)?;
+                    }
+                }
+                if let Some(overlay) = &info_overlay {
+                    if overlay.width <= frame_input.viewport.width && overlay.height <= frame_input.viewport.height {
+                        overlay.render(0, (frame_input.viewport.height - overlay.height) as i32, &overlay_camera)?;
+                    }
+                }
+                Ok(())
+            })?;
             Ok(())
-        }).unwrap();
-        
-        FrameOutput::default()
-    }).unwrap();
+        };
+
+        match render_frame() {
+            Ok(()) => FrameOutput::default(),
+            Err(err) => {
+                eprintln!("{}", err);
+                FrameOutput { exit: true, ..Default::default() }
+            },
+        }
+    })?;
+    Ok(())
 }
 
+
+/// An image shown on top of the scene at a fixed position on the screen, like the colorbar.
+struct ScreenOverlay {
+    quad : Mesh,
+    texture : Texture2D,
+    width : usize,
+    height : usize,
+}
+
+
+impl ScreenOverlay {
+
+    /// Copy the image to the GPU.
+    fn new(img : &image::RgbaImage, context : &Context) -> Result<Self> {
+        let (quad, texture) = textured_quad_from_image(img, context)?;
+        Ok(ScreenOverlay { quad, texture, width: img.width() as usize, height: img.height() as usize })
+    }
+
+
+    /// Render the overlay with its bottom left corner at the given position in pixels, using an orthographic camera looking at the x/y plane.
+    fn render(&self, x : i32, y : i32, camera : &Camera) -> std::result::Result<(), three_d::Error> {
+        let viewport = Viewport { x, y, width: self.width, height: self.height };
+        self.quad.render_with_texture(&self.texture, RenderStates { depth_test: DepthTestType::Always, ..Default::default() }, viewport, &Mat4::identity(), camera)
+    }
+}
+
+
+/// Draw lines of text into an image with the given background color, used for the information on picked vertices.
+fn text_overlay_image(lines : &[String], bg_color : &[f32; 4]) -> image::RgbaImage {
+    let (scale, padding, line_spacing) = (2, 8, 6);
+    let width = lines.iter().map(|line| text_width(line, scale)).max().unwrap_or(0) + 2 * padding;
+    let height = lines.len() as u32 * (text_height(scale) + line_spacing) - line_spacing + 2 * padding;
+    let bg = [(bg_color[0] * 255.0) as u8, (bg_color[1] * 255.0) as u8, (bg_color[2] * 255.0) as u8, 255];
+    let is_bg_dark = bg_color[0] + bg_color[1] + bg_color[2] < 1.5;
+    let text_color = if is_bg_dark { [255, 255, 255, 255] } else { [0, 0, 0, 255] };
+    let mut img = image::RgbaImage::from_pixel(width, height, image::Rgba(bg));
+    for (line_idx, line) in lines.iter().enumerate() {
+        let y = padding + line_idx as u32 * (text_height(scale) + line_spacing);
+        draw_text(&mut img, padding as i64, y as i64, line, text_color, scale);
+    }
+    img
+}
+
+
+/// Describe the name and visibility of the selected color layer of the meshes, used by the layer controls of the scene. Returns `None` if there is no such layer.
+fn layer_state(meshes : &[ColoredBrainMesh], layer_idx : usize) -> Option<String> {
+    let layer = meshes.iter().find_map(|m| m.layers.layers.get(layer_idx))?;
+    Some(format!("Layer {} '{}': {}", layer_idx, layer.name, if layer.visible { "visible" } else { "hidden" }))
+}
@@ -0,0 +1,27 @@
+uniform float ambient;
+uniform float diffuse;
+uniform float specular;
+uniform float specularPower;
+uniform vec3 lightDirection;
+uniform vec3 eyePosition;
+
+in vec3 pos;
+in vec3 nor;
+in vec4 col;
+
+layout (location = 0) out vec4 outColor;
+
+void main()
+{
+    // Light both sides of the surface: flip normals which point away from the viewer.
+    vec3 toEye = normalize(eyePosition - pos);
+    vec3 normal = normalize(nor);
+    if (dot(normal, toEye) < 0.0) {
+        normal = -normal;
+    }
+    vec3 toLight = normalize(-lightDirection);
+    float diffuseFactor = max(dot(normal, toLight), 0.0);
+    float specularFactor = diffuseFactor > 0.0 ? pow(max(dot(reflect(-toLight, normal), toEye), 0.0), specularPower) : 0.0;
+    vec3 color = col.rgb / 255.0;
+    outColor = vec4(color * (ambient + diffuse * diffuseFactor) + vec3(specular * specularFactor), col.a / 255.0);
+}
@@ -0,0 +1,246 @@
+//! Surface-based smoothing of per-vertex data, e.g., to smooth noisy cortical thickness maps before coloring them.
+//!
+//! Vertices with NaN values, like those of the medial wall, are ignored: they do not contribute to the smoothed values
+//! of their neighbors and stay NaN. This corresponds to smoothing only within the cortex mask, like FreeSurfer's `mris_fwhm`.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use neuroformats::BrainMesh;
+
+use crate::geometry::{length3, sub3, vertex_coords};
+use crate::topology::{MeshTopology};
+use crate::error::{BrainviewError, Result};
+
+
+/// The factor between the full width at half maximum and the standard deviation of a Gaussian, `2 * sqrt(2 * ln(2))`.
+const FWHM_TO_SIGMA : f32 = 2.354_82;
+
+/// The Gaussian kernel is cut off at this many standard deviations.
+const KERNEL_CUTOFF_SIGMAS : f32 = 3.0;
+
+
+/// Smooth per-vertex data by iteratively replacing each value with the mean of the values of the vertex and its neighbors.
+///
+/// The amount of smoothing depends on the number of iterations and on the vertex density of the mesh. NaN values are ignored, see the module documentation.
+pub fn smooth_nn(topology : &MeshTopology, data : &[f32], iterations : usize) -> Result<Vec<f32>> {
+    check_data_length(topology, data)?;
+    let mut current = data.to_vec();
+    for _ in 0..iterations {
+        let next : Vec<f32> = (0..topology.num_vertices).map(|vidx| {
+            if current[vidx].is_nan() {
+                return f32::NAN;
+            }
+            let (sum, count) = topology.neighbors[vidx].iter().map(|n| current[*n]).filter(|v| !v.is_nan())
+                .fold((current[vidx], 1usize), |(sum, count), v| (sum + v, count + 1));
+            sum / count as f32
+        }).collect();
+        current = next;
+    }
+    Ok(current)
+}
+
+
+/// Smooth per-vertex data with a Gaussian kernel with the given full width at half maximum (FWHM), in the units of the mesh coordinates, i.e., mm for FreeSurfer surfaces.
+///
+/// The geodesic distance between vertices is approximated by the length of the shortest path along the mesh edges. The kernel is cut off at
+/// 3 standard deviations. Paths do not pass through vertices with NaN values, see the module documentation. An FWHM of 0 returns the data unchanged.
+pub fn smooth_fwhm(mesh : &BrainMesh, topology : &MeshTopology, data : &[f32], fwhm : f32) -> Result<Vec<f32>> {
+    check_data_length(topology, data)?;
+    if fwhm <= 0.0 {
+        return Ok(data.to_vec());
+    }
+    let sigma = fwhm / FWHM_TO_SIGMA;
+    let cutoff = KERNEL_CUTOFF_SIGMAS * sigma;
+
+    let mut search = GeodesicSearch::new(topology.num_vertices);
+    let smoothed = (0..topology.num_vertices).map(|vidx| {
+        if data[vidx].is_nan() {
+            return f32::NAN;
+        }
+        let (mut weighted_sum, mut weight_sum) = (0.0f64, 0.0f64);
+        for (nidx, dist) in search.within(mesh, topology, data, vidx, cutoff) {
+            let weight = (-(dist * dist) / (2.0 * sigma * sigma)).exp() as f64;
+            weighted_sum += weight * data[nidx] as f64;
+            weight_sum += weight;
+        }
+        (weighted_sum / weight_sum) as f32
+    }).collect();
+    Ok(smoothed)
+}
+
+
+/// Make sure there is one data value per vertex.
+fn check_data_length(topology : &MeshTopology, data : &[f32]) -> Result<()> {
+    if data.len() != topology.num_vertices {
+        return Err(BrainviewError::VertexCountMismatch(topology.num_vertices, data.len()));
+    }
+    Ok(())
+}
+
+
+/// A vertex in the queue of the Dijkstra search, ordered so that the `BinaryHeap` pops the closest vertex first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct QueuedVertex {
+    dist : f32,
+    vidx : usize,
+}
+
+
+impl Eq for QueuedVertex {}
+
+
+impl Ord for QueuedVertex {
+    fn cmp(&self, other : &Self) -> Ordering {
+        other.dist.partial_cmp(&self.dist).unwrap_or(Ordering::Equal)
+    }
+}
+
+
+impl PartialOrd for QueuedVertex {
+    fn partial_cmp(&self, other : &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+
+/// Dijkstra searches along the mesh edges, with buffers that are reused between searches from different start vertices.
+struct GeodesicSearch {
+    dist : Vec<f32>,
+    touched : Vec<usize>,
+    heap : BinaryHeap<QueuedVertex>,
+}
+
+
+impl GeodesicSearch {
+
+    fn new(num_vertices : usize) -> Self {
+        GeodesicSearch { dist : vec![f32::INFINITY; num_vertices], touched : Vec::new(), heap : BinaryHeap::new() }
+    }
+
+
+    /// Find the vertices within the given path length of the start vertex, and their distance to it. Vertices with NaN data values are skipped.
+    fn within(&mut self, mesh : &BrainMesh, topology : &MeshTopology, data : &[f32], start : usize, max_dist : f32) -> Vec<(usize, f32)> {
+        for vidx in self.touched.drain(..) {
+            self.dist[vidx] = f32::INFINITY;
+        }
+        self.heap.clear();
+
+        let mut found : Vec<(usize, f32)> = Vec::new();
+        self.dist[start] = 0.0;
+        self.touched.push(start);
+        self.heap.push(QueuedVertex { dist: 0.0, vidx: start });
+        while let Some(QueuedVertex { dist, vidx }) = self.heap.pop() {
+            if dist > self.dist[vidx] {
+                continue; // A shorter path to this vertex was found after it was queued.
+            }
+            found.push((vidx, dist));
+            let coords = vertex_coords(&mesh.vertices, vidx);
+            for n in topology.neighbors[vidx].iter() {
+                if data[*n].is_nan() {
+                    continue;
+                }
+                let ndist = dist + length3(&sub3(&vertex_coords(&mesh.vertices, *n), &coords));
+                if ndist <= max_dist && ndist < self.dist[*n] {
+                    if self.dist[*n].is_infinite() {
+                        self.touched.push(*n);
+                    }
+                    self.dist[*n] = ndist;
+                    self.heap.push(QueuedVertex { dist: ndist, vidx: *n });
+                }
+            }
+        }
+        found
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A flat square grid mesh with `n` x `n` vertices, 1 mm apart, with two triangles per grid cell.
+    fn grid_mesh(n : usize) -> BrainMesh {
+        let mut vertices : Vec<f32> = Vec::with_capacity(n * n * 3);
+        for row in 0..n {
+            for col in 0..n {
+                vertices.extend_from_slice(&[col as f32, row as f32, 0.0]);
+            }
+        }
+        let mut faces : Vec<i32> = Vec::with_capacity((n - 1) * (n - 1) * 6);
+        for row in 0..n - 1 {
+            for col in 0..n - 1 {
+                let v = (row * n + col) as i32;
+                let n = n as i32;
+                faces.extend_from_slice(&[v, v + 1, v + n + 1, v, v + n + 1, v + n]);
+            }
+        }
+        BrainMesh { vertices, faces }
+    }
+
+    /// Data for the `n` x `n` grid mesh that is 0 everywhere, except for a value of 1 at the center vertex.
+    fn center_spike(n : usize) -> Vec<f32> {
+        let mut data = vec![0.0; n * n];
+        data[(n / 2) * n + n / 2] = 1.0;
+        data
+    }
+
+    #[test]
+    fn constant_data_is_not_changed_by_smoothing() {
+        let mesh = grid_mesh(5);
+        let topology = MeshTopology::from_mesh(&mesh);
+        let data = vec![2.5; 25];
+        for value in smooth_nn(&topology, &data, 3).unwrap().iter().chain(smooth_fwhm(&mesh, &topology, &data, 2.0).unwrap().iter()) {
+            assert!((value - 2.5).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn smoothing_with_an_fwhm_of_zero_returns_the_data() {
+        let mesh = grid_mesh(5);
+        let topology = MeshTopology::from_mesh(&mesh);
+        let data : Vec<f32> = (0..25).map(|v| v as f32).collect();
+        assert_eq!(data, smooth_fwhm(&mesh, &topology, &data, 0.0).unwrap());
+        assert_eq!(data, smooth_nn(&topology, &data, 0).unwrap());
+    }
+
+    #[test]
+    fn nan_values_are_ignored_and_do_not_spread() {
+        let mesh = grid_mesh(5);
+        let topology = MeshTopology::from_mesh(&mesh);
+        let mut data = vec![1.0; 25];
+        data[12] = f32::NAN;
+        data[0] = 5.0;
+        for smoothed in [smooth_nn(&topology, &data, 2).unwrap(), smooth_fwhm(&mesh, &topology, &data, 3.0).unwrap()].iter() {
+            assert!(smoothed[12].is_nan());
+            assert!(smoothed.iter().enumerate().all(|(vidx, v)| vidx == 12 || v.is_finite()));
+            // The neighbors of the NaN vertex only average the valid values.
+            for n in topology.neighbors[12].iter() {
+                assert!(smoothed[*n] >= 1.0 && smoothed[*n] < 5.0);
+            }
+        }
+    }
+
+    #[test]
+    fn smoothing_roughly_conserves_the_total_signal() {
+        let n = 15;
+        let mesh = grid_mesh(n);
+        let topology = MeshTopology::from_mesh(&mesh);
+        let data = center_spike(n);
+        let nn_sum : f32 = smooth_nn(&topology, &data, 2).unwrap().iter().sum();
+        assert!((nn_sum - 1.0).abs() < 0.05, "sum after nearest neighbor smoothing is {}", nn_sum);
+
+        let smoothed = smooth_fwhm(&mesh, &topology, &data, 3.0).unwrap();
+        let fwhm_sum : f32 = smoothed.iter().sum();
+        assert!((fwhm_sum - 1.0).abs() < 0.1, "sum after Gaussian smoothing is {}", fwhm_sum);
+        assert!(smoothed[(n / 2) * n + n / 2] < 0.5);
+    }
+
+    #[test]
+    fn data_with_the_wrong_length_is_rejected() {
+        let mesh = grid_mesh(3);
+        let topology = MeshTopology::from_mesh(&mesh);
+        assert!(matches!(smooth_nn(&topology, &[1.0; 8], 1), Err(BrainviewError::VertexCountMismatch(9, 8))));
+        assert!(matches!(smooth_fwhm(&mesh, &topology, &[1.0; 10], 2.0), Err(BrainviewError::VertexCountMismatch(9, 10))));
+    }
+}
@@ -0,0 +1,234 @@
+//! FreeSurfer subjects and the file layout of a FreeSurfer `SUBJECTS_DIR`.
+//!
+//! A subjects directory contains one directory per subject, with the surfaces and morphometry data in the `surf` sub directory
+//! and labels and parcellations in the `label` sub directory. Template subjects like `fsaverage` live in the same directory.
+//! Data mapped to a template, e.g., by `recon-all -qcache`, is stored in the `surf` directory of the subject, in files
+//! like `lh.thickness.fwhm10.fsaverage.mgh`, but is displayed on the surfaces of the template subject.
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::error::{BrainviewError, Result};
+use crate::hemisphere::{Hemisphere};
+
+
+/// The environment variable FreeSurfer uses for the subjects directory.
+pub const SUBJECTS_DIR_ENV : &str = "SUBJECTS_DIR";
+
+/// The name of the standard FreeSurfer template subject.
+pub const FSAVERAGE : &str = "fsaverage";
+
+
+/// A FreeSurfer subjects directory, containing the directories of subjects and templates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubjectsDir {
+    pub path : PathBuf,
+}
+
+
+impl SubjectsDir {
+
+    /// A subjects directory at the given path.
+    pub fn new<P: AsRef<Path>>(path : P) -> Self {
+        SubjectsDir { path: path.as_ref().to_path_buf() }
+    }
+
+
+    /// The subjects directory given by the `SUBJECTS_DIR` environment variable. Returns a `SubjectsDirNotSet` error if it is not set.
+    pub fn from_env() -> Result<Self> {
+        match env::var_os(SUBJECTS_DIR_ENV) {
+            Some(path) if !path.is_empty() => Ok(SubjectsDir::new(path)),
+            _ => Err(BrainviewError::SubjectsDirNotSet),
+        }
+    }
+
+
+    /// The subject with the given name in this directory.
+    pub fn subject(&self, name : &str) -> Subject {
+        Subject::new(self.path.join(name))
+    }
+
+
+    /// The fsaverage template subject in this directory.
+    pub fn fsaverage(&self) -> Subject {
+        self.subject(FSAVERAGE)
+    }
+}
+
+
+/// The space that per-vertex data is defined in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Space {
+    /// The native space of the subject, i.e., one value per vertex of the surfaces of the subject.
+    Native,
+    /// The space of the template subject with the given name, like `fsaverage`.
+    Template(String),
+}
+
+
+impl Space {
+
+    /// The fsaverage template space.
+    pub fn fsaverage() -> Self {
+        Space::Template(String::from(FSAVERAGE))
+    }
+}
+
+
+impl FromStr for Space {
+    type Err = String;
+
+    /// Parse a space: `native` for the native space, or the name of a template subject like `fsaverage`.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "" => Err(String::from("The space must be `native` or the name of a template subject.")),
+            "native" => Ok(Space::Native),
+            template => Ok(Space::Template(String::from(template))),
+        }
+    }
+}
+
+
+/// A morphometry measure, like `thickness`, in a space and optionally smoothed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Measure {
+    pub name : String,
+    pub space : Space,
+    /// The full width at half maximum of the smoothing kernel in mm, or `None` for unsmoothed data.
+    pub fwhm : Option<u32>,
+}
+
+
+impl Measure {
+
+    /// The unsmoothed measure in the native space of the subject, e.g., the file `lh.thickness`.
+    pub fn native(name : &str) -> Self {
+        Measure { name: String::from(name), space: Space::Native, fwhm: None }
+    }
+
+
+    /// The measure mapped to fsaverage, smoothed with the given FWHM if any, e.g., the file `lh.thickness.fwhm10.fsaverage.mgh`.
+    pub fn fsaverage(name : &str, fwhm : Option<u32>) -> Self {
+        Measure { name: String::from(name), space: Space::fsaverage(), fwhm }
+    }
+
+
+    /// The file name of the measure for the given hemisphere, following the conventions of `recon-all`.
+    ///
+    /// Unsmoothed native space data is stored in curv format without file extension, like `lh.thickness`. Smoothed or template space
+    /// data is stored in MGH format, like `lh.thickness.fwhm10.mgh`, `lh.thickness.fsaverage.mgh` or `lh.thickness.fwhm10.fsaverage.mgh`.
+    pub fn file_name(&self, hemi : Hemisphere) -> String {
+        let mut name = hemi.file_name(&self.name);
+        if let Some(fwhm) = self.fwhm {
+            name.push_str(&format!(".fwhm{}", fwhm));
+        }
+        if let Space::Template(template) = &self.space {
+            name.push_str(&format!(".{}", template));
+        }
+        if self.fwhm.is_some() || self.space != Space::Native {
+            name.push_str(".mgh");
+        }
+        name
+    }
+}
+
+
+/// A FreeSurfer subject, given by its directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Subject {
+    pub dir : PathBuf,
+}
+
+
+impl Subject {
+
+    /// The subject with the given subject directory, e.g., `$SUBJECTS_DIR/bert`.
+    pub fn new<P: AsRef<Path>>(dir : P) -> Self {
+        Subject { dir: dir.as_ref().to_path_buf() }
+    }
+
+
+    /// The name of the subject, i.e., the name of its directory.
+    pub fn name(&self) -> String {
+        self.dir.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()
+    }
+
+
+    /// The path of a file in the `surf` sub directory, like `lh.white` or `lh.thickness`.
+    pub fn surf_file(&self, file_name : &str) -> PathBuf {
+        self.dir.join("surf").join(file_name)
+    }
+
+
+    /// The path of a file in the `label` sub directory, like `lh.aparc.annot` or `lh.cortex.label`.
+    pub fn label_file(&self, file_name : &str) -> PathBuf {
+        self.dir.join("label").join(file_name)
+    }
+
+
+    /// The path of a surface of a hemisphere, e.g., `surf/lh.white` for the surface `white`.
+    pub fn surface_path(&self, hemi : Hemisphere, surface : &str) -> PathBuf {
+        self.surf_file(&hemi.file_name(surface))
+    }
+
+
+    /// The path of a morphometry data file of a hemisphere, see `Measure::file_name`.
+    pub fn measure_path(&self, hemi : Hemisphere, measure : &Measure) -> PathBuf {
+        self.surf_file(&measure.file_name(hemi))
+    }
+
+
+    /// The path of a parcellation of a hemisphere, e.g., `label/lh.aparc.annot` for the atlas `aparc`.
+    pub fn annot_path(&self, hemi : Hemisphere, atlas : &str) -> PathBuf {
+        self.label_file(&hemi.file_name(&format!("{}.annot", atlas)))
+    }
+
+
+    /// The path of a label of a hemisphere, e.g., `label/lh.cortex.label` for the label `cortex`.
+    pub fn label_path(&self, hemi : Hemisphere, label : &str) -> PathBuf {
+        self.label_file(&hemi.file_name(&format!("{}.label", label)))
+    }
+
+
+    /// The subject whose surfaces data in the given space is displayed on: this subject for native space data, the template subject in the same subjects directory otherwise.
+    pub fn surface_subject(&self, space : &Space) -> Subject {
+        match space {
+            Space::Native => self.clone(),
+            Space::Template(template) => Subject::new(self.dir.parent().unwrap_or_else(|| Path::new("")).join(template)),
+        }
+    }
+
+
+    /// Resolve a measure of this subject into the paths of the surface to display it on and of the data file.
+    ///
+    /// For template space data, the surface is the one of the template subject, see `surface_subject`.
+    pub fn resolve_measure(&self, hemi : Hemisphere, surface : &str, measure : &Measure) -> (PathBuf, PathBuf) {
+        (self.surface_subject(&measure.space).surface_path(hemi, surface), self.measure_path(hemi, measure))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn spaces_are_parsed_from_their_names() {
+        assert_eq!(Ok(Space::Native), "native".parse::<Space>());
+        assert_eq!(Ok(Space::fsaverage()), "fsaverage".parse::<Space>());
+        assert!("".parse::<Space>().is_err());
+    }
+
+    #[test]
+    fn template_space_measures_are_resolved_to_the_template_surfaces() {
+        let subject = Subject::new("/data/subjects/bert");
+        let (surface_file, data_file) = subject.resolve_measure(Hemisphere::Lh, "white", &Measure::fsaverage("thickness", Some(10)));
+        assert_eq!(PathBuf::from("/data/subjects/fsaverage/surf/lh.white"), surface_file);
+        assert_eq!(PathBuf::from("/data/subjects/bert/surf/lh.thickness.fwhm10.fsaverage.mgh"), data_file);
+
+        let (surface_file, data_file) = subject.resolve_measure(Hemisphere::Rh, "pial", &Measure::native("area"));
+        assert_eq!(PathBuf::from("/data/subjects/bert/surf/rh.pial"), surface_file);
+        assert_eq!(PathBuf::from("/data/subjects/bert/surf/rh.area"), data_file);
+    }
+}
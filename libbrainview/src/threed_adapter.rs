@@ -2,24 +2,73 @@
 
 use std::rc::Rc;
 
-use three_d::{Mesh, CPUMesh, context::Glstruct};
+use image::RgbaImage;
+use three_d::{Mesh, CPUMesh, CPUTexture, Format, Interpolation, Texture2D, Vec3, Wrapping, vec3, context::Glstruct};
+use three_d::{Camera, ElementBuffer, Mat4, Matrix, MeshProgram, RenderStates, SquareMatrix, VertexBuffer, Viewport};
 use crate::{f32tou32, ColoredBrainMesh};
+use crate::geometry::{vertex_normals};
 use crate::error::{Result};
 
 
-/// Create a renderable GPU mesh for three-d from a brain mesh and color data.
+/// Create a renderable GPU mesh for three-d from a brain mesh and color data. The mesh includes vertex normals, so it can be rendered with lighting.
 pub fn mesh_from_colored_brain_mesh(cb_mesh : &ColoredBrainMesh, context: &Rc<Glstruct>) -> Result<three_d::Mesh> {
     let cpu_mesh = CPUMesh {
         positions : cb_mesh.mesh.vertices.clone(),
+        normals : Some(vertex_normals(&cb_mesh.mesh)),
         colors : Some(cb_mesh.colors_rgba_u8().clone()),
         indices : Some(f32tou32(cb_mesh.mesh.faces.clone())),
         ..Default::default()
     };
-    let mesh = Mesh::new(context, &cpu_mesh).unwrap();
+    let mesh = Mesh::new(context, &cpu_mesh)?;
     Ok(mesh)
 }
 
 
+/// A brain mesh on the GPU whose vertex colors can be replaced without copying the geometry again, e.g., to show the frames of time series data.
+///
+/// Unlike a three-d `Mesh`, it can only be rendered with a `MeshProgram` whose fragment shader uses the normals and the vertex colors, like
+/// the shader of the lighting module.
+pub struct GpuBrainMesh {
+    position_buffer : VertexBuffer,
+    normal_buffer : VertexBuffer,
+    index_buffer : ElementBuffer,
+    color_buffer : VertexBuffer,
+}
+
+
+impl GpuBrainMesh {
+
+    /// Copy the geometry, normals and vertex colors of a brain mesh to the GPU.
+    pub fn new(cb_mesh : &ColoredBrainMesh, context : &Rc<Glstruct>) -> Result<Self> {
+        Ok(GpuBrainMesh {
+            position_buffer : VertexBuffer::new_with_static_f32(context, &cb_mesh.mesh.vertices)?,
+            normal_buffer : VertexBuffer::new_with_static_f32(context, &vertex_normals(&cb_mesh.mesh))?,
+            index_buffer : ElementBuffer::new_with_u32(context, &f32tou32(cb_mesh.mesh.faces.clone()))?,
+            color_buffer : VertexBuffer::new_with_dynamic_u8(context, &cb_mesh.colors_rgba_u8())?,
+        })
+    }
+
+
+    /// Replace the vertex colors, given as 4 RGBA values per vertex like `ColoredBrainMesh.vertex_colors`.
+    pub fn update_colors(&mut self, colors : &[u8]) {
+        self.color_buffer.fill_with_dynamic_u8(colors);
+    }
+
+
+    /// Render the mesh with the given program, like `three_d::Mesh::render`. Must be called in a render target render function, e.g., of `Screen::write`.
+    pub fn render(&self, program : &MeshProgram, render_states : RenderStates, viewport : Viewport, transformation : &Mat4, camera : &Camera) -> std::result::Result<(), three_d::Error> {
+        program.use_uniform_mat4("modelMatrix", transformation)?;
+        program.use_uniform_block(camera.matrix_buffer(), "Camera");
+        program.use_attribute_vec3(&self.position_buffer, "position")?;
+        program.use_uniform_mat4("normalMatrix", &transformation.invert().unwrap_or_else(Mat4::identity).transpose())?;
+        program.use_attribute_vec3(&self.normal_buffer, "normal")?;
+        program.use_attribute_vec4(&self.color_buffer, "color")?;
+        program.draw_elements(render_states, viewport, &self.index_buffer);
+        Ok(())
+    }
+}
+
+
 /// Compute the aabb (axis-aligned bounding box) for a mesh.
 pub fn brain_mesh_aabb(cb_mesh : &ColoredBrainMesh) -> Result<three_d::AxisAlignedBoundingBox> {
     let cpu_mesh = CPUMesh {
@@ -30,3 +79,34 @@ pub fn brain_mesh_aabb(cb_mesh : &ColoredBrainMesh) -> Result<three_d::AxisAlign
     Ok(cpu_mesh.compute_aabb())
 }
 
+
+/// Create a GPU texture from an image and a square mesh from -1 to 1 in the x/y plane it can be rendered on, e.g., as a screen overlay.
+pub fn textured_quad_from_image(img : &RgbaImage, context: &Rc<Glstruct>) -> Result<(three_d::Mesh, Texture2D)> {
+    let cpu_texture = CPUTexture {
+        data : img.as_raw().clone(),
+        width : img.width() as usize,
+        height : img.height() as usize,
+        format : Format::RGBA8,
+        min_filter : Interpolation::Nearest,
+        mag_filter : Interpolation::Nearest,
+        mip_map_filter : None,
+        wrap_s : Wrapping::ClampToEdge,
+        wrap_t : Wrapping::ClampToEdge,
+        ..Default::default()
+    };
+    let texture = Texture2D::new_with_u8(context, &cpu_texture)?;
+    let cpu_mesh = CPUMesh {
+        positions : vec![-1.0, -1.0, 0.0, 1.0, -1.0, 0.0, 1.0, 1.0, 0.0, -1.0, 1.0, 0.0],
+        uvs : Some(vec![0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 1.0]),
+        indices : Some(vec![0, 1, 2, 0, 2, 3]),
+        ..Default::default()
+    };
+    let mesh = Mesh::new(context, &cpu_mesh)?;
+    Ok((mesh, texture))
+}
+
+
+/// Convert a plain coordinate array to a three-d vector.
+pub fn threed_vec3(v : &[f32; 3]) -> Vec3 {
+    vec3(v[0], v[1], v[2])
+}
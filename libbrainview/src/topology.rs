@@ -0,0 +1,259 @@
+//! Mesh topology: vertex neighbors, edges, vertex-to-face incidence, boundary edges and connected components.
+//!
+//! A `BrainMesh` only stores flat vertex and face vectors. The `MeshTopology` computed from it is the basis for
+//! smoothing, outlines, clusters and other operations that need to know which vertices are connected.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::OnceLock;
+
+use neuroformats::BrainMesh;
+
+
+/// The topology of a triangular mesh. Edges are given as pairs of vertex indices, with the smaller index first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeshTopology {
+    pub num_vertices : usize,
+    /// For each vertex, the indices of the vertices connected to it by an edge, in ascending order.
+    pub neighbors : Vec<Vec<usize>>,
+    /// All edges of the mesh, sorted.
+    pub edges : Vec<[usize; 2]>,
+    /// For each vertex, the indices of the faces it is part of.
+    pub vertex_faces : Vec<Vec<usize>>,
+    /// The edges which are part of only one face, sorted. Empty for a closed mesh like a FreeSurfer surface.
+    pub boundary_edges : Vec<[usize; 2]>,
+    /// For each vertex, the index of the connected component it belongs to. Components are numbered in the order of their lowest vertex index.
+    pub components : Vec<usize>,
+    pub num_components : usize,
+}
+
+
+impl MeshTopology {
+
+    /// Compute the topology of a mesh.
+    pub fn from_mesh(mesh : &BrainMesh) -> Self {
+        let num_vertices = mesh.num_vertices();
+        let mut vertex_faces : Vec<Vec<usize>> = vec![Vec::new(); num_vertices];
+        let mut edge_face_counts : HashMap<[usize; 2], usize> = HashMap::new();
+        for (face_idx, face) in mesh.faces.chunks(3).enumerate() {
+            let face = [face[0] as usize, face[1] as usize, face[2] as usize];
+            for vidx in face.iter() {
+                vertex_faces[*vidx].push(face_idx);
+            }
+            for (a, b) in [(face[0], face[1]), (face[1], face[2]), (face[2], face[0])].iter() {
+                *edge_face_counts.entry([*a.min(b), *a.max(b)]).or_insert(0) += 1;
+            }
+        }
+
+        let mut edges : Vec<[usize; 2]> = edge_face_counts.keys().copied().collect();
+        edges.sort_unstable();
+        let boundary_edges : Vec<[usize; 2]> = edges.iter().filter(|e| edge_face_counts[*e] == 1).copied().collect();
+
+        let mut neighbors : Vec<Vec<usize>> = vec![Vec::new(); num_vertices];
+        for [a, b] in edges.iter() {
+            neighbors[*a].push(*b);
+            neighbors[*b].push(*a);
+        }
+        for vneighbors in neighbors.iter_mut() {
+            vneighbors.sort_unstable();
+        }
+
+        let (components, num_components) = connected_components(&neighbors);
+        MeshTopology { num_vertices, neighbors, edges, vertex_faces, boundary_edges, components, num_components }
+    }
+
+
+    /// The number of edges of the mesh.
+    pub fn num_edges(&self) -> usize {
+        self.edges.len()
+    }
+
+
+    /// Whether the mesh is closed, i.e., has no boundary edges.
+    pub fn is_closed(&self) -> bool {
+        self.boundary_edges.is_empty()
+    }
+
+
+    /// The vertices on the boundary of the mesh, i.e., the vertices of the boundary edges.
+    pub fn boundary_vertices(&self) -> Vec<bool> {
+        let mut on_boundary : Vec<bool> = vec![false; self.num_vertices];
+        for [a, b] in self.boundary_edges.iter() {
+            on_boundary[*a] = true;
+            on_boundary[*b] = true;
+        }
+        on_boundary
+    }
+
+
+    /// The vertices which can be reached from the given vertex by following at most `k` edges, including the vertex itself.
+    pub fn k_ring(&self, vertex_idx : usize, k : usize) -> Vec<usize> {
+        let mut visited : Vec<bool> = vec![false; self.num_vertices];
+        let mut ring : Vec<usize> = vec![vertex_idx];
+        let mut queue : VecDeque<(usize, usize)> = VecDeque::new();
+        visited[vertex_idx] = true;
+        queue.push_back((vertex_idx, 0));
+        while let Some((vidx, dist)) = queue.pop_front() {
+            if dist >= k {
+                continue;
+            }
+            for n in self.neighbors[vidx].iter() {
+                if !visited[*n] {
+                    visited[*n] = true;
+                    ring.push(*n);
+                    queue.push_back((*n, dist + 1));
+                }
+            }
+        }
+        ring
+    }
+
+
+    /// The sizes of the connected components, in number of vertices.
+    pub fn component_sizes(&self) -> Vec<usize> {
+        let mut sizes : Vec<usize> = vec![0; self.num_components];
+        for component in self.components.iter() {
+            sizes[*component] += 1;
+        }
+        sizes
+    }
+}
+
+
+/// Label the connected components of a graph given by its adjacency lists. Returns the component index of each vertex and the number of components.
+fn connected_components(neighbors : &[Vec<usize>]) -> (Vec<usize>, usize) {
+    let mut components : Vec<usize> = vec![usize::MAX; neighbors.len()];
+    let mut num_components : usize = 0;
+    let mut queue : VecDeque<usize> = VecDeque::new();
+    for start in 0..neighbors.len() {
+        if components[start] != usize::MAX {
+            continue;
+        }
+        components[start] = num_components;
+        queue.push_back(start);
+        while let Some(vidx) = queue.pop_front() {
+            for n in neighbors[vidx].iter() {
+                if components[*n] == usize::MAX {
+                    components[*n] = num_components;
+                    queue.push_back(*n);
+                }
+            }
+        }
+        num_components += 1;
+    }
+    (components, num_components)
+}
+
+
+/// A lazily computed `MeshTopology`, used to cache the topology of a `ColoredBrainMesh`.
+///
+/// The cache does not take part in comparisons, so two meshes are equal independently of whether their topology has been computed.
+/// It uses a `OnceLock`, so meshes with a cache can still be shared between threads.
+#[derive(Clone, Default)]
+pub struct TopologyCache {
+    topology : OnceLock<MeshTopology>,
+}
+
+
+impl TopologyCache {
+
+    /// Create an empty cache.
+    pub fn new() -> Self {
+        TopologyCache { topology: OnceLock::new() }
+    }
+
+
+    /// Get the cached topology, computing it from the mesh first if needed.
+    pub fn get_or_compute(&self, mesh : &BrainMesh) -> &MeshTopology {
+        self.topology.get_or_init(|| MeshTopology::from_mesh(mesh))
+    }
+
+
+    /// Discard the cached topology, e.g., after the faces of the mesh have changed.
+    pub fn clear(&mut self) {
+        self.topology = OnceLock::new();
+    }
+}
+
+
+impl PartialEq for TopologyCache {
+
+    /// Always true: the topology is derived from the faces of the mesh, which are compared by `BrainMesh` already. Comparing the
+    /// cache contents would make a mesh unequal to its clone just because only one of them has computed its topology.
+    fn eq(&self, _other : &Self) -> bool {
+        true
+    }
+}
+
+
+impl fmt::Debug for TopologyCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TopologyCache {{ computed: {} }}", self.topology.get().is_some())
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Two triangles sharing the edge between vertices 1 and 2, and a vertex 4 which is not part of any face.
+    fn two_triangle_mesh() -> BrainMesh {
+        BrainMesh { vertices: vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 5.0, 5.0, 5.0], faces: vec![0, 1, 2, 1, 3, 2] }
+    }
+
+    #[test]
+    fn the_neighbors_and_edges_of_two_triangles_are_computed() {
+        let topology = MeshTopology::from_mesh(&two_triangle_mesh());
+        assert_eq!(5, topology.num_vertices);
+        assert_eq!(vec![vec![1, 2], vec![0, 2, 3], vec![0, 1, 3], vec![1, 2], vec![]], topology.neighbors);
+        assert_eq!(vec![[0, 1], [0, 2], [1, 2], [1, 3], [2, 3]], topology.edges);
+        assert_eq!(5, topology.num_edges());
+        assert_eq!(vec![vec![0], vec![0, 1], vec![0, 1], vec![1], vec![]], topology.vertex_faces);
+        assert_eq!(vec![0, 1, 2, 3], topology.k_ring(0, 2));
+        assert_eq!(vec![0, 1, 2], topology.k_ring(0, 1));
+    }
+
+    #[test]
+    fn the_shared_edge_of_two_triangles_is_not_a_boundary_edge() {
+        let topology = MeshTopology::from_mesh(&two_triangle_mesh());
+        assert_eq!(vec![[0, 1], [0, 2], [1, 3], [2, 3]], topology.boundary_edges);
+        assert!(!topology.is_closed());
+        assert_eq!(vec![true, true, true, true, false], topology.boundary_vertices());
+    }
+
+    #[test]
+    fn a_tetrahedron_is_closed() {
+        let mesh = BrainMesh { vertices: vec![0.0; 12], faces: vec![0, 2, 1, 0, 1, 3, 0, 3, 2, 1, 2, 3] };
+        let topology = MeshTopology::from_mesh(&mesh);
+        assert!(topology.is_closed());
+        assert_eq!(6, topology.num_edges());
+    }
+
+    #[test]
+    fn unconnected_vertices_form_separate_components() {
+        let topology = MeshTopology::from_mesh(&two_triangle_mesh());
+        assert_eq!(2, topology.num_components);
+        assert_eq!(vec![0, 0, 0, 0, 1], topology.components);
+        assert_eq!(vec![4, 1], topology.component_sizes());
+    }
+
+    #[test]
+    fn the_topology_cache_computes_once_and_can_be_cleared() {
+        let mesh = two_triangle_mesh();
+        let mut cache = TopologyCache::new();
+        assert_eq!("TopologyCache { computed: false }", format!("{:?}", cache));
+        assert_eq!(&MeshTopology::from_mesh(&mesh), cache.get_or_compute(&mesh));
+        assert_eq!("TopologyCache { computed: true }", format!("{:?}", cache));
+        assert!(cache == TopologyCache::new());
+        cache.clear();
+        assert_eq!("TopologyCache { computed: false }", format!("{:?}", cache));
+    }
+
+    #[test]
+    fn the_topology_cache_can_be_shared_between_threads() {
+        fn assert_send_sync<T : Send + Sync>() {}
+        assert_send_sync::<TopologyCache>();
+        assert_send_sync::<crate::brainmesh::ColoredBrainMesh>();
+    }
+}
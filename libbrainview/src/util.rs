@@ -1,5 +1,8 @@
 
-use neuroformats::vec32minmax;
+use std::path::Path;
+
+use crate::color::{NAN_COLOR};
+use crate::error::{BrainviewError, Result};
 
 /// Convert a vector of `i32` values to `u32`.
 ///
@@ -16,21 +19,150 @@ pub fn f32tou32(data: Vec<i32>) -> Vec<u32> {
 }
 
 
-/// Scale the data to range `0..1`.
-///
-/// # Panics
+/// Scale the data to range `0..1`, see `scale_to_range`. NaN values stay NaN, and constant data is mapped to `0.5`.
 ///
-/// If the `data` input vector is empty.
-pub fn scale_to_01(data: Vec<f32>) -> Vec<f32> {
+/// Returns an `EmptyData` error if the `data` input vector is empty, and a `NoValidData` error if all values are NaN.
+pub fn scale_to_01(data: Vec<f32>) -> Result<Vec<f32>> {
     if (data).is_empty() {
-        panic!("Input data must not be empty.");
+        return Err(BrainviewError::EmptyData);
     }
-    let mut scaled : Vec<f32> = Vec::with_capacity(data.len());
-    let (dmin, dmax) = vec32minmax(&data, true);
-    for v in data.iter() {
-        scaled.push((*v - dmin) / (dmax - dmin));
+    let (dmin, dmax) = data.iter().filter(|v| !v.is_nan())
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(dmin, dmax), v| (dmin.min(*v), dmax.max(*v)));
+    if dmin > dmax {
+        return Err(BrainviewError::NoValidData);
+    }
+    Ok(scale_to_range(&data, dmin, dmax))
+}
+
+
+/// Scale the data so that `vmin` maps to 0 and `vmax` maps to 1.
+///
+/// Values outside of the range `[vmin, vmax]` are mapped to values outside of `0..1`, NaN values stay NaN. If `vmin`
+/// and `vmax` are equal, all other values are mapped to `0.5`.
+pub fn scale_to_range(data: &[f32], vmin: f32, vmax: f32) -> Vec<f32> {
+    let span = vmax - vmin;
+    data.iter().map(|v| {
+        if v.is_nan() {
+            f32::NAN
+        } else if span == 0.0 {
+            0.5
+        } else {
+            (*v - vmin) / span
+        }
+    }).collect()
+}
+
+
+/// Compute the given percentile, in range `0..100`, of sorted data using linear interpolation between the closest ranks.
+///
+/// Returns `None` if the data is empty.
+pub fn percentile(sorted_data: &[f32], pct: f32) -> Option<f32> {
+    if sorted_data.is_empty() {
+        return None;
     }
-    scaled
+    let rank = (pct.clamp(0.0, 100.0) / 100.0) * (sorted_data.len() - 1) as f32;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    let frac = rank - lower as f32;
+    Some(sorted_data[lower] + (sorted_data[upper] - sorted_data[lower]) * frac)
 }
 
 
+/// Pad per-vertex data with NaN values or truncate it, so that it has exactly one value for each of `num_vertices` vertices.
+///
+/// The `ColoredBrainMesh` constructors reject data that does not match the vertex count of the mesh, as this typically means the data
+/// belongs to another subject or template, like fsaverage. Use this function to explicitly accept the data anyway.
+pub fn fit_to_vertex_count(data: Vec<f32>, num_vertices: usize) -> Vec<f32> {
+    let mut data = data;
+    data.resize(num_vertices, f32::NAN);
+    data
+}
+
+
+/// Pad RGBA vertex colors with the color of NaN values, see `NAN_COLOR`, or truncate them, so that there are exactly 4 values for each of `num_vertices` vertices.
+///
+/// See `fit_to_vertex_count` for per-vertex data.
+pub fn fit_colors_to_vertex_count(colors: Vec<u8>, num_vertices: usize) -> Vec<u8> {
+    let mut colors = colors;
+    colors.truncate((colors.len() / 4).min(num_vertices) * 4);  // Also drops the values of an incomplete last color.
+    while colors.len() < num_vertices * 4 {
+        colors.extend_from_slice(&[NAN_COLOR[0], NAN_COLOR[1], NAN_COLOR[2], 255]);
+    }
+    colors
+}
+
+
+/// Convert RGB colors, 3 values per vertex, to opaque RGBA colors, 4 values per vertex.
+///
+/// Used for the region colors of parcellations: the fourth value in FreeSurfer color tables is a transparency, not an alpha value, and 0 for most atlases.
+pub fn rgb_to_opaque_rgba(colors: &[u8]) -> Vec<u8> {
+    colors.chunks_exact(3).flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255]).collect()
+}
+
+
+/// Read a file with one of the neuroformats readers, like `read_surf`.
+///
+/// A missing file results in a `MissingFile` error, other failures in a `Parse` error. Both contain the path of the file.
+pub(crate) fn read_file<T, F>(path : &Path, reader : F) -> Result<T>
+    where F: FnOnce(&Path) -> neuroformats::error::Result<T> {
+    if !path.is_file() {
+        return Err(BrainviewError::MissingFile(path.to_path_buf()));
+    }
+    reader(path).map_err(|err| BrainviewError::Parse(path.to_path_buf(), err))
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn data_is_scaled_to_the_unit_range() {
+        assert_eq!(vec![0.0, 0.25, 1.0], scale_to_01(vec![2.0, 3.0, 6.0]).unwrap());
+    }
+
+    #[test]
+    fn constant_data_is_scaled_to_one_half() {
+        assert_eq!(vec![0.5, 0.5, 0.5], scale_to_01(vec![4.0, 4.0, 4.0]).unwrap());
+    }
+
+    #[test]
+    fn nan_values_are_ignored_when_scaling() {
+        let scaled = scale_to_01(vec![1.0, f32::NAN, 3.0]).unwrap();
+        assert_eq!(0.0, scaled[0]);
+        assert!(scaled[1].is_nan());
+        assert_eq!(1.0, scaled[2]);
+    }
+
+    #[test]
+    fn scaling_empty_or_all_nan_data_is_an_error() {
+        assert!(matches!(scale_to_01(Vec::new()), Err(BrainviewError::EmptyData)));
+        assert!(matches!(scale_to_01(vec![f32::NAN; 3]), Err(BrainviewError::NoValidData)));
+    }
+
+    #[test]
+    fn data_is_padded_with_nan_values_or_truncated_to_the_vertex_count() {
+        let padded = fit_to_vertex_count(vec![1.0, 2.0], 4);
+        assert_eq!(vec![1.0, 2.0], padded[0..2].to_vec());
+        assert!(padded[2..].iter().all(|v| v.is_nan()));
+        assert_eq!(vec![1.0, 2.0], fit_to_vertex_count(vec![1.0, 2.0, 3.0], 2));
+        assert_eq!(vec![1.0, 2.0], fit_to_vertex_count(vec![1.0, 2.0], 2));
+    }
+
+    #[test]
+    fn colors_are_padded_with_the_nan_color_or_truncated_to_the_vertex_count() {
+        let nan_rgba = [NAN_COLOR[0], NAN_COLOR[1], NAN_COLOR[2], 255];
+        let padded = fit_colors_to_vertex_count(vec![1, 2, 3, 4], 3);
+        assert_eq!(vec![1, 2, 3, 4], padded[0..4].to_vec());
+        assert_eq!(nan_rgba.repeat(2), padded[4..].to_vec());
+        assert_eq!(vec![1, 2, 3, 4], fit_colors_to_vertex_count(vec![1, 2, 3, 4, 5, 6, 7, 8], 1));
+    }
+
+    #[test]
+    fn an_incomplete_last_color_is_dropped_when_fitting_colors() {
+        let nan_rgba = [NAN_COLOR[0], NAN_COLOR[1], NAN_COLOR[2], 255];
+        let fitted = fit_colors_to_vertex_count(vec![1, 2, 3, 4, 5, 6], 2);
+        assert_eq!(vec![1, 2, 3, 4], fitted[0..4].to_vec());
+        assert_eq!(nan_rgba.to_vec(), fitted[4..].to_vec());
+    }
+}
@@ -1,7 +1,8 @@
-/// The vertexcolor trait and implementations.
+//! The vertexcolor trait and implementations.
 
 use crate::fs_display::{FsLabelDisplay, FsAnnotDisplay, FsCurvDisplay};
 use crate::color_from_data;
+use crate::util::{rgb_to_opaque_rgba};
 
 pub trait VertexColor {
     fn vertex_color_rgba(&self) -> Vec<u8>;
@@ -12,20 +13,20 @@ impl VertexColor for FsLabelDisplay {
         if self.label.is_binary() {
             binary_colors_for_data(self.label.is_surface_vertex_in_label(self.num_surface_verts), self.color_bin_inside, self.color_bin_outside)
         } else {
-            color_from_data(self.label.as_surface_data(self.num_surface_verts, f32::NAN))
+            color_from_data(self.label.as_surface_data(self.num_surface_verts, f32::NAN), &self.mapping)
         }
     }
 }
 
 impl VertexColor for FsAnnotDisplay {    
     fn vertex_color_rgba(&self) -> Vec<u8> {
-        self.annot.vertex_colors(true, self.unmatched_region_index)        
+        rgb_to_opaque_rgba(&self.annot.vertex_colors(false, self.unmatched_region_index))
     }
 }
 
 impl VertexColor for FsCurvDisplay {    
     fn vertex_color_rgba(&self) -> Vec<u8> {
-        color_from_data(self.curv.data.clone())
+        color_from_data(self.curv.data.clone(), &self.mapping)
     }
 }
 
@@ -0,0 +1,183 @@
+//! Reading per-vertex data from files, like morphometry data and statistical maps.
+//!
+//! Native space morphometry data is stored in FreeSurfer curv files, like `lh.thickness`. Smoothed data, data mapped to a
+//! template like fsaverage and group-level results are typically stored in MGH or MGZ files, which are volume files with
+//! shape `(num_vertices, 1, 1, num_frames)` for per-vertex data. Files with several frames contain several values per
+//! vertex, e.g., one per subject or time point. GIFTI files like `.func.gii` or `.shape.gii` store per-vertex data in one or
+//! more data arrays, see the `gifti` module.
+
+use std::path::Path;
+
+use neuroformats::{FsMgh, read_curv, read_mgh};
+
+use crate::error::{BrainviewError, Result};
+use crate::gifti::{read_gifti, read_gifti_data, GiftiArray};
+use crate::util::{read_file};
+
+
+/// Whether the file is an MGH or MGZ file, according to its file extension.
+pub fn is_mgh_file<P: AsRef<Path>>(path : P) -> bool {
+    let path = path.as_ref().to_string_lossy();
+    path.ends_with(".mgh") || path.ends_with(".mgz")
+}
+
+
+/// Whether the file is a GIFTI file, like `lh.thickness.shape.gii`, according to its file extension.
+pub fn is_gifti_file<P: AsRef<Path>>(path : P) -> bool {
+    path.as_ref().to_string_lossy().ends_with(".gii")
+}
+
+
+/// Read all frames of per-vertex data from an MGH or MGZ file. Each frame contains one value per vertex.
+///
+/// The first three dimensions of the volume are flattened, so the number of vertices is their product. This supports both the
+/// standard shape `(num_vertices, 1, 1)` and files that spread the vertices over several dimensions. Returns an `InvalidFile` error
+/// if the file contains fewer values than its dimensions require, e.g., because its data type is not supported.
+pub fn read_mgh_frames<P: AsRef<Path>>(path : P) -> Result<Vec<Vec<f32>>> {
+    let mgh = read_file(path.as_ref(), |p| read_mgh(p))?;
+    split_mgh_frames(path.as_ref(), &mgh_values(&mgh), mgh.dim())
+}
+
+
+/// Read one frame of per-vertex data from an MGH or MGZ file, see `read_mgh_frames`. Returns an `InvalidFrame` error if the file has no such frame.
+pub fn read_mgh_frame<P: AsRef<Path>>(path : P, frame : usize) -> Result<Vec<f32>> {
+    let mut frames = read_mgh_frames(path)?;
+    if frame >= frames.len() {
+        return Err(BrainviewError::InvalidFrame(frame, frames.len()));
+    }
+    Ok(frames.swap_remove(frame))
+}
+
+
+/// Read per-vertex data from a curv file or, if the file name ends with `.mgh` or `.mgz`, from the given frame of an MGH or MGZ file.
+/// For GIFTI files ending with `.gii`, the frame is the index of the data array.
+///
+/// Curv files only have a single frame, so the frame must be 0 for them.
+pub fn read_vertex_data<P: AsRef<Path>>(path : P, frame : usize) -> Result<Vec<f32>> {
+    let path = path.as_ref();
+    if is_mgh_file(path) {
+        read_mgh_frame(path, frame)
+    } else if is_gifti_file(path) {
+        let image = read_gifti(path)?;
+        let data_array = image.data_array(&GiftiArray::Index(frame)).ok_or(BrainviewError::InvalidFrame(frame, image.data_arrays.len()))?;
+        Ok(data_array.data.to_f32())
+    } else if frame > 0 {
+        Err(BrainviewError::InvalidFrame(frame, 1))
+    } else {
+        Ok(read_file(path, |p| read_curv(p))?.data)
+    }
+}
+
+
+/// Split the values of an MGH volume with the given dimensions into frames, see `read_mgh_frames`. The `path` is only used for the error message.
+fn split_mgh_frames(path : &Path, values : &[f32], dim : [usize; 4]) -> Result<Vec<Vec<f32>>> {
+    let [dim1, dim2, dim3, num_frames] = dim;
+    let num_vertices = dim1 * dim2 * dim3;
+    if values.len() < num_vertices * num_frames {
+        let msg = format!("Expected {} values for {} vertices and {} frames, but the file contains {} values.", num_vertices * num_frames, num_vertices, num_frames, values.len());
+        return Err(BrainviewError::InvalidFile(path.to_path_buf(), msg));
+    }
+    if num_vertices == 0 {
+        return Ok(vec![Vec::new(); num_frames]);
+    }
+    // The data is in file order, i.e., the first dimension varies fastest and the frames are stored one after the other.
+    Ok(values.chunks(num_vertices).take(num_frames).map(|frame| frame.to_vec()).collect())
+}
+
+
+/// Get the values of an MGH volume as `f32`, in file order, independent of the data type of the file.
+fn mgh_values(mgh : &FsMgh) -> Vec<f32> {
+    let data = &mgh.data;
+    if let Some(values) = &data.mri_float {
+        values.iter().copied().collect()
+    } else if let Some(values) = &data.mri_int {
+        values.iter().map(|v| *v as f32).collect()
+    } else if let Some(values) = &data.mri_short {
+        values.iter().map(|v| *v as f32).collect()
+    } else if let Some(values) = &data.mri_uchar {
+        values.iter().map(|v| *v as f32).collect()
+    } else {
+        Vec::new()
+    }
+}
+
+
+/// Read per-vertex data from the selected data array of a GIFTI file, or from a curv, MGH or MGZ file, see `read_vertex_data`.
+///
+/// For files other than GIFTI files, an array selected by index is used as the frame. Returns a `NoSuchDataArray` error if a GIFTI file
+/// does not contain the selected array, or if an array is selected by name for a file which is not a GIFTI file.
+pub fn read_vertex_data_array<P: AsRef<Path>>(path : P, array : &GiftiArray) -> Result<Vec<f32>> {
+    let path = path.as_ref();
+    match array {
+        _ if is_gifti_file(path) => read_gifti_data(path, array),
+        GiftiArray::Index(frame) => read_vertex_data(path, *frame),
+        GiftiArray::Name(_) => Err(BrainviewError::NoSuchDataArray(path.to_path_buf(), array.to_string())),
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    /// Write an uncompressed MGH file with float data and the given dimensions. The values are written as given, in file order.
+    fn write_float_mgh(path : &Path, dim : [i32; 4], values : &[f32]) {
+        let mut bytes : Vec<u8> = Vec::new();
+        for v in [1, dim[0], dim[1], dim[2], dim[3], 3, 0].iter() {  // Version, dimensions, data type MRI_FLOAT and degrees of freedom.
+            bytes.extend_from_slice(&v.to_be_bytes());
+        }
+        bytes.extend_from_slice(&0i16.to_be_bytes());  // The RAS information is not valid.
+        bytes.resize(284, 0);  // The data starts after the header at byte 284.
+        for v in values.iter() {
+            bytes.extend_from_slice(&v.to_be_bytes());
+        }
+        fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn mgh_values_are_split_into_frames() {
+        let frames = split_mgh_frames(Path::new("lh.bold.mgh"), &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0], [3, 1, 1, 2]).unwrap();
+        assert_eq!(vec![vec![1.0, 2.0, 3.0], vec![4.0, 5.0, 6.0]], frames);
+        let frames = split_mgh_frames(Path::new("lh.bold.mgh"), &[1.0, 2.0, 3.0, 4.0], [2, 2, 1, 1]).unwrap();
+        assert_eq!(vec![vec![1.0, 2.0, 3.0, 4.0]], frames);
+    }
+
+    #[test]
+    fn mgh_volumes_without_vertices_have_empty_frames() {
+        assert_eq!(vec![Vec::<f32>::new(); 3], split_mgh_frames(Path::new("lh.bold.mgh"), &[], [0, 1, 1, 3]).unwrap());
+    }
+
+    #[test]
+    fn mgh_volumes_with_too_few_values_are_invalid() {
+        let result = split_mgh_frames(Path::new("lh.bold.mgh"), &[1.0, 2.0, 3.0, 4.0], [3, 1, 1, 2]);
+        assert!(matches!(result, Err(BrainviewError::InvalidFile(path, _)) if path == Path::new("lh.bold.mgh")));
+    }
+
+    #[test]
+    fn frames_of_mgh_files_can_be_selected() {
+        let path = std::env::temp_dir().join(format!("brainview_test_{}.bold.mgh", std::process::id()));
+        write_float_mgh(&path, [2, 1, 1, 3], &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        let frames = read_mgh_frames(&path);
+        let second = read_mgh_frame(&path, 1);
+        let invalid = read_mgh_frame(&path, 3);
+        let via_array = read_vertex_data_array(&path, &GiftiArray::Index(2));
+        fs::remove_file(&path).unwrap();
+        assert_eq!(3, frames.unwrap().len());
+        assert_eq!(vec![3.0, 4.0], second.unwrap());
+        assert!(matches!(invalid, Err(BrainviewError::InvalidFrame(3, 3))));
+        assert_eq!(vec![5.0, 6.0], via_array.unwrap());
+    }
+
+    #[test]
+    fn curv_files_only_have_frame_0() {
+        assert!(matches!(read_vertex_data("lh.thickness", 1), Err(BrainviewError::InvalidFrame(1, 1))));
+        assert!(matches!(read_vertex_data_array("lh.thickness", &GiftiArray::Index(2)), Err(BrainviewError::InvalidFrame(2, 1))));
+    }
+
+    #[test]
+    fn data_arrays_can_only_be_selected_by_name_in_gifti_files() {
+        let result = read_vertex_data_array("lh.thickness", &GiftiArray::Name(String::from("thickness")));
+        assert!(matches!(result, Err(BrainviewError::NoSuchDataArray(path, _)) if path == Path::new("lh.thickness")));
+    }
+}
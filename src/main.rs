@@ -1,37 +1,325 @@
+use std::path::Path;
+use std::process;
 
+use structopt::StructOpt;
+use libbrainview::{ColoredBrainMesh, Colormap, ColorMapping, ColorRange, OutOfRange, Threshold, Hemisphere, scene, SceneSettings, CameraSpec, ViewPreset, render_to_image, compose_figure, FigureLayout, Colorbar, Lighting, SubjectsDir, MeshFormat, export_mesh, read_cifti, read_mesh, Subject, Measure, Space, GiftiArray, HemisphereLayout, apply_hemisphere_layout};
 
-//use neuroformats::{read_surf, read_curv};
-use libbrainview::{ColoredBrainMesh, scene, SceneSettings};
 
-fn main() {
-    //let args: Vec<String> = std::env::args().collect();
+/// Command line options for the brainview binary.
+#[derive(Debug, StructOpt)]
+#[structopt(name = "brainview", about = "A simple viewer for surface-based brain morphometry data in the standard FreeSurfer output directory structure.")]
+struct Opt {
+    /// The subject directory, e.g., `$SUBJECTS_DIR/subject1`, or the name of a subject in `$SUBJECTS_DIR`, like `fsaverage`.
+    subject_dir: String,
+
+    /// The hemisphere to show. Can be given twice. Shows both hemispheres if omitted.
+    #[structopt(long = "hemi", possible_values = &["lh", "rh"], number_of_values = 1)]
+    hemis: Vec<Hemisphere>,
+
+    /// The surface to display, e.g., `white`, `pial` or `inflated`.
+    #[structopt(long, default_value = "white")]
+    surface: String,
+
+    /// The colormap used for morphometry data and statistical overlays, e.g., `viridis`, `rdbu` or `heat`. Append `_r` to reverse it.
+    /// Defaults to `viridis` for morphometry data and `heat` for statistical overlays.
+    #[structopt(long)]
+    colormap: Option<Colormap>,
+
+    /// The data range mapped to the colormap: `minmax`, `fixed:VMIN:VMAX`, `percentile:LOWER:UPPER`, `symmetric` or `symmetric:PERCENTILE`.
+    /// Defaults to `minmax` for morphometry data and `symmetric` for statistical overlays.
+    #[structopt(long, allow_hyphen_values = true)]
+    range: Option<ColorRange>,
+
+    /// Make vertices with values outside of the range transparent instead of clipping them to the colormap ends.
+    #[structopt(long)]
+    transparent_outside: bool,
+
+    /// A label from the `label` directory that is drawn on top of the overlay, e.g., `cortex` for `?h.cortex.label`. Can be given several times.
+    /// In the interactive viewer, `L` selects a layer and `V` toggles it.
+    #[structopt(long = "label-layer", number_of_values = 1)]
+    label_layers: Vec<String>,
+
+    /// The opacity of the label layers, in range 0 to 1.
+    #[structopt(long, default_value = "0.6")]
+    label_opacity: f32,
+
+    /// A brain atlas parcellation from the `label` directory whose region outlines are drawn on top of the overlay, e.g., `aparc` for `?h.aparc.annot`, or a GIFTI label file.
+    #[structopt(long)]
+    annot_outline: Option<String>,
+
+    /// The width of the region outlines in edges.
+    #[structopt(long, default_value = "1")]
+    outline_width: usize,
+
+    /// Show a colorbar for morphometry data and statistical overlays.
+    #[structopt(long)]
+    colorbar: bool,
+
+    /// The label drawn above the colorbar, e.g., `Thickness (mm)`.
+    #[structopt(long)]
+    colorbar_label: Option<String>,
+
+    /// Export the colorbar to this file, as SVG if the file name ends with `.svg` and as PNG otherwise.
+    #[structopt(long)]
+    colorbar_file: Option<String>,
+
+    /// The data array of GIFTI files to show, by index starting at 0 or by name. Used for GIFTI files with `morph` and `annot` and for `--annot-outline`.
+    /// Defaults to the first data array.
+    #[structopt(long)]
+    array: Option<GiftiArray>,
+
+    /// Render the flat vertex colors, without lighting.
+    #[structopt(long)]
+    no_lighting: bool,
+
+    /// The initial view. Lateral and medial views refer to the first hemisphere shown.
+    #[structopt(long, default_value = "lateral", possible_values = &["lateral", "medial", "dorsal", "ventral", "anterior", "posterior"])]
+    view: ViewPreset,
+
+    /// Where the hemispheres are placed: `native` coordinates, `separated` along the x axis so inflated surfaces do not overlap, or
+    /// `side-by-side` in the lateral view. The gap between the hemispheres can be appended, e.g., `separated:20`.
+    #[structopt(long, default_value = "native")]
+    layout: HemisphereLayout,
+
+    /// Render the scene to this PNG file instead of opening a window. Works without a display or GPU.
+    #[structopt(long)]
+    screenshot: Option<String>,
+
+    /// The frame of the `frames` overlay that is shown first, or rendered with `--screenshot` and `--figure`. Frames are counted from 0.
+    /// Only valid with the `frames` overlay.
+    #[structopt(long)]
+    frame: Option<usize>,
+
+    /// The number of frames per second when playing the frames of the `frames` overlay. In the interactive viewer, `Space` plays or pauses the frames,
+    /// and `N` and `B` show the next and previous frame.
+    #[structopt(long, default_value = "2.0")]
+    fps: f32,
+
+    /// Start playing the frames of the `frames` overlay when the viewer opens.
+    #[structopt(long)]
+    play: bool,
+
+    /// Render a figure with the lateral and medial views of all hemispheres shown to this PNG file instead of opening a window.
+    #[structopt(long, conflicts_with = "screenshot")]
+    figure: Option<String>,
+
+    /// Export the colored meshes to this file instead of opening a window, as PLY, OBJ with MTL, GLB or STL depending on the file extension.
+    /// If several hemispheres are shown, one file per hemisphere is written, with the hemisphere prepended to the file name, e.g., `lh.brain.glb`.
+    #[structopt(long, conflicts_with_all = &["screenshot", "figure"])]
+    export: Option<String>,
 
-    // * Read brain meshes for both hemis:
-    //let lh_white = read_surf("resources/subjects_dir/subject1/surf/lh.white").unwrap();
-    //let rh_white = read_surf("resources/subjects_dir/subject1/surf/rh.white").unwrap();
-    //
-    // * Read brain morphometry data:
-    //let lh_curv = read_curv("resources/subjects_dir/subject1/surf/lh.thickness").unwrap();
-    //let rh_curv = read_curv("resources/subjects_dir/subject1/surf/rh.thickness").unwrap();
-    //
-    // * Create ColoredBrainMeshes from the meshes and morphometry data:
-    //let lh_cbmesh = ColoredBrainMesh::from_brainmesh_and_data(&lh_white.mesh, lh_curv.data).unwrap();
-    //let rh_cbmesh = ColoredBrainMesh::from_brainmesh_and_data(&rh_white.mesh, rh_curv.data).unwrap();
+    /// The format of the exported meshes, overriding the file extension. Use `ply-ascii` for text PLY files.
+    #[structopt(long, possible_values = &["ply", "ply-ascii", "obj", "glb", "stl"])]
+    export_format: Option<MeshFormat>,
+
+    #[structopt(subcommand)]
+    overlay: Overlay,
+}
+
+
+// The per-vertex data that is used to color the surface. (Not a doc comment: structopt would use it as the about text of the app.)
+#[derive(Debug, StructOpt)]
+enum Overlay {
+    /// Morphometry data from the `surf` directory, e.g., `thickness` for `?h.thickness`.
+    Morph {
+        measure: String,
+        /// The space of the data: `native`, or a template subject like `fsaverage` for data mapped to it, e.g., by `recon-all -qcache`.
+        /// Template space data is shown on the surfaces of the template subject, which must be in the same directory as the subject.
+        #[structopt(long, default_value = "native")]
+        space: Space,
+        /// The FWHM of the smoothing kernel in mm, to show smoothed data like `?h.thickness.fwhm10.fsaverage.mgh` for `--space fsaverage --fwhm 10`.
+        #[structopt(long)]
+        fwhm: Option<u32>,
+    },
+    /// A thresholded statistical overlay from the `surf` directory, e.g., `tstat` for `?h.tstat`, on top of a grayscale curvature background.
+    Stat {
+        overlay: String,
+        /// The background data from the `surf` directory, e.g., `curv` or `sulc`.
+        #[structopt(long, default_value = "curv")]
+        background: String,
+        /// Show positive values greater than or equal to this threshold.
+        #[structopt(long, default_value = "2.0")]
+        pos_thresh: f32,
+        /// Show negative values less than or equal to minus this threshold.
+        #[structopt(long, default_value = "2.0")]
+        neg_thresh: f32,
+        /// Treat the overlay as p-values and show values less than or equal to this alpha level, instead of using the thresholds.
+        #[structopt(long)]
+        alpha: Option<f32>,
+    },
+    /// Per-vertex data with several frames, like a time series, from an MGH or MGZ file in the `surf` directory, e.g., `bold.mgz` for `?h.bold.mgz`.
+    /// All frames share the color range.
+    Frames {
+        measure: String,
+    },
+    /// A brain atlas parcellation from the `label` directory, e.g., `aparc` for `?h.aparc.annot`, or a GIFTI label file like `aparc.label.gii` for `?h.aparc.label.gii`.
+    Annot {
+        atlas: String,
+    },
+    /// A label from the `label` directory, e.g., `entorhinal_exvivo` for `?h.entorhinal_exvivo.label`.
+    Label {
+        label: String,
+    },
+    /// A CIFTI-2 file with data or a parcellation on the cortex of both hemispheres, like a `.dscalar.nii` or `.dlabel.nii` file from the HCP,
+    /// given by its path. It is shown on the surfaces of the subject, which must match the CIFTI file, e.g., the fs_LR 32k surfaces.
+    Cifti {
+        file: String,
+        /// The map to show, by index starting at 0 or by name.
+        #[structopt(long, default_value = "0")]
+        map: String,
+    },
+}
+
+
+/// The file name of a parcellation for a hemisphere: an annot file for atlas names like `aparc`, or the given GIFTI file for names ending with `.gii`.
+fn annot_file(atlas: &str, hemi: Hemisphere) -> String {
+    if atlas.ends_with(".gii") { hemi.file_name(atlas) } else { hemi.file_name(&format!("{}.annot", atlas)) }
+}
+
+
+/// The file name of a label for a hemisphere, like `lh.cortex.label` for `cortex`.
+fn label_file(label: &str, hemi: Hemisphere) -> String {
+    hemi.file_name(&format!("{}.label", label))
+}
 
 
-    // * Automatically create a ColoredBrainMesh from the given files in the standard FreeSurfer output directory structure.
-    //let lh_cbmesh = ColoredBrainMesh::from_freesurfer_dir("resources/subjects_dir/subject1", "lh.white", "lh.thickness").unwrap();
-    //let rh_cbmesh = ColoredBrainMesh::from_freesurfer_dir("resources/subjects_dir/subject1", "rh.white", "rh.thickness").unwrap();
+/// Load the surfaces and overlays for the hemispheres. Morphometry data is colored with a color scale shared by all hemispheres.
+fn load_meshes(opt: &Opt, hemis: &[Hemisphere]) -> Result<Vec<ColoredBrainMesh>, String> {
+    let out_of_range = if opt.transparent_outside { OutOfRange::Transparent } else { OutOfRange::Clip };
 
-    // * Automatically create a ColoredBrainMesh from the given annot files in the standard FreeSurfer output directory structure.
-    //let lh_cbmesh = ColoredBrainMesh::from_freesurfer_annot("resources/subjects_dir/subject1", "lh.white", "lh.aparc.annot").unwrap();
-    //let rh_cbmesh = ColoredBrainMesh::from_freesurfer_annot("resources/subjects_dir/subject1", "rh.white", "rh.aparc.annot").unwrap();
+    let cb_meshes = match &opt.overlay {
+        Overlay::Morph { measure, space, fwhm } => {
+            let mapping = ColorMapping { colormap: opt.colormap.unwrap_or_default(), range: opt.range.unwrap_or(ColorRange::MinMax), out_of_range };
+            let measure = Measure { name: measure.clone(), space: space.clone(), fwhm: *fwhm };
+            ColoredBrainMesh::from_subject_measures_shared_range(&Subject::new(&opt.subject_dir), hemis, &opt.surface, &measure, opt.array.clone(), Some(mapping)).map(|(cb_meshes, (vmin, vmax))| {
+                println!("Color range for {}: {} to {}", measure.name, vmin, vmax);
+                cb_meshes
+            })
+        },
+        Overlay::Frames { measure } => {
+            let mapping = ColorMapping { colormap: opt.colormap.unwrap_or_default(), range: opt.range.unwrap_or(ColorRange::MinMax), out_of_range };
+            ColoredBrainMesh::from_freesurfer_frames_shared_range(&[&opt.subject_dir], hemis, &opt.surface, measure, Some(mapping)).map(|(cb_meshes, (vmin, vmax))| {
+                println!("Color range for all {} frames of {}: {} to {}", cb_meshes[0].frames.as_ref().map_or(0, |f| f.num_frames()), measure, vmin, vmax);
+                cb_meshes
+            })
+        },
+        Overlay::Stat { overlay, background, pos_thresh, neg_thresh, alpha } => {
+            let colormap = match opt.colormap {
+                Some(colormap) => colormap,
+                None => Colormap::from_name("heat").map_err(|e| e.to_string())?,
+            };
+            let mapping = ColorMapping { colormap, range: opt.range.unwrap_or(ColorRange::Symmetric), out_of_range };
+            let threshold = match alpha {
+                Some(alpha) => Threshold::PValue(*alpha),
+                None => Threshold::Signed { pos: *pos_thresh, neg: *neg_thresh },
+            };
+            ColoredBrainMesh::from_freesurfer_threshold_overlays_shared_range(&opt.subject_dir, hemis, &opt.surface, overlay, background, threshold, Some(mapping)).map(|(cb_meshes, (vmin, vmax))| {
+                println!("Color range for {}: {} to {}", overlay, vmin, vmax);
+                cb_meshes
+            })
+        },
+        Overlay::Annot { atlas } => hemis.iter().map(|hemi| ColoredBrainMesh::from_freesurfer_annot(&opt.subject_dir, &hemi.file_name(&opt.surface), &annot_file(atlas, *hemi), opt.array.clone())).collect(),
+        Overlay::Label { label } => hemis.iter().map(|hemi| ColoredBrainMesh::from_freesurfer_label(&opt.subject_dir, &hemi.file_name(&opt.surface), &label_file(label, *hemi))).collect(),
+        Overlay::Cifti { file, map } => {
+            let cifti = read_cifti(file).map_err(|e| format!("Could not load data: {}", e))?;
+            let map_index = map.parse::<usize>().ok().or_else(|| cifti.map_index(map)).ok_or_else(|| format!("The file '{}' contains no map named '{}'.", file, map))?;
+            let mapping = ColorMapping { colormap: opt.colormap.unwrap_or_default(), range: opt.range.unwrap_or(ColorRange::MinMax), out_of_range };
+            let subject = Subject::new(&opt.subject_dir);
+            hemis.iter().map(|hemi| {
+                let surface = read_mesh(subject.surface_path(*hemi, &opt.surface))?;
+                ColoredBrainMesh::from_cifti(&cifti, &surface, *hemi, map_index, Some(mapping))
+            }).collect()
+        },
+    };
+    let mut cb_meshes = cb_meshes.map_err(|e| format!("Could not load data: {}", e))?;
 
-    // * Automatically create a ColoredBrainMesh from the given label files in the standard FreeSurfer output directory structure.
-    let lh_cbmesh = ColoredBrainMesh::from_freesurfer_label("resources/subjects_dir/subject1", "lh.white", "lh.entorhinal_exvivo.label").unwrap();
-    let rh_cbmesh = ColoredBrainMesh::from_freesurfer_label("resources/subjects_dir/subject1", "rh.white", "rh.entorhinal_exvivo.label").unwrap();
+    // Layers have to match the surfaces, so for template space data they are read from the template subject.
+    let layer_dir = match &opt.overlay {
+        Overlay::Morph { space, .. } => Subject::new(&opt.subject_dir).surface_subject(space).dir.to_string_lossy().into_owned(),
+        _ => opt.subject_dir.clone(),
+    };
+    let label_color : [u8; 4] = [255, 255, 255, 255];
+    for (cb_mesh, hemi) in cb_meshes.iter_mut().zip(hemis.iter()) {
+        for label in opt.label_layers.iter() {
+            cb_mesh.push_freesurfer_label_layer(&layer_dir, &label_file(label, *hemi), label_color, opt.label_opacity).map_err(|e| format!("Could not load label: {}", e))?;
+        }
+        if let Some(atlas) = &opt.annot_outline {
+            cb_mesh.push_freesurfer_annot_outline_layer(&layer_dir, &annot_file(atlas, *hemi), opt.outline_width, None, opt.array.clone()).map_err(|e| format!("Could not load parcellation: {}", e))?;
+        }
+    }
+    Ok(cb_meshes)
+}
+
+
+/// The export file for a hemisphere: the file name with the hemisphere prepended, like `lh.brain.glb` for `brain.glb`.
+fn export_file_for_hemi(export_file: &str, hemi: Hemisphere) -> String {
+    let path = Path::new(export_file);
+    let file_name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    path.with_file_name(hemi.file_name(&file_name)).to_string_lossy().into_owned()
+}
+
+
+fn run(opt: Opt) -> Result<(), String> {
+    let mut opt = opt;
+    if opt.frame.is_some() && !matches!(opt.overlay, Overlay::Frames { .. }) {
+        return Err(String::from("The option --frame can only be used with the frames overlay."));
+    }
+    if !Path::new(&opt.subject_dir).is_dir() {
+        // Like the FreeSurfer tools, accept the name of a subject in the subjects directory.
+        match SubjectsDir::from_env().map(|subjects_dir| subjects_dir.subject(&opt.subject_dir)) {
+            Ok(subject) if subject.dir.is_dir() => opt.subject_dir = subject.dir.to_string_lossy().into_owned(),
+            _ => return Err(format!("Subject directory '{}' not found.", opt.subject_dir)),
+        }
+    }
+
+    let hemis = if opt.hemis.is_empty() { Hemisphere::both() } else { opt.hemis.clone() };
+    let mut cb_meshes = load_meshes(&opt, &hemis)?;
+    if let Some(frame) = opt.frame {
+        for cb_mesh in cb_meshes.iter_mut() {
+            cb_mesh.show_frame(frame).map_err(|e| format!("Could not show frame: {}", e))?;
+        }
+    }
+
+    // All meshes share the color scale, so the colorbar of the first one is valid for all of them.
+    let colorbar = if opt.colorbar || opt.colorbar_file.is_some() {
+        let colorbar = Colorbar::for_mesh(&cb_meshes[0]).ok_or_else(|| String::from("A colorbar is only available for morphometry data and statistical overlays."))?;
+        Some(colorbar.with_label(opt.colorbar_label.as_deref().unwrap_or("")))
+    } else {
+        None
+    };
+    if let (Some(colorbar), Some(colorbar_file)) = (&colorbar, &opt.colorbar_file) {
+        colorbar.save(colorbar_file).map_err(|e| format!("Could not write colorbar '{}': {}", colorbar_file, e))?;
+    }
+    let colorbar = if opt.colorbar { colorbar } else { None };
 
     // Visualize the ColoredBrainMeshes.
-    let scenesettings = SceneSettings::default();  // Can be used to change resolution, background color, etc.
-    scene(vec![lh_cbmesh, rh_cbmesh], Some(scenesettings))
+    let lighting = if opt.no_lighting { None } else { Some(Lighting::default()) };
+    let scenesettings = SceneSettings { view: Some(opt.view), view_hemisphere: hemis[0], colorbar: colorbar.clone(), lighting, frame_rate: opt.fps, play_frames: opt.play, hemisphere_layout: opt.layout, ..Default::default() };  // Can be used to change resolution, background color, etc.
+    if let Some(image_file) = &opt.screenshot {
+        apply_hemisphere_layout(&mut cb_meshes, opt.layout);
+        let camera = CameraSpec::for_settings(&cb_meshes, &scenesettings).map_err(|e| format!("Rendering failed: {}", e))?;
+        let img = render_to_image(&cb_meshes, &scenesettings, &camera).map_err(|e| format!("Rendering failed: {}", e))?;
+        img.save(image_file).map_err(|e| format!("Could not write image '{}': {}", image_file, e))?;
+    } else if let Some(export_file) = &opt.export {
+        for (cb_mesh, hemi) in cb_meshes.iter().zip(hemis.iter()) {
+            let path = if hemis.len() > 1 { export_file_for_hemi(export_file, *hemi) } else { export_file.clone() };
+            export_mesh(cb_mesh, &path, opt.export_format).map_err(|e| format!("Could not export mesh '{}': {}", path, e))?;
+        }
+    } else if let Some(image_file) = &opt.figure {
+        let layout = FigureLayout { colorbar, lighting, ..FigureLayout::lateral_medial(&hemis) };
+        let img = compose_figure(&cb_meshes, &layout).map_err(|e| format!("Rendering failed: {}", e))?;
+        img.save(image_file).map_err(|e| format!("Could not write image '{}': {}", image_file, e))?;
+    } else {
+        scene(cb_meshes, Some(scenesettings)).map_err(|e| format!("Could not show scene: {}", e))?;
+    }
+    Ok(())
+}
+
+
+fn main() {
+    let opt = Opt::from_args();
+    if let Err(msg) = run(opt) {
+        eprintln!("brainview: {}", msg);
+        process::exit(1);
+    }
 }